@@ -1,16 +1,19 @@
 use std::{
-    fs::File,
-    io::{Seek, SeekFrom},
-    path::PathBuf,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
 };
 
 use byteorder::{LittleEndian, ReadBytesExt};
-use seek_bufread::BufReader;
+use lru::LruCache;
+use memmap2::Mmap;
 use thiserror::Error;
 
 use crate::bitcoin::block_reader::BlockchainRead;
 use crate::bitcoin::proto::block::Block;
-use crate::bitcoin::Bitcoin;
+use crate::bitcoin::CoinType;
 
 #[derive(Error, Debug)]
 pub enum BlkError {
@@ -18,45 +21,311 @@ pub enum BlkError {
     AnyhowError(#[from] anyhow::Error),
     #[error("IO error")]
     IOError(#[from] std::io::Error),
+    #[error("Block reader stopped")]
+    ReaderStopped,
+    #[error("Blk file not found: `{0}`")]
+    BlkNotFound(String),
+    #[error("Unsupported xor key of `{0}` bytes in blocks/xor.dat")]
+    UnsupportedXorKey(usize),
+    #[error("Unexpected magic `{0:#010x}` at offset `{1}` of blk file, is it obfuscated?")]
+    UnexpectedMagic(u32, u64),
+    #[error("Block data offset `{0}` leaves no room for its magic and size")]
+    InvalidDataOffset(u64),
+    #[error("Reading block at height `{0}` panicked: `{1}`")]
+    ReaderPanicked(u64, String),
 }
 
-pub struct BLK {
+/// Blk files kept mapped by `BlkFiles`.
+const OPEN_BLK_FILES: usize = 16;
+
+/// Key obfuscating blk and rev files since Bitcoin Core 28.
+const XOR_KEY_PATH: &str = "blocks/xor.dat";
+const XOR_KEY_SIZE: usize = 8;
+
+/// Reads the xor key of blk files, `None` if they are stored plainly.
+pub fn read_xor_key(btc_data_dir: &Path) -> Result<Option<[u8; XOR_KEY_SIZE]>, BlkError> {
+    let key = match fs::read(btc_data_dir.join(XOR_KEY_PATH)) {
+        Ok(key) => key,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let key: [u8; XOR_KEY_SIZE] = key
+        .as_slice()
+        .try_into()
+        .map_err(|_| BlkError::UnsupportedXorKey(key.len()))?;
+
+    Ok(match key == [0; XOR_KEY_SIZE] {
+        true => None,
+        false => Some(key),
+    })
+}
+
+/// De-obfuscates a reader, keeping track of its position across seeks, as
+/// each byte is xored with the key byte at its offset in the file.
+pub struct XorReader<R> {
+    inner: R,
+    key: Option<[u8; XOR_KEY_SIZE]>,
+    position: u64,
+}
+
+impl<R> XorReader<R> {
+    pub fn new(inner: R, key: Option<[u8; XOR_KEY_SIZE]>) -> XorReader<R> {
+        XorReader {
+            inner,
+            key,
+            position: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for XorReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(key) = self.key {
+            for (i, byte) in buf[..n].iter_mut().enumerate() {
+                *byte ^= key[(self.position as usize + i) % XOR_KEY_SIZE];
+            }
+        }
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for XorReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = self.inner.seek(pos)?;
+        Ok(self.position)
+    }
+}
+
+/// Offset of the magic and size preceding block data at `data_offset`.
+fn record_start(data_offset: u64) -> Result<u64, BlkError> {
+    data_offset
+        .checked_sub(8)
+        .ok_or(BlkError::InvalidDataOffset(data_offset))
+}
+
+fn blk_path(btc_data_dir: &Path, index: u64) -> PathBuf {
+    btc_data_dir
+        .join("blocks")
+        .join(format!("blk{:0>5}.dat", index))
+}
+
+/// Blocks parsed straight from memory-mapped blk files, the last used files
+/// kept mapped. Shared by the threads reading blocks in parallel.
+pub struct BlkFiles {
     btc_data_dir: PathBuf,
-    index: u64,
-    reader: Option<BufReader<File>>,
+    coin: CoinType,
+    xor_key: Option<[u8; XOR_KEY_SIZE]>,
+    maps: Mutex<LruCache<u64, Arc<Mmap>>>,
 }
 
-impl BLK {
-    pub fn new(btc_data_dir: PathBuf, index: u64) -> BLK {
-        BLK {
+impl BlkFiles {
+    pub fn new(btc_data_dir: PathBuf, coin: CoinType) -> Result<BlkFiles, BlkError> {
+        Ok(BlkFiles {
+            xor_key: read_xor_key(&btc_data_dir)?,
             btc_data_dir,
-            index,
-            reader: None,
-        }
+            coin,
+            maps: Mutex::new(LruCache::new(NonZeroUsize::new(OPEN_BLK_FILES).unwrap())),
+        })
     }
 
-    pub fn open(&mut self) {
-        if self.reader.is_none() {
-            let blk_filename = format!("blk{:0>5}.dat", self.index);
-            let blk_filepath = self.btc_data_dir.join("blocks").join(blk_filename);
+    pub fn btc_data_dir(&self) -> &Path {
+        &self.btc_data_dir
+    }
 
-            let file = File::open(blk_filepath.clone())
-                .expect(format!("blk file: {:?} not found.", blk_filepath.as_os_str()).as_str());
-            self.reader = Some(BufReader::new(file));
+    /// Mapping of blk file `index`, covering at least `len` bytes if the file does.
+    fn map(&self, index: u64, len: u64) -> Result<Arc<Mmap>, BlkError> {
+        let mut maps = self.maps.lock().unwrap();
+        if let Some(map) = maps.get(&index) {
+            if map.len() as u64 >= len {
+                return Ok(map.clone());
+            }
         }
+
+        let path = blk_path(&self.btc_data_dir, index);
+        let file = File::open(&path)
+            .map_err(|_| BlkError::BlkNotFound(path.to_string_lossy().to_string()))?;
+        // bitcoind only appends to blk files, the blocks already written never change.
+        let map = Arc::new(unsafe { Mmap::map(&file)? });
+        maps.put(index, map.clone());
+
+        Ok(map)
     }
 
-    pub fn close(&mut self) {
-        if self.reader.is_some() {
-            self.reader = None;
+    pub fn read_block(&self, index: u64, data_offset: u64) -> Result<Block, BlkError> {
+        let start = record_start(data_offset)?;
+        let map = self.map(index, data_offset)?;
+        let mut prefix = self.reader(&map, start, 8)?;
+        let magic = prefix.read_u32::<LittleEndian>()?;
+        if magic != self.coin.magic {
+            return Err(BlkError::UnexpectedMagic(magic, start));
         }
+        let block_size = prefix.read_u32::<LittleEndian>()?;
+
+        let map = self.map(index, data_offset + block_size as u64)?;
+        let mut block = self.reader(&map, data_offset, block_size as u64)?;
+        Ok(block.read_block(block_size, &self.coin)?)
     }
 
-    pub fn read_block(&mut self, data_offset: u64) -> Result<Block, BlkError> {
-        let reader = self.reader.as_mut().unwrap();
-        reader.seek(SeekFrom::Start(data_offset - 4))?;
+    /// Reader of `len` bytes of a mapping from `offset`, de-obfuscating bytes
+    /// as they are parsed rather than copying the whole block first.
+    fn reader<'a>(
+        &self,
+        map: &'a Mmap,
+        offset: u64,
+        len: u64,
+    ) -> Result<XorReader<&'a [u8]>, BlkError> {
+        let bytes = map
+            .get(offset as usize..(offset + len) as usize)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+
+        Ok(XorReader {
+            inner: bytes,
+            key: self.xor_key,
+            position: offset,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::Bitcoin;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::consensus::serialize;
+    use bitcoin::Network;
+    use seek_bufread::BufReader;
+
+    const XOR_KEY: [u8; XOR_KEY_SIZE] = [0x1f, 0x2e, 0x3d, 0x4c, 0x5b, 0x6a, 0x79, 0x88];
+
+    /// Reads one block through `XorReader` from its own file handle, as the
+    /// scan reads blk files.
+    fn read_block_at(
+        btc_data_dir: &Path,
+        coin: &CoinType,
+        index: u64,
+        data_offset: u64,
+    ) -> Result<Block, BlkError> {
+        let path = blk_path(btc_data_dir, index);
+        let file = File::open(&path)
+            .map_err(|_| BlkError::BlkNotFound(path.to_string_lossy().to_string()))?;
+        let mut reader = BufReader::new(XorReader::new(file, read_xor_key(btc_data_dir)?));
+
+        let start = record_start(data_offset)?;
+        reader.seek(SeekFrom::Start(start))?;
+        let magic = reader.read_u32::<LittleEndian>()?;
+        if magic != coin.magic {
+            return Err(BlkError::UnexpectedMagic(magic, start));
+        }
         let block_size = reader.read_u32::<LittleEndian>()?;
-        let coin = Bitcoin.into();
-        Ok(reader.read_block(block_size, &coin)?)
+        Ok(reader.read_block(block_size, coin)?)
+    }
+
+    fn write_blk(dir: &Path, xor_key: Option<[u8; XOR_KEY_SIZE]>) {
+        let block = serialize(&genesis_block(Network::Bitcoin));
+        let mut blk = vec![0u8; 5];
+        blk.extend_from_slice(&[0xf9, 0xbe, 0xb4, 0xd9]);
+        blk.extend_from_slice(&(block.len() as u32).to_le_bytes());
+        blk.extend_from_slice(&block);
+
+        if let Some(key) = xor_key {
+            for (i, byte) in blk.iter_mut().enumerate() {
+                *byte ^= key[i % XOR_KEY_SIZE];
+            }
+            fs::write(dir.join(XOR_KEY_PATH), key).unwrap();
+        }
+        fs::write(dir.join("blocks").join("blk00000.dat"), blk).unwrap();
+    }
+
+    #[test]
+    fn test_read_obfuscated_block() {
+        let dir = std::env::temp_dir().join(format!("ordi-blk-xor-{}", std::process::id()));
+        fs::create_dir_all(dir.join("blocks")).unwrap();
+
+        write_blk(&dir, Some(XOR_KEY));
+        let block = read_block_at(&dir, &Bitcoin.into(), 0, 13).unwrap();
+        assert_eq!(
+            block.header.hash,
+            genesis_block(Network::Bitcoin).block_hash().to_raw_hash()
+        );
+        let block = BlkFiles::new(dir.clone(), Bitcoin.into())
+            .unwrap()
+            .read_block(0, 13)
+            .unwrap();
+        assert_eq!(
+            block.header.hash,
+            genesis_block(Network::Bitcoin).block_hash().to_raw_hash()
+        );
+
+        fs::remove_file(dir.join(XOR_KEY_PATH)).unwrap();
+        assert!(matches!(
+            read_block_at(&dir, &Bitcoin.into(), 0, 13),
+            Err(BlkError::UnexpectedMagic(_, 5))
+        ));
+
+        fs::write(dir.join(XOR_KEY_PATH), [0u8; 32]).unwrap();
+        assert!(matches!(
+            read_block_at(&dir, &Bitcoin.into(), 0, 13),
+            Err(BlkError::UnsupportedXorKey(32))
+        ));
+
+        assert!(matches!(
+            read_block_at(&dir, &Bitcoin.into(), 1, 13),
+            Err(BlkError::BlkNotFound(_))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_blk_files_remap_appended_blocks() {
+        let dir = std::env::temp_dir().join(format!("ordi-blk-mmap-{}", std::process::id()));
+        fs::create_dir_all(dir.join("blocks")).unwrap();
+        write_blk(&dir, None);
+
+        let files = BlkFiles::new(dir.clone(), Bitcoin.into()).unwrap();
+        let block = files.read_block(0, 13).unwrap();
+        assert_eq!(block.txs.len(), 1);
+
+        let mut blk = fs::read(dir.join("blocks").join("blk00000.dat")).unwrap();
+        let len = blk.len() as u64;
+        blk.extend_from_within(5..);
+        fs::write(dir.join("blocks").join("blk00000.dat"), blk).unwrap();
+
+        let block = files.read_block(0, len + 8).unwrap();
+        assert_eq!(
+            block.header.hash,
+            genesis_block(Network::Bitcoin).block_hash().to_raw_hash()
+        );
+        assert!(files.read_block(0, len * 2).is_err());
+        assert!(matches!(
+            files.read_block(0, 7),
+            Err(BlkError::InvalidDataOffset(7))
+        ));
+        assert!(matches!(
+            read_block_at(&dir, &Bitcoin.into(), 0, 7),
+            Err(BlkError::InvalidDataOffset(7))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_xor_reader_seek() {
+        let plain = (0..64u8).collect::<Vec<_>>();
+        let obfuscated = plain
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| byte ^ XOR_KEY[i % XOR_KEY_SIZE])
+            .collect::<Vec<_>>();
+
+        let mut reader = BufReader::new(XorReader::new(io::Cursor::new(obfuscated), Some(XOR_KEY)));
+        let mut buf = [0u8; 5];
+        reader.seek(SeekFrom::Start(29)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, plain[29..34]);
+
+        reader.seek(SeekFrom::Start(3)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, plain[3..8]);
     }
 }
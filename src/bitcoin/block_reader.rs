@@ -7,6 +7,7 @@ use byteorder::{LittleEndian, ReadBytesExt};
 
 use crate::bitcoin::proto::block::{AuxPowExtension, Block};
 use crate::bitcoin::proto::header::BlockHeader;
+use crate::bitcoin::proto::script::AddressEncoding;
 use crate::bitcoin::proto::tx::{RawTx, TxInput, TxOutpoint, TxOutput};
 use crate::bitcoin::proto::varuint::VarUint;
 use crate::bitcoin::proto::MerkleBranch;
@@ -31,12 +32,12 @@ pub trait BlockchainRead: io::Read {
         // Parse AuxPow data if present
         let aux_pow_extension = match coin.aux_pow_activation_version {
             Some(version) if header.version >= version => {
-                Some(self.read_aux_pow_extension(coin.version_id)?)
+                Some(self.read_aux_pow_extension(coin.address_encoding())?)
             }
             _ => None,
         };
         let tx_count = VarUint::read_from(self)?;
-        let txs = self.read_txs(tx_count.value, coin.version_id)?;
+        let txs = self.read_txs(tx_count.value, coin.address_encoding())?;
         Ok(Block::new(size, header, aux_pow_extension, tx_count, txs))
     }
 
@@ -58,12 +59,18 @@ pub trait BlockchainRead: io::Read {
         })
     }
 
-    fn read_txs(&mut self, tx_count: u64, version_id: u8) -> anyhow::Result<Vec<RawTx>> {
-        (0..tx_count).map(|_| self.read_tx(version_id)).collect()
+    fn read_txs(
+        &mut self,
+        tx_count: u64,
+        address_encoding: AddressEncoding,
+    ) -> anyhow::Result<Vec<RawTx>> {
+        (0..tx_count)
+            .map(|_| self.read_tx(address_encoding))
+            .collect()
     }
 
     /// Reads a transaction as specified here: https://en.bitcoin.it/wiki/Protocol_specification#tx
-    fn read_tx(&mut self, version_id: u8) -> anyhow::Result<RawTx> {
+    fn read_tx(&mut self, address_encoding: AddressEncoding) -> anyhow::Result<RawTx> {
         let mut flags = 0u8;
         let version = self.read_u32::<LittleEndian>()?;
 
@@ -93,6 +100,12 @@ pub trait BlockchainRead: io::Read {
                 inputs[witness_index as usize].witness = Some(Witness::from_slice(&witnesses));
             }
         }
+        // Litecoin's MWEB flag, followed by an optional MWEB transaction. In blocks only the
+        // HogEx transaction has the flag, without a transaction, the MWEB data of the block
+        // comes after its transactions and is not read.
+        if flags & 8 > 0 && self.read_u8()? != 0 {
+            anyhow::bail!("MWEB transaction in the block's transactions");
+        }
         let locktime = self.read_u32::<LittleEndian>()?;
         let tx = RawTx {
             version,
@@ -101,7 +114,7 @@ pub trait BlockchainRead: io::Read {
             out_count,
             outputs,
             locktime,
-            version_id,
+            address_encoding,
         };
         Ok(tx)
     }
@@ -158,8 +171,11 @@ pub trait BlockchainRead: io::Read {
     }
 
     /// Reads the additional AuxPow fields as specified here https://en.bitcoin.it/wiki/Merged_mining_specification#Aux_proof-of-work_block
-    fn read_aux_pow_extension(&mut self, version_id: u8) -> anyhow::Result<AuxPowExtension> {
-        let coinbase_tx = self.read_tx(version_id)?;
+    fn read_aux_pow_extension(
+        &mut self,
+        address_encoding: AddressEncoding,
+    ) -> anyhow::Result<AuxPowExtension> {
+        let coinbase_tx = self.read_tx(address_encoding)?;
         let block_hash = sha256d::Hash::from_byte_array(self.read_256hash()?);
 
         let coinbase_branch = self.read_merkle_branch()?;
@@ -180,3 +196,39 @@ pub trait BlockchainRead: io::Read {
 /// All types that implement `Read` get methods defined in `BlockchainRead`
 /// for free.
 impl<R: io::Read + ?Sized> BlockchainRead for R {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use bitcoin::Network;
+
+    use super::*;
+
+    /// A HogEx-like transaction: MWEB flag, one input, one output.
+    fn mweb_flagged_tx(mweb_tx: u8) -> Vec<u8> {
+        let mut bytes = 2u32.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&[0x00, 0x08, 0x01]);
+        bytes.extend_from_slice(&[0x11; 32]);
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&[0x00]);
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(&[0x01]);
+        bytes.extend_from_slice(&1000u64.to_le_bytes());
+        bytes.extend_from_slice(&[0x01, 0x51]);
+        bytes.push(mweb_tx);
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_read_mweb_flagged_tx() {
+        let encoding = AddressEncoding::Bitcoin(Network::Bitcoin);
+        let tx = Cursor::new(mweb_flagged_tx(0)).read_tx(encoding).unwrap();
+        assert_eq!(tx.inputs.len(), 1);
+        assert_eq!(tx.outputs[0].value, 1000);
+        assert_eq!(tx.locktime, 0);
+
+        assert!(Cursor::new(mweb_flagged_tx(1)).read_tx(encoding).is_err());
+    }
+}
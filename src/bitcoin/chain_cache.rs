@@ -0,0 +1,172 @@
+//! Active chain of the block index, kept in a store under `ordi_data_dir` so
+//! it is looked up by height on demand and only new blocks are added on refresh.
+
+use std::path::Path;
+
+use bitcoin::hashes::{sha256d, Hash};
+use bitcoin::pow::Work;
+use log::info;
+
+use crate::bitcoin::index::{IndexEntry, IndexError};
+use crate::store::{LevelDBStore, Store, WriteBatch};
+
+/// Height and chain work of the tip.
+const TIP_KEY: &[u8] = b"t";
+/// `h<height>` to the entry of the block at that height.
+const HEIGHT_PREFIX: u8 = b'h';
+/// `c<block hash>` to the height of a block in the chain.
+const HASH_PREFIX: u8 = b'c';
+
+pub(crate) struct ChainCache {
+    store: LevelDBStore,
+}
+
+impl ChainCache {
+    pub(crate) fn open(dir: &Path) -> Result<ChainCache, IndexError> {
+        Ok(ChainCache {
+            store: LevelDBStore::open(dir)?,
+        })
+    }
+
+    /// The store, for callers keeping their own keys beside the chain.
+    pub(crate) fn store(&mut self) -> &mut dyn Store {
+        &mut self.store
+    }
+
+    /// Height and chain work of the tip. Work is only tracked by the scan mode.
+    pub(crate) fn tip(&mut self) -> Result<Option<(u64, Work)>, IndexError> {
+        let tip = match self.store.get(TIP_KEY)? {
+            Some(tip) if tip.len() == 40 => tip,
+            _ => return Ok(None),
+        };
+
+        let height = u64::from_le_bytes(tip[..8].try_into().unwrap());
+        let work = Work::from_le_bytes(tip[8..].try_into().unwrap());
+        Ok(Some((height, work)))
+    }
+
+    pub(crate) fn get(&mut self, height: u64) -> Result<Option<IndexEntry>, IndexError> {
+        Ok(self
+            .store
+            .get(&height_key(height))?
+            .and_then(|value| IndexEntry::from_bytes(&value)))
+    }
+
+    pub(crate) fn height_of(
+        &mut self,
+        block_hash: &sha256d::Hash,
+    ) -> Result<Option<u64>, IndexError> {
+        Ok(self
+            .store
+            .get(&hash_key(block_hash))?
+            .and_then(|value| value.try_into().ok())
+            .map(u64::from_le_bytes))
+    }
+
+    /// Whether `entry` is the block at its height in the chain.
+    pub(crate) fn contains(&mut self, entry: &IndexEntry) -> Result<bool, IndexError> {
+        Ok(self.height_of(&entry.block_hash)? == Some(entry.height))
+    }
+
+    /// Makes the block of `entries` the tip. `entries` go from it back to the
+    /// block after the one it forks from, blocks replaced or cut off are logged
+    /// as stale.
+    pub(crate) fn connect(&mut self, entries: &[IndexEntry], work: Work) -> Result<(), IndexError> {
+        let (tip, fork_height) = match (entries.first(), entries.last()) {
+            (Some(tip), Some(first)) => (tip.height, first.height),
+            _ => return Ok(()),
+        };
+        let old_tip = self.tip()?.map(|(height, _)| height);
+
+        let mut wb = WriteBatch::new();
+        if let Some(old_tip) = old_tip {
+            for height in fork_height..=old_tip {
+                if let Some(stale) = self.get(height)? {
+                    info!(
+                        "Skipped stale block: {} at height: {}.",
+                        stale.block_hash, height
+                    );
+                    wb.delete(&hash_key(&stale.block_hash));
+                    wb.delete(&height_key(height));
+                }
+            }
+        }
+
+        for entry in entries {
+            wb.put(&height_key(entry.height), &entry.to_bytes());
+            wb.put(&hash_key(&entry.block_hash), &entry.height.to_le_bytes());
+        }
+
+        let mut tip_value = tip.to_le_bytes().to_vec();
+        tip_value.extend_from_slice(&work.to_le_bytes());
+        wb.put(TIP_KEY, &tip_value);
+
+        self.store.write(wb)?;
+        self.store.flush()?;
+        Ok(())
+    }
+}
+
+fn height_key(height: u64) -> Vec<u8> {
+    let mut key = vec![HEIGHT_PREFIX];
+    key.extend_from_slice(&height.to_be_bytes());
+    key
+}
+
+fn hash_key(block_hash: &sha256d::Hash) -> Vec<u8> {
+    let mut key = vec![HASH_PREFIX];
+    key.extend_from_slice(block_hash.as_byte_array());
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(n: u8, prev: u8, height: u64) -> IndexEntry {
+        IndexEntry {
+            block_hash: sha256d::Hash::from_byte_array([n; 32]),
+            prev_hash: sha256d::Hash::from_byte_array([prev; 32]),
+            bits: 0x207fffff,
+            blk_index: 0,
+            data_offset: n as u64,
+            version: 1,
+            height,
+            status: 5 | 8,
+            tx_count: 1,
+        }
+    }
+
+    #[test]
+    fn test_connect_reorg() {
+        let mut cache = ChainCache {
+            store: LevelDBStore::in_memory("chain_cache").unwrap(),
+        };
+        let work = |n: u8| Work::from_le_bytes([n; 32]);
+        assert_eq!(cache.tip().unwrap(), None);
+
+        // 1 - 2 - 3 - 4, then 2 - 5 becomes the tip.
+        cache
+            .connect(
+                &[
+                    entry(4, 3, 3),
+                    entry(3, 2, 2),
+                    entry(2, 1, 1),
+                    entry(1, 0, 0),
+                ],
+                work(1),
+            )
+            .unwrap();
+        assert_eq!(cache.tip().unwrap(), Some((3, work(1))));
+        assert_eq!(cache.get(2).unwrap(), Some(entry(3, 2, 2)));
+
+        cache.connect(&[entry(5, 2, 2)], work(2)).unwrap();
+        assert_eq!(cache.tip().unwrap(), Some((2, work(2))));
+        assert_eq!(cache.get(1).unwrap(), Some(entry(2, 1, 1)));
+        assert_eq!(cache.get(2).unwrap(), Some(entry(5, 2, 2)));
+        assert_eq!(cache.get(3).unwrap(), None);
+        assert_eq!(cache.height_of(&entry(3, 2, 2).block_hash).unwrap(), None);
+        assert_eq!(cache.height_of(&entry(4, 3, 3).block_hash).unwrap(), None);
+        assert!(cache.contains(&entry(5, 2, 2)).unwrap());
+    }
+}
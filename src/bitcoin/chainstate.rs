@@ -0,0 +1,328 @@
+//! Reader for Bitcoin Core's `chainstate` LevelDB, the unspent outputs at the node's best block.
+//!
+//! See `txdb.cpp`, `coins.h` and `compressor.cpp` in https://github.com/bitcoin/bitcoin.
+//! Spent outputs are erased from it, so it can only bootstrap `output_value`
+//! at the node's best block, not answer lookups of outputs spent since.
+
+use std::path::Path;
+
+use bitcoin::hashes::{hash160, sha256d, Hash};
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::{PubkeyHash, ScriptBuf, ScriptHash, Txid};
+use rusty_leveldb::{DBIterator, LdbIterator, Options, Status, DB};
+use thiserror::Error;
+
+use crate::bitcoin::common::utils::read_varint;
+use crate::outpoint::OutPoint;
+
+const CHAINSTATE_PATH: &str = "chainstate";
+const DB_COIN: u8 = b'C';
+const DB_BEST_BLOCK: u8 = b'B';
+const OBFUSCATE_KEY_KEY: &[u8] = b"\x0e\x00obfuscate_key";
+
+/// Scripts with these sizes are compressed templates, see `ScriptCompression`.
+const SPECIAL_SCRIPTS: u64 = 6;
+const MAX_SCRIPT_SIZE: u64 = 10000;
+
+#[derive(Error, Debug)]
+pub enum ChainstateError {
+    #[error("Chainstate not found: `{0}`")]
+    DatabaseNotFound(String),
+    #[error("Open database error: `{0}`")]
+    OpenDatabase(#[from] Status),
+    #[error("Read chainstate entry: `{0}`")]
+    IOError(#[from] std::io::Error),
+    #[error("Best block not found, chainstate is being flushed or empty")]
+    BestBlockNotFound,
+    #[error("Malformed coin key")]
+    MalformedKey,
+}
+
+/// An unspent output in the chainstate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UtxoEntry {
+    pub outpoint: OutPoint,
+    pub height: u32,
+    pub coinbase: bool,
+    pub value: u64,
+    pub script_pubkey: ScriptBuf,
+}
+
+pub struct Chainstate {
+    db: DB,
+    obfuscate_key: Vec<u8>,
+}
+
+impl Chainstate {
+    /// Opens `<btc_data_dir>/chainstate`. bitcoind holds a lock on it while running,
+    /// so it has to be stopped or the directory copied.
+    pub fn open(btc_data_dir: &Path) -> Result<Chainstate, ChainstateError> {
+        let chainstate_path = btc_data_dir.join(CHAINSTATE_PATH);
+        if !chainstate_path.exists() {
+            return Err(ChainstateError::DatabaseNotFound(
+                chainstate_path.to_string_lossy().to_string(),
+            ));
+        }
+
+        let options = Options {
+            create_if_missing: false,
+            ..Default::default()
+        };
+        let mut db = DB::open(chainstate_path, options)?;
+
+        // Serialized as a vector: length followed by the key, not obfuscated itself.
+        let obfuscate_key = match db.get(OBFUSCATE_KEY_KEY) {
+            Some(value) if !value.is_empty() => value[1..].to_vec(),
+            _ => vec![],
+        };
+
+        Ok(Chainstate { db, obfuscate_key })
+    }
+
+    /// Hash of the block up to which the chainstate is written.
+    pub fn best_block(&mut self) -> Result<sha256d::Hash, ChainstateError> {
+        let mut value = self
+            .db
+            .get(&[DB_BEST_BLOCK])
+            .ok_or(ChainstateError::BestBlockNotFound)?;
+        deobfuscate(&self.obfuscate_key, &mut value);
+
+        sha256d::Hash::from_slice(&value).map_err(|_| ChainstateError::BestBlockNotFound)
+    }
+
+    pub fn get(&mut self, outpoint: &OutPoint) -> Result<Option<UtxoEntry>, ChainstateError> {
+        let key = coin_key(outpoint);
+        match self.db.get(&key) {
+            Some(mut value) => {
+                deobfuscate(&self.obfuscate_key, &mut value);
+                Ok(Some(decode_coin(&key, &value)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Iterates every unspent output, in key order.
+    pub fn iter(&mut self) -> Result<ChainstateIter, ChainstateError> {
+        let mut iter = self.db.new_iter()?;
+        iter.seek(&[DB_COIN]);
+
+        Ok(ChainstateIter {
+            iter,
+            obfuscate_key: self.obfuscate_key.clone(),
+        })
+    }
+}
+
+pub struct ChainstateIter {
+    iter: DBIterator,
+    obfuscate_key: Vec<u8>,
+}
+
+impl Iterator for ChainstateIter {
+    type Item = Result<UtxoEntry, ChainstateError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (mut key, mut value) = (vec![], vec![]);
+        if !self.iter.valid() || !self.iter.current(&mut key, &mut value) {
+            return None;
+        }
+        if key.first() != Some(&DB_COIN) {
+            return None;
+        }
+        self.iter.advance();
+
+        deobfuscate(&self.obfuscate_key, &mut value);
+        Some(decode_coin(&key, &value))
+    }
+}
+
+fn coin_key(outpoint: &OutPoint) -> Vec<u8> {
+    let mut key = Vec::with_capacity(1 + 32 + 5);
+    key.push(DB_COIN);
+    key.extend_from_slice(outpoint.txid.as_byte_array());
+    write_varint(outpoint.vout.into(), &mut key);
+    key
+}
+
+fn deobfuscate(obfuscate_key: &[u8], value: &mut [u8]) {
+    if obfuscate_key.is_empty() {
+        return;
+    }
+
+    for (i, byte) in value.iter_mut().enumerate() {
+        *byte ^= obfuscate_key[i % obfuscate_key.len()];
+    }
+}
+
+/// Key is `C<txid><VARINT(vout)>`, value is `VARINT(height * 2 + coinbase)`
+/// followed by the compressed amount and script.
+fn decode_coin(key: &[u8], value: &[u8]) -> Result<UtxoEntry, ChainstateError> {
+    if key.len() < 34 || key[0] != DB_COIN {
+        return Err(ChainstateError::MalformedKey);
+    }
+    let txid = Txid::from_slice(&key[1..33]).map_err(|_| ChainstateError::MalformedKey)?;
+    let vout = read_varint(&mut &key[33..])?;
+    let vout = u32::try_from(vout).map_err(|_| ChainstateError::MalformedKey)?;
+
+    let mut reader = value;
+    let code = read_varint(&mut reader)?;
+    let value = decompress_amount(read_varint(&mut reader)?);
+    let script_pubkey = read_compressed_script(&mut reader)?;
+
+    Ok(UtxoEntry {
+        outpoint: OutPoint::new(txid, vout),
+        height: (code >> 1) as u32,
+        coinbase: code & 1 == 1,
+        value,
+        script_pubkey,
+    })
+}
+
+fn write_varint(mut n: u64, bytes: &mut Vec<u8>) {
+    let mut tmp = [0u8; 10];
+    let mut len = 0;
+    loop {
+        tmp[len] = (n & 0x7F) as u8 | if len > 0 { 0x80 } else { 0x00 };
+        if n <= 0x7F {
+            break;
+        }
+        n = (n >> 7) - 1;
+        len += 1;
+    }
+    bytes.extend(tmp[..=len].iter().rev());
+}
+
+/// Inverse of `CompressAmount`.
+fn decompress_amount(mut x: u64) -> u64 {
+    if x == 0 {
+        return 0;
+    }
+    x -= 1;
+    let mut e = x % 10;
+    x /= 10;
+    let mut n = if e < 9 {
+        let d = (x % 9) + 1;
+        x /= 9;
+        x * 10 + d
+    } else {
+        x + 1
+    };
+    while e > 0 {
+        n *= 10;
+        e -= 1;
+    }
+    n
+}
+
+/// Inverse of `ScriptCompression`. Like bitcoind, an invalid compressed
+/// public key yields an empty script.
+fn read_compressed_script(reader: &mut &[u8]) -> Result<ScriptBuf, ChainstateError> {
+    let size = read_varint(reader)?;
+    if size < SPECIAL_SCRIPTS {
+        let len = if size < 2 { 20 } else { 32 };
+        let data = take(reader, len)?;
+
+        return Ok(match size {
+            0 => ScriptBuf::new_p2pkh(&PubkeyHash::from_raw_hash(
+                hash160::Hash::from_slice(data).unwrap(),
+            )),
+            1 => ScriptBuf::new_p2sh(&ScriptHash::from_raw_hash(
+                hash160::Hash::from_slice(data).unwrap(),
+            )),
+            2 | 3 => {
+                let mut script = Vec::with_capacity(35);
+                script.extend([0x21, size as u8]);
+                script.extend_from_slice(data);
+                script.push(0xac);
+                ScriptBuf::from(script)
+            }
+            _ => {
+                let mut compressed = [0u8; 33];
+                compressed[0] = size as u8 - 2;
+                compressed[1..].copy_from_slice(data);
+                match PublicKey::from_slice(&compressed) {
+                    Ok(pubkey) => {
+                        let mut script = Vec::with_capacity(67);
+                        script.push(0x41);
+                        script.extend_from_slice(&pubkey.serialize_uncompressed());
+                        script.push(0xac);
+                        ScriptBuf::from(script)
+                    }
+                    Err(_) => ScriptBuf::new(),
+                }
+            }
+        });
+    }
+
+    let size = size - SPECIAL_SCRIPTS;
+    if size > MAX_SCRIPT_SIZE {
+        // Overly long script, replaced with a short invalid one.
+        take(reader, size as usize)?;
+        return Ok(ScriptBuf::from(vec![0x6a]));
+    }
+
+    Ok(ScriptBuf::from(take(reader, size as usize)?.to_vec()))
+}
+
+fn take<'a>(reader: &mut &'a [u8], len: usize) -> Result<&'a [u8], ChainstateError> {
+    if reader.len() < len {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    let (data, rest) = reader.split_at(len);
+    *reader = rest;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TXID: &str = "1111111111111111111111111111111111111111111111111111111111111111";
+
+    #[test]
+    fn test_decompress_amount() {
+        assert_eq!(decompress_amount(0), 0);
+        assert_eq!(decompress_amount(1), 1);
+        assert_eq!(decompress_amount(7), 1_000_000);
+        assert_eq!(decompress_amount(9), 100_000_000);
+        assert_eq!(decompress_amount(0x32), 5_000_000_000);
+        assert_eq!(decompress_amount(0x1406f40), 2_100_000_000_000_000);
+    }
+
+    #[test]
+    fn test_decode_obfuscated_p2pkh_coin() {
+        let outpoint = OutPoint::new(TXID.parse().unwrap(), 300);
+        let key = coin_key(&outpoint);
+        assert_eq!(&key[33..], &[0x81, 0x2c]);
+
+        // Height 100 coinbase, 50 BTC, P2PKH.
+        let mut value = vec![0x80, 0x49, 0x32, 0x00];
+        value.extend_from_slice(&[0xab; 20]);
+
+        let obfuscate_key = [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0];
+        deobfuscate(&obfuscate_key, &mut value);
+        assert_ne!(value[0], 0x80);
+        deobfuscate(&obfuscate_key, &mut value);
+
+        let coin = decode_coin(&key, &value).unwrap();
+        assert_eq!(coin.outpoint, outpoint);
+        assert_eq!(coin.height, 100);
+        assert!(coin.coinbase);
+        assert_eq!(coin.value, 5_000_000_000);
+        assert!(coin.script_pubkey.is_p2pkh());
+    }
+
+    #[test]
+    fn test_decode_raw_script() {
+        // Script of 2 bytes, stored with size 2 + 6.
+        let mut reader = [0x08, 0x51, 0x52].as_slice();
+        assert_eq!(
+            read_compressed_script(&mut reader).unwrap(),
+            ScriptBuf::from(vec![0x51, 0x52])
+        );
+        assert!(reader.is_empty());
+
+        let mut reader = [0x01, 0x00].as_slice();
+        assert!(read_compressed_script(&mut reader).is_err());
+    }
+}
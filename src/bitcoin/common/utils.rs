@@ -1,3 +1,4 @@
+use std::io::{self, Read};
 use std::path::PathBuf;
 
 use bitcoin::hashes::{sha256d, Hash};
@@ -65,6 +66,36 @@ pub fn get_mean(slice: &[u32]) -> f64 {
     sum as f64 / slice.len() as f64
 }
 
+/// Reads Bitcoin Core's `VARINT`, used in its LevelDB databases.
+/// It is NOT the same as CompactSize, see `serialize.h` in https://github.com/bitcoin/bitcoin
+pub fn read_varint<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut n: u64 = 0;
+    loop {
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte)?;
+        let ch_data = byte[0];
+        if n > u64::MAX >> 7 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "varint too large",
+            ));
+        }
+        n = (n << 7) | (ch_data & 0x7F) as u64;
+        if ch_data & 0x80 > 0 {
+            if n == u64::MAX {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "varint too large",
+                ));
+            }
+            n += 1;
+        } else {
+            break;
+        }
+    }
+    Ok(n)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -80,6 +111,15 @@ mod tests {
         assert_eq!(arr_to_hex(&test), expected);
     }
 
+    #[test]
+    fn test_read_varint() {
+        assert_eq!(read_varint(&mut [0x00].as_slice()).unwrap(), 0);
+        assert_eq!(read_varint(&mut [0x7f].as_slice()).unwrap(), 127);
+        assert_eq!(read_varint(&mut [0x80, 0x00].as_slice()).unwrap(), 128);
+        assert_eq!(read_varint(&mut [0x80, 0x49].as_slice()).unwrap(), 201);
+        assert!(read_varint(&mut [0x80].as_slice()).is_err());
+    }
+
     #[test]
     fn test_merkle_root() {
         let hashes = Vec::from([
@@ -1,23 +1,37 @@
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
-use std::io::Cursor;
-use std::path::PathBuf;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use bitcoin::hashes::{sha256d, Hash};
-use byteorder::ReadBytesExt;
-use log::info;
+use bitcoin::pow::{CompactTarget, Target, Work};
+use byteorder::{LittleEndian, ReadBytesExt};
+use log::{info, warn};
 use rusty_leveldb::{LdbIterator, Options, Status, DB};
 use thiserror::Error;
 
-use crate::bitcoin::blk::{BlkError, BLK};
+use crate::bitcoin::blk::{BlkError, BlkFiles};
+use crate::bitcoin::chain_cache::ChainCache;
+use crate::bitcoin::chainstate::Chainstate;
+use crate::bitcoin::common::utils::read_varint;
+use crate::bitcoin::prefetch::BlockLocation;
 use crate::bitcoin::proto::block::Block;
+use crate::bitcoin::{scan, Bitcoin, CoinType};
+use crate::store::StoreError;
 
 const INDEX_PATH: &str = "blocks/index";
-pub const FIRST_INSCRIPTION_HEIGHT: u64 = 767430;
 const DEFAULT_INSCRIPTION_HEIGHT: usize = 100000;
 const _DEFAULT_BLK_NUM: usize = 10000;
+const INDEX_ENTRY_SIZE: usize = 32 + 32 + 4 + 8 * 6;
 
+/// Block status flags, see `BlockStatus` in `chain.h` of bitcoind.
+const BLOCK_VALID_MASK: u64 = 7;
 const BLOCK_VALID_CHAIN: u64 = 4;
-const BLOCK_HAVE_DATA: u64 = 8;
+pub(crate) const BLOCK_VALID_SCRIPTS: u64 = 5;
+pub(crate) const BLOCK_HAVE_DATA: u64 = 8;
+const BLOCK_HAVE_UNDO: u64 = 16;
+const BLOCK_FAILED_MASK: u64 = 32 | 64;
 
 #[derive(Error, Debug)]
 pub enum IndexError {
@@ -29,23 +43,43 @@ pub enum IndexError {
     InvalidHeight(u64, u64),
     #[error("Entry not found")]
     EntryNotFound,
+    #[error("Parent of block `{0}` at height `{1}` not found")]
+    ParentNotFound(sha256d::Hash, u64),
+    #[error("Block at height `{0}` is not stored in blk files")]
+    BlockDataNotFound(u64),
     #[error("Open database error: `{0}`")]
     OpenDatabase(#[from] Status),
+    #[error("Scanned index error: `{0}`")]
+    StoreError(#[from] StoreError),
+    #[error("Unknown index mode: `{0}`")]
+    UnknownMode(String),
     #[error("Read varint")]
     IOError(#[from] std::io::Error),
 }
 
+/// Where the active chain is read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexMode {
+    /// bitcoind's `blocks/index`.
+    LevelDB,
+    /// Scanning blk files.
+    Scan,
+}
+
+impl IndexMode {
+    /// `leveldb` (default) or `scan`.
+    pub fn from_name(name: &str) -> Result<IndexMode, IndexError> {
+        match name {
+            "" | "leveldb" => Ok(IndexMode::LevelDB),
+            "scan" => Ok(IndexMode::Scan),
+            _ => Err(IndexError::UnknownMode(name.to_string())),
+        }
+    }
+}
+
 fn parse_index_for_ordinals(
     btc_data_dir: &PathBuf,
-) -> Result<
-    (
-        HashMap<u64, IndexEntry>,
-        u64,
-        HashMap<u64, u64>,
-        HashMap<u64, BLK>,
-    ),
-    IndexError,
-> {
+) -> Result<HashMap<u64, IndexEntry>, IndexError> {
     let index_path = btc_data_dir.join(INDEX_PATH);
     if !index_path.exists() {
         return Err(IndexError::DatabaseNotFound(
@@ -53,10 +87,7 @@ fn parse_index_for_ordinals(
         ));
     }
 
-    let mut index = HashMap::with_capacity(DEFAULT_INSCRIPTION_HEIGHT * 10);
-    let mut max_height: u64 = 0;
-    let mut max_height_in_blk = HashMap::new();
-    let mut blks = HashMap::new();
+    let mut entries = HashMap::with_capacity(DEFAULT_INSCRIPTION_HEIGHT * 10);
     let mut iter = DB::open(index_path, Options::default())?.new_iter()?;
     let (mut key, mut value) = (vec![], vec![]);
 
@@ -64,81 +95,236 @@ fn parse_index_for_ordinals(
         iter.current(&mut key, &mut value);
         if is_block_index_entry(&key) {
             let record = IndexEntry::from_leveldb_kv(&key[1..], &value)?;
-            if record.status & (BLOCK_VALID_CHAIN | BLOCK_HAVE_DATA | BLOCK_VALID_CHAIN) > 0 {
-                let height_in_blk = max_height_in_blk
-                    .entry(record.blk_index)
-                    .or_insert(record.height);
-                if record.height > *height_in_blk {
-                    *height_in_blk = record.height;
-                }
+            entries.insert(record.block_hash, record);
+        }
+    }
 
-                blks.entry(record.blk_index)
-                    .or_insert(BLK::new(btc_data_dir.clone(), record.blk_index));
+    let (index, stale) = select_main_chain(entries)?;
+    for entry in stale {
+        info!(
+            "Skipped stale block: {} at height: {}.",
+            entry.block_hash, entry.height
+        );
+    }
 
-                if record.height > max_height {
-                    max_height = record.height;
-                }
+    Ok(index)
+}
+
+/// Builds the active chain by walking back from the valid block with the most
+/// work, as bitcoind does. Returns it by height, with the stale blocks having data.
+///
+/// Among tips of equal work bitcoind keeps the one it received first, which is
+/// not in `blocks/index`; the one stored first in blk files is taken instead.
+pub(crate) fn select_main_chain(
+    mut entries: HashMap<sha256d::Hash, IndexEntry>,
+) -> Result<(HashMap<u64, IndexEntry>, Vec<IndexEntry>), IndexError> {
+    // Parents come first in height order, so their chain work is known.
+    let mut by_height = entries.values().collect::<Vec<_>>();
+    by_height.sort_by_key(|entry| (entry.height, entry.position()));
+
+    let mut chain_work: HashMap<sha256d::Hash, Work> = HashMap::with_capacity(entries.len());
+    let mut tip: Option<(&IndexEntry, Work)> = None;
+    for entry in by_height {
+        let work = match chain_work.get(&entry.prev_hash) {
+            Some(prev_work) => *prev_work + entry.work(),
+            None => entry.work(),
+        };
+        chain_work.insert(entry.block_hash, work);
+
+        let more_work = |(tip, tip_work): (&IndexEntry, Work)| {
+            work > tip_work || (work == tip_work && entry.position() < tip.position())
+        };
+        if entry.is_valid_chain() && tip.is_none_or(more_work) {
+            tip = Some((entry, work));
+        }
+    }
+
+    let mut index = HashMap::new();
+    let mut height = tip.map_or(0, |(entry, _)| entry.height);
+    let mut block_hash = tip.map(|(entry, _)| entry.block_hash);
+    while let Some(hash) = block_hash {
+        let entry = entries.remove(&hash).ok_or(IndexError::EntryNotFound)?;
+        if entry.height != height {
+            return Err(IndexError::InvalidHeight(entry.height, height));
+        }
+        if entry.height > 0 && !entries.contains_key(&entry.prev_hash) {
+            return Err(IndexError::ParentNotFound(entry.block_hash, entry.height));
+        }
+
+        block_hash = match entry.height {
+            0 => None,
+            _ => Some(entry.prev_hash),
+        };
+        height = height.saturating_sub(1);
+        index.insert(entry.height, entry);
+    }
+
+    let mut stale = entries
+        .into_values()
+        .filter(|entry| entry.has_data())
+        .collect::<Vec<_>>();
+    stale.sort_by_key(|entry| entry.height);
+
+    Ok((index, stale))
+}
 
-                index.insert(record.height, record);
+/// Walks back from bitcoind's best block with point lookups in `blocks/index`,
+/// until it meets the cached chain. Without a chainstate to read the best block
+/// from, the whole index is parsed instead, which is much slower.
+fn refresh_from_leveldb(btc_data_dir: &Path, cache: &mut ChainCache) -> Result<(), IndexError> {
+    let best_block = match Chainstate::open(btc_data_dir).and_then(|mut c| c.best_block()) {
+        Ok(best_block) => best_block,
+        Err(e) => {
+            warn!(
+                "Best block not read from chainstate, parsing the whole block index: {}",
+                e
+            );
+            let mut entries = parse_index_for_ordinals(&btc_data_dir.to_path_buf())?
+                .into_values()
+                .collect::<Vec<_>>();
+            entries.sort_by_key(|entry| std::cmp::Reverse(entry.height));
+
+            let mut connected = vec![];
+            for entry in entries {
+                if cache.contains(&entry)? {
+                    break;
+                }
+                connected.push(entry);
             }
+            return cache.connect(&connected, Work::from_le_bytes([0; 32]));
         }
+    };
+
+    let index_path = btc_data_dir.join(INDEX_PATH);
+    if !index_path.exists() {
+        return Err(IndexError::DatabaseNotFound(
+            index_path.to_str().unwrap().to_string(),
+        ));
     }
+    let mut db = DB::open(index_path, Options::default())?;
+
+    let mut entries = vec![];
+    let mut block_hash = best_block;
+    loop {
+        let mut key = vec![b'b'];
+        key.extend_from_slice(block_hash.as_byte_array());
+        let value = db.get(&key).ok_or(IndexError::EntryNotFound)?;
+        let entry = IndexEntry::from_leveldb_kv(&key[1..], &value)?;
+        if cache.contains(&entry)? {
+            break;
+        }
 
-    for (height, entry) in index.iter() {
-        if entry.height != *height {
-            return Err(IndexError::InvalidHeight(entry.height, *height));
+        block_hash = entry.prev_hash;
+        let genesis = entry.height == 0;
+        entries.push(entry);
+        if genesis {
+            break;
         }
     }
 
-    info!("All index entries are valid until height: {}.", max_height);
-    Ok((index, max_height, max_height_in_blk, blks))
+    cache.connect(&entries, Work::from_le_bytes([0; 32]))
 }
 
+/// Active chain by height, kept in a store and only read on demand.
 pub struct Index {
     pub btc_data_dir: PathBuf,
-    pub entries: HashMap<u64, IndexEntry>,
-    pub max_height: u64,
-    pub max_height_in_blk: HashMap<u64, u64>,
-    pub blks: HashMap<u64, BLK>,
+    pub coin: CoinType,
+    pub mode: IndexMode,
+    pub files: Arc<BlkFiles>,
+    cache: RefCell<ChainCache>,
+    max_height: Cell<u64>,
 }
 
 impl Index {
-    pub fn new(btc_data_dir: PathBuf) -> Result<Index, IndexError> {
-        let start = std::time::Instant::now();
-
-        let (entries, max_height, max_height_in_blk, blks) =
-            parse_index_for_ordinals(&btc_data_dir)?;
-        info!("Parsed bitcoin index, {}s.", start.elapsed().as_secs());
+    pub fn new(btc_data_dir: PathBuf, cache_dir: &Path) -> Result<Index, IndexError> {
+        Index::open(btc_data_dir, cache_dir, Bitcoin.into(), IndexMode::LevelDB)
+    }
 
-        Ok(Index {
+    /// Opens the chain cached in `cache_dir` and brings it up to date.
+    pub fn open(
+        btc_data_dir: PathBuf,
+        cache_dir: &Path,
+        coin: CoinType,
+        mode: IndexMode,
+    ) -> Result<Index, IndexError> {
+        let index = Index {
+            files: Arc::new(BlkFiles::new(btc_data_dir.clone(), coin.clone())?),
             btc_data_dir,
-            entries,
-            max_height,
-            max_height_in_blk,
-            blks,
-        })
+            coin,
+            mode,
+            cache: RefCell::new(ChainCache::open(cache_dir)?),
+            max_height: Cell::new(0),
+        };
+        index.refresh()?;
+
+        Ok(index)
     }
 
-    pub fn catch_block(&mut self, height: u64) -> Result<Block, IndexError> {
-        let (blk_index, data_offset) = self
-            .get_index_entry(height)
-            .map(|entry| (entry.blk_index, entry.data_offset))
-            .expect("Invalid height.");
-        let blk = self.blks.get_mut(&blk_index).unwrap();
-        blk.open();
+    /// Adds the blocks bitcoind accepted since the last refresh, returns the
+    /// height of the tip.
+    pub fn refresh(&self) -> Result<u64, IndexError> {
+        let start = std::time::Instant::now();
+
+        let mut cache = self.cache.borrow_mut();
+        match self.mode {
+            IndexMode::LevelDB => refresh_from_leveldb(&self.btc_data_dir, &mut cache)?,
+            IndexMode::Scan => scan::scan(&self.btc_data_dir, &mut cache, &self.coin)?,
+        }
+        let max_height = cache.tip()?.map_or(0, |(height, _)| height);
+        if max_height != self.max_height.get() {
+            info!(
+                "Refreshed bitcoin index until height: {}, {}s.",
+                max_height,
+                start.elapsed().as_secs()
+            );
+        }
+        self.max_height.set(max_height);
+
+        Ok(max_height)
+    }
 
-        let block = blk.read_block(data_offset);
+    pub fn max_height(&self) -> u64 {
+        self.max_height.get()
+    }
 
-        if *self.max_height_in_blk.get(&blk_index).unwrap() == height {
-            blk.close();
-            // todo: remove relevant variables
+    pub fn catch_block(&self, height: u64) -> Result<Block, IndexError> {
+        let entry = self
+            .get_index_entry(height)?
+            .ok_or(IndexError::EntryNotFound)?;
+        if !entry.has_data() {
+            return Err(IndexError::BlockDataNotFound(height));
         }
 
-        Ok(block?)
+        Ok(self.files.read_block(entry.blk_index, entry.data_offset)?)
+    }
+
+    /// Locations of blocks `from..=to` in blk files.
+    pub fn block_locations(&self, from: u64, to: u64) -> Result<Vec<BlockLocation>, IndexError> {
+        (from..=to)
+            .map(|height| {
+                let entry = self
+                    .get_index_entry(height)?
+                    .ok_or(IndexError::EntryNotFound)?;
+                if !entry.has_data() {
+                    return Err(IndexError::BlockDataNotFound(height));
+                }
+
+                Ok(BlockLocation {
+                    height,
+                    blk_index: entry.blk_index,
+                    data_offset: entry.data_offset,
+                })
+            })
+            .collect()
+    }
+
+    pub fn get_index_entry(&self, height: u64) -> Result<Option<IndexEntry>, IndexError> {
+        self.cache.borrow_mut().get(height)
     }
 
-    pub fn get_index_entry(&self, height: u64) -> Option<&IndexEntry> {
-        self.entries.get(&height)
+    /// Height of a block in the active chain.
+    pub fn height_of(&self, block_hash: &sha256d::Hash) -> Result<Option<u64>, IndexError> {
+        self.cache.borrow_mut().height_of(block_hash)
     }
 
     pub fn get_block_entry_by_block_hash(
@@ -165,8 +351,12 @@ impl Index {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct IndexEntry {
     pub block_hash: sha256d::Hash,
+    pub prev_hash: sha256d::Hash,
+    pub bits: u32,
+    /// Only set when the block is stored, see `has_data`.
     pub blk_index: u64,
     pub data_offset: u64,
     pub version: u64,
@@ -176,6 +366,45 @@ pub struct IndexEntry {
 }
 
 impl IndexEntry {
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(INDEX_ENTRY_SIZE);
+        bytes.extend_from_slice(self.block_hash.as_byte_array());
+        bytes.extend_from_slice(self.prev_hash.as_byte_array());
+        bytes.extend_from_slice(&self.bits.to_le_bytes());
+        for n in [
+            self.blk_index,
+            self.data_offset,
+            self.version,
+            self.height,
+            self.status,
+            self.tx_count,
+        ] {
+            bytes.extend_from_slice(&n.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Option<IndexEntry> {
+        if bytes.len() != INDEX_ENTRY_SIZE {
+            return None;
+        }
+
+        let u64_at = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+        Some(IndexEntry {
+            block_hash: sha256d::Hash::from_slice(&bytes[..32]).ok()?,
+            prev_hash: sha256d::Hash::from_slice(&bytes[32..64]).ok()?,
+            bits: u32::from_le_bytes(bytes[64..68].try_into().unwrap()),
+            blk_index: u64_at(68),
+            data_offset: u64_at(76),
+            version: u64_at(84),
+            height: u64_at(92),
+            status: u64_at(100),
+            tx_count: u64_at(108),
+        })
+    }
+
+    /// Value is `CDiskBlockIndex`: the positions in blk and rev files are only
+    /// written when the block and its undo data are stored, followed by the header.
     fn from_leveldb_kv(key: &[u8], value: &[u8]) -> Result<IndexEntry, IndexError> {
         let mut reader = Cursor::new(value);
 
@@ -184,11 +413,30 @@ impl IndexEntry {
         let height = read_varint(&mut reader)?;
         let status = read_varint(&mut reader)?;
         let tx_count = read_varint(&mut reader)?;
-        let blk_index = read_varint(&mut reader)?;
-        let data_offset = read_varint(&mut reader)?;
+        let blk_index = match status & (BLOCK_HAVE_DATA | BLOCK_HAVE_UNDO) {
+            0 => 0,
+            _ => read_varint(&mut reader)?,
+        };
+        let data_offset = match status & BLOCK_HAVE_DATA {
+            0 => 0,
+            _ => read_varint(&mut reader)?,
+        };
+        if status & BLOCK_HAVE_UNDO != 0 {
+            read_varint(&mut reader)?;
+        }
+
+        let _header_version = reader.read_u32::<LittleEndian>()?;
+        let mut prev_hash = [0u8; 32];
+        reader.read_exact(&mut prev_hash)?;
+        let mut merkle_root = [0u8; 32];
+        reader.read_exact(&mut merkle_root)?;
+        let _timestamp = reader.read_u32::<LittleEndian>()?;
+        let bits = reader.read_u32::<LittleEndian>()?;
 
         Ok(IndexEntry {
             block_hash: sha256d::Hash::from_byte_array(block_hash),
+            prev_hash: sha256d::Hash::from_byte_array(prev_hash),
+            bits,
             blk_index,
             data_offset,
             version,
@@ -197,6 +445,26 @@ impl IndexEntry {
             tx_count,
         })
     }
+
+    pub fn has_data(&self) -> bool {
+        self.status & BLOCK_HAVE_DATA != 0
+    }
+
+    /// Where the block is stored, blocks are appended to blk files as received.
+    fn position(&self) -> (u64, u64) {
+        (self.blk_index, self.data_offset)
+    }
+
+    /// Fully validated up to the chain level, stored and not failed.
+    fn is_valid_chain(&self) -> bool {
+        self.status & BLOCK_VALID_MASK >= BLOCK_VALID_CHAIN
+            && self.has_data()
+            && self.status & BLOCK_FAILED_MASK == 0
+    }
+
+    pub(crate) fn work(&self) -> Work {
+        Target::from_compact(CompactTarget::from_consensus(self.bits)).to_work()
+    }
 }
 
 #[inline]
@@ -204,24 +472,161 @@ fn is_block_index_entry(data: &[u8]) -> bool {
     *data.first().unwrap() == b'b'
 }
 
-/// TODO: this is a wonky 1:1 translation from https://github.com/bitcoin/bitcoin
-/// It is NOT the same as CompactSize.
-fn read_varint(reader: &mut Cursor<&[u8]>) -> Result<u64, IndexError> {
-    let mut n = 0;
-    loop {
-        let ch_data = reader.read_u8()?;
-        if n > u64::MAX >> 7 {
-            panic!("size too large");
-        }
-        n = (n << 7) | (ch_data & 0x7F) as u64;
-        if ch_data & 0x80 > 0 {
-            if n == u64::MAX {
-                panic!("size too large");
-            }
-            n += 1;
-        } else {
-            break;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const VALID: u64 = 5 | BLOCK_HAVE_DATA | BLOCK_HAVE_UNDO;
+    const REGTEST_BITS: u32 = 0x207fffff;
+
+    fn hash(n: u8) -> sha256d::Hash {
+        sha256d::Hash::from_byte_array([n; 32])
+    }
+
+    fn entry(n: u8, prev: u8, height: u64, status: u64) -> IndexEntry {
+        IndexEntry {
+            block_hash: hash(n),
+            prev_hash: if height == 0 { hash(0) } else { hash(prev) },
+            bits: REGTEST_BITS,
+            blk_index: 0,
+            data_offset: n as u64,
+            version: 250000,
+            height,
+            status,
+            tx_count: 1,
+        }
+    }
+
+    #[test]
+    fn test_select_main_chain() {
+        // 1 - 2 - 3 - 5 - 7(failed)
+        //      \- 4 - 6
+        //          \- 8(headers only, more work)
+        let entries = || {
+            [
+                entry(1, 0, 0, VALID),
+                entry(2, 1, 1, VALID),
+                entry(3, 2, 2, VALID),
+                entry(4, 2, 2, VALID),
+                entry(5, 3, 3, VALID),
+                entry(6, 4, 3, VALID),
+                entry(7, 5, 4, VALID | 32),
+                entry(8, 6, 4, 1),
+            ]
+        };
+        let select = |entries: [IndexEntry; 8]| {
+            let (index, stale) = select_main_chain(
+                entries
+                    .into_iter()
+                    .map(|entry| (entry.block_hash, entry))
+                    .collect(),
+            )
+            .unwrap();
+            let chain = (0..index.len() as u64)
+                .map(|height| index[&height].block_hash)
+                .collect::<Vec<_>>();
+            (chain, stale)
+        };
+
+        // Equal work, the tip stored first wins; the failed and headers-only
+        // blocks never do.
+        let (chain, stale) = select(entries());
+        assert_eq!(chain, [hash(1), hash(2), hash(3), hash(5)]);
+        assert_eq!(
+            stale.iter().map(|entry| entry.height).collect::<Vec<_>>(),
+            [2, 3, 4]
+        );
+
+        // 5 stored in a later blk file than 6.
+        let mut later = entries();
+        later[4].blk_index = 1;
+        let (chain, _) = select(later);
+        assert_eq!(chain, [hash(1), hash(2), hash(4), hash(6)]);
+    }
+
+    #[test]
+    fn test_select_main_chain_most_work() {
+        // 1 - 2 - 3
+        //  \- 4 - 5 - 6
+        let entries = [
+            entry(1, 0, 0, VALID),
+            entry(2, 1, 1, VALID),
+            entry(3, 2, 2, VALID),
+            entry(4, 1, 1, VALID),
+            entry(5, 4, 2, VALID),
+            entry(6, 5, 3, VALID),
+        ]
+        .into_iter()
+        .map(|entry| (entry.block_hash, entry))
+        .collect();
+
+        let (index, stale) = select_main_chain(entries).unwrap();
+        assert_eq!(index.len(), 4);
+        assert_eq!(index[&1].block_hash, hash(4));
+        assert_eq!(index[&3].block_hash, hash(6));
+        assert_eq!(stale.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_entry_without_data() {
+        // Version, height 100, status valid tree without data, no transactions.
+        let mut value = vec![0x00, 0x64, 0x02, 0x00];
+        value.extend_from_slice(&1u32.to_le_bytes());
+        value.extend_from_slice(&[0xaa; 32]);
+        value.extend_from_slice(&[0xbb; 32]);
+        value.extend_from_slice(&0u32.to_le_bytes());
+        value.extend_from_slice(&REGTEST_BITS.to_le_bytes());
+        value.extend_from_slice(&0u32.to_le_bytes());
+
+        let entry = IndexEntry::from_leveldb_kv(&[0x11; 32], &value).unwrap();
+        assert_eq!(entry.height, 100);
+        assert!(!entry.has_data());
+        assert_eq!(entry.prev_hash, sha256d::Hash::from_byte_array([0xaa; 32]));
+        assert_eq!(entry.bits, REGTEST_BITS);
+
+        // Stored with undo data: file 3, data at 8, undo at 16.
+        let mut value = vec![0x00, 0x64, 0x1d, 0x01, 0x03, 0x08, 0x10];
+        value.extend_from_slice(&[0u8; 80]);
+        let entry = IndexEntry::from_leveldb_kv(&[0x11; 32], &value).unwrap();
+        assert!(entry.is_valid_chain());
+        assert_eq!((entry.blk_index, entry.data_offset), (3, 8));
+    }
+
+    /// `blocks/index` record of a stored block, with one byte varints.
+    fn leveldb_value(height: u8, prev_hash: sha256d::Hash, data_offset: u8) -> Vec<u8> {
+        let mut value = vec![0x01, height, VALID as u8, 0x01, 0x00, data_offset, 0x00];
+        value.extend_from_slice(&1u32.to_le_bytes());
+        value.extend_from_slice(prev_hash.as_byte_array());
+        value.extend_from_slice(&[0; 32]);
+        value.extend_from_slice(&0u32.to_le_bytes());
+        value.extend_from_slice(&REGTEST_BITS.to_le_bytes());
+        value.extend_from_slice(&0u32.to_le_bytes());
+        value
+    }
+
+    #[test]
+    fn test_refresh_from_leveldb_without_chainstate() {
+        let dir = std::env::temp_dir().join(format!("ordi-index-{}", std::process::id()));
+        let btc_data_dir = dir.join("btc");
+        fs::create_dir_all(btc_data_dir.join("blocks")).unwrap();
+        let mut db = DB::open(btc_data_dir.join(INDEX_PATH), Options::default()).unwrap();
+        for (n, height, prev) in [(1, 0, 0), (2, 1, 1)] {
+            let mut key = vec![b'b'];
+            key.extend_from_slice(hash(n).as_byte_array());
+            db.put(&key, &leveldb_value(height, hash(prev), n)).unwrap();
         }
+        db.flush().unwrap();
+        drop(db);
+
+        // No chainstate, the whole index is parsed.
+        let mut cache = ChainCache::open(&dir.join("cache")).unwrap();
+        refresh_from_leveldb(&btc_data_dir, &mut cache).unwrap();
+        assert_eq!(cache.tip().unwrap().map(|(height, _)| height), Some(1));
+        assert_eq!(cache.get(1).unwrap().unwrap().block_hash, hash(2));
+        assert_eq!(cache.get(0).unwrap().unwrap().block_hash, hash(1));
+
+        drop(cache);
+        fs::remove_dir_all(dir).unwrap();
     }
-    Ok(n)
 }
@@ -3,13 +3,22 @@ use std::{
     str::FromStr,
 };
 
-use bitcoin::hashes::sha256d;
+use bitcoin::consensus::serialize;
+use bitcoin::hashes::{sha256d, Hash};
+use bitcoin::Network;
+
+use crate::bitcoin::common::utils::hex_to_vec;
+use crate::bitcoin::proto::script::{AddressEncoding, AddressPrefixes};
 
 pub mod blk;
+pub mod chainstate;
 pub mod index;
+pub mod prefetch;
 pub mod proto;
+pub mod scan;
 
-mod block_reader;
+pub(crate) mod block_reader;
+mod chain_cache;
 mod common;
 
 /// Trait to specify the underlying coin of a blockchain
@@ -21,27 +30,55 @@ pub trait Coin {
     fn magic(&self) -> u32;
     // https://en.bitcoin.it/wiki/List_of_address_prefixes
     fn version_id(&self) -> u8;
+    // Version byte of script hash addresses
+    fn script_version_id(&self) -> u8 {
+        0x05
+    }
+    // Human readable part of bech32 addresses, for coins with segwit
+    fn bech32_hrp(&self) -> Option<&'static str> {
+        None
+    }
     // Returns genesis hash
     fn genesis(&self) -> sha256d::Hash;
     // Activates AuxPow for the returned version and above
     fn aux_pow_activation_version(&self) -> Option<u32> {
         None
     }
+    // Network of rust-bitcoin encoding addresses, otherwise they are built from version_id
+    fn network(&self) -> Option<Network> {
+        None
+    }
     // Default working directory to look for datadir, for example .bitcoin
     fn default_folder(&self) -> PathBuf;
 }
 
 pub struct Bitcoin;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 // Holds the selected coin type information
 pub struct CoinType {
     pub name: String,
     pub magic: u32,
     pub version_id: u8,
+    pub script_version_id: u8,
+    pub bech32_hrp: Option<&'static str>,
     pub genesis_hash: sha256d::Hash,
     pub aux_pow_activation_version: Option<u32>,
     pub default_folder: PathBuf,
+    pub network: Option<Network>,
+}
+
+impl CoinType {
+    pub fn address_encoding(&self) -> AddressEncoding {
+        match self.network {
+            Some(network) => AddressEncoding::Bitcoin(network),
+            None => AddressEncoding::Prefixes(AddressPrefixes {
+                version_id: self.version_id,
+                script_version_id: self.script_version_id,
+                bech32_hrp: self.bech32_hrp,
+            }),
+        }
+    }
 }
 
 impl<T: Coin> From<T> for CoinType {
@@ -50,9 +87,12 @@ impl<T: Coin> From<T> for CoinType {
             name: coin.name(),
             magic: coin.magic(),
             version_id: coin.version_id(),
+            script_version_id: coin.script_version_id(),
+            bech32_hrp: coin.bech32_hrp(),
             genesis_hash: coin.genesis(),
             aux_pow_activation_version: coin.aux_pow_activation_version(),
             default_folder: coin.default_folder(),
+            network: coin.network(),
         }
     }
 }
@@ -71,7 +111,179 @@ impl Coin for Bitcoin {
         sha256d::Hash::from_str("000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f")
             .unwrap()
     }
+    fn network(&self) -> Option<Network> {
+        Some(Network::Bitcoin)
+    }
     fn default_folder(&self) -> PathBuf {
         Path::new(".bitcoin").join("blocks")
     }
 }
+
+pub struct BitcoinTestnet3;
+
+impl Coin for BitcoinTestnet3 {
+    fn name(&self) -> String {
+        String::from("Bitcoin Testnet3")
+    }
+    fn magic(&self) -> u32 {
+        0x0709110b
+    }
+    fn version_id(&self) -> u8 {
+        0x6f
+    }
+    fn genesis(&self) -> sha256d::Hash {
+        sha256d::Hash::from_str("000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943")
+            .unwrap()
+    }
+    fn network(&self) -> Option<Network> {
+        Some(Network::Testnet)
+    }
+    fn default_folder(&self) -> PathBuf {
+        Path::new(".bitcoin").join("testnet3").join("blocks")
+    }
+}
+
+pub struct BitcoinTestnet4;
+
+impl Coin for BitcoinTestnet4 {
+    fn name(&self) -> String {
+        String::from("Bitcoin Testnet4")
+    }
+    fn magic(&self) -> u32 {
+        0x283f161c
+    }
+    fn version_id(&self) -> u8 {
+        0x6f
+    }
+    fn genesis(&self) -> sha256d::Hash {
+        sha256d::Hash::from_str("00000000da84f2bafbbc53dee25a72ae507ff4914b867c565be350b0da8bf043")
+            .unwrap()
+    }
+    // Same address encoding as testnet3
+    fn network(&self) -> Option<Network> {
+        Some(Network::Testnet)
+    }
+    fn default_folder(&self) -> PathBuf {
+        Path::new(".bitcoin").join("testnet4").join("blocks")
+    }
+}
+
+/// Signet, the default one or a custom one with its own block challenge.
+pub struct BitcoinSignet {
+    pub challenge: Vec<u8>,
+}
+
+impl BitcoinSignet {
+    pub const DEFAULT_CHALLENGE: &'static str = "512103ad5e0edad18cb1f0fc0d28a3d4f1f3e445640337489abb10404f2d1e086be430210359ef5021964fe22d6f8e05b2463c9540ce96883fe3b278760f048f5189f2e6c452ae";
+}
+
+impl Default for BitcoinSignet {
+    fn default() -> BitcoinSignet {
+        BitcoinSignet {
+            challenge: hex_to_vec(BitcoinSignet::DEFAULT_CHALLENGE),
+        }
+    }
+}
+
+impl Coin for BitcoinSignet {
+    fn name(&self) -> String {
+        String::from("Bitcoin Signet")
+    }
+    // First bytes of the hash of the serialized challenge, see `SigNetParams` in bitcoind
+    fn magic(&self) -> u32 {
+        let hash = sha256d::Hash::hash(&serialize(&self.challenge));
+        u32::from_le_bytes(hash.as_byte_array()[..4].try_into().unwrap())
+    }
+    fn version_id(&self) -> u8 {
+        0x6f
+    }
+    // Custom signets share the genesis block of the default one
+    fn genesis(&self) -> sha256d::Hash {
+        sha256d::Hash::from_str("00000008819873e925422c1ff0f99f7cc9bbb232af63a077a480a3633bee1ef6")
+            .unwrap()
+    }
+    fn network(&self) -> Option<Network> {
+        Some(Network::Signet)
+    }
+    fn default_folder(&self) -> PathBuf {
+        Path::new(".bitcoin").join("signet").join("blocks")
+    }
+}
+
+pub struct BitcoinRegtest;
+
+impl Coin for BitcoinRegtest {
+    fn name(&self) -> String {
+        String::from("Bitcoin Regtest")
+    }
+    fn magic(&self) -> u32 {
+        0xdab5bffa
+    }
+    fn version_id(&self) -> u8 {
+        0x6f
+    }
+    fn genesis(&self) -> sha256d::Hash {
+        sha256d::Hash::from_str("0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206")
+            .unwrap()
+    }
+    fn network(&self) -> Option<Network> {
+        Some(Network::Regtest)
+    }
+    fn default_folder(&self) -> PathBuf {
+        Path::new(".bitcoin").join("regtest").join("blocks")
+    }
+}
+
+pub struct Dogecoin;
+
+impl Coin for Dogecoin {
+    fn name(&self) -> String {
+        String::from("Dogecoin")
+    }
+    fn magic(&self) -> u32 {
+        0xc0c0c0c0
+    }
+    fn version_id(&self) -> u8 {
+        0x1e
+    }
+    fn script_version_id(&self) -> u8 {
+        0x16
+    }
+    fn genesis(&self) -> sha256d::Hash {
+        sha256d::Hash::from_str("1a91e3dace36e2be3bf030a65679fe821aa1d6ef92e7c9902eb318182c355691")
+            .unwrap()
+    }
+    fn aux_pow_activation_version(&self) -> Option<u32> {
+        Some(0x00620102)
+    }
+    fn default_folder(&self) -> PathBuf {
+        Path::new(".dogecoin").join("blocks")
+    }
+}
+
+pub struct Litecoin;
+
+impl Coin for Litecoin {
+    fn name(&self) -> String {
+        String::from("Litecoin")
+    }
+    fn magic(&self) -> u32 {
+        0xdbb6c0fb
+    }
+    fn version_id(&self) -> u8 {
+        0x30
+    }
+    fn script_version_id(&self) -> u8 {
+        0x32
+    }
+    fn bech32_hrp(&self) -> Option<&'static str> {
+        Some("ltc")
+    }
+    fn genesis(&self) -> sha256d::Hash {
+        sha256d::Hash::from_str("12a765e31ffd4059bada1e25190f6e98c99d9714d334efa41a195a7e7e04bfe2")
+            .unwrap()
+    }
+    fn default_folder(&self) -> PathBuf {
+        Path::new(".litecoin").join("blocks")
+    }
+}
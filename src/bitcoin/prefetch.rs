@@ -0,0 +1,164 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use crate::bitcoin::blk::{BlkError, BlkFiles};
+use crate::bitcoin::proto::block::Block;
+
+/// Where a block is stored in blk files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockLocation {
+    pub height: u64,
+    pub blk_index: u64,
+    pub data_offset: u64,
+}
+
+type Prefetched<T> = mpsc::Receiver<Result<(u64, T), BlkError>>;
+
+/// Reads blocks and maps them with `f` on the rayon pool, ahead of the consumer.
+/// Blocks are parsed from the mappings `files` keeps of each blk file.
+///
+/// A dispatcher thread queues one receiver per block, in height order, into a
+/// channel bounded to `depth`, so at most `depth + 1` blocks wait to be consumed.
+pub struct BlockPrefetcher<T> {
+    receiver: mpsc::Receiver<Prefetched<T>>,
+}
+
+impl<T: Send + 'static> BlockPrefetcher<T> {
+    pub fn new<F>(
+        files: Arc<BlkFiles>,
+        locations: Vec<BlockLocation>,
+        depth: usize,
+        f: F,
+    ) -> BlockPrefetcher<T>
+    where
+        F: Fn(u64, Block) -> T + Send + Sync + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(depth);
+        let f = Arc::new(f);
+
+        thread::spawn(move || {
+            for location in locations {
+                let (block_sender, block_receiver) = mpsc::sync_channel(1);
+                // Blocks while `depth` blocks are queued, stops once the consumer is dropped.
+                if sender.send(block_receiver).is_err() {
+                    break;
+                }
+
+                let files = files.clone();
+                let f = f.clone();
+                rayon::spawn(move || {
+                    // A panic would abort the process on the pool, the consumer
+                    // gets it as an error instead.
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        files
+                            .read_block(location.blk_index, location.data_offset)
+                            .map(|block| (location.height, f(location.height, block)))
+                    }))
+                    .unwrap_or_else(|panic| {
+                        Err(BlkError::ReaderPanicked(
+                            location.height,
+                            panic_message(&*panic),
+                        ))
+                    });
+                    let _ = block_sender.send(result);
+                });
+            }
+        });
+
+        BlockPrefetcher { receiver }
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => panic.downcast_ref::<String>().cloned().unwrap_or_default(),
+    }
+}
+
+impl<T> Iterator for BlockPrefetcher<T> {
+    type Item = Result<(u64, T), BlkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let block_receiver = self.receiver.recv().ok()?;
+        Some(
+            block_receiver
+                .recv()
+                .unwrap_or(Err(BlkError::ReaderStopped)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::Bitcoin;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::consensus::serialize;
+    use bitcoin::Network;
+
+    fn blk_files(name: &str) -> (std::path::PathBuf, Arc<BlkFiles>) {
+        let dir = std::env::temp_dir().join(format!("ordi-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(dir.join("blocks")).unwrap();
+
+        let block = serialize(&genesis_block(Network::Bitcoin));
+        let mut blk = vec![0xf9, 0xbe, 0xb4, 0xd9];
+        blk.extend_from_slice(&(block.len() as u32).to_le_bytes());
+        blk.extend_from_slice(&block);
+        std::fs::write(dir.join("blocks").join("blk00000.dat"), blk).unwrap();
+
+        let files = Arc::new(BlkFiles::new(dir.clone(), Bitcoin.into()).unwrap());
+        (dir, files)
+    }
+
+    fn locations(count: u64) -> Vec<BlockLocation> {
+        (0..count)
+            .map(|height| BlockLocation {
+                height,
+                blk_index: 0,
+                data_offset: 8,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_prefetch_preserves_order() {
+        let (dir, files) = blk_files("prefetch");
+        let blocks = BlockPrefetcher::new(files, locations(32), 4, |height, block| {
+            (height, block.txs.len())
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+        assert_eq!(
+            blocks,
+            (0..32)
+                .map(|height| (height, (height, 1)))
+                .collect::<Vec<_>>()
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_prefetch_reports_panics() {
+        let (dir, files) = blk_files("prefetch-panic");
+        let mut blocks = BlockPrefetcher::new(files, locations(8), 4, |height, _| {
+            if height == 3 {
+                panic!("malformed block");
+            }
+            height
+        });
+
+        for height in 0..3 {
+            assert_eq!(blocks.next().unwrap().unwrap(), (height, height));
+        }
+        assert!(matches!(
+            blocks.next().unwrap(),
+            Err(BlkError::ReaderPanicked(3, message)) if message == "malformed block"
+        ));
+        assert_eq!(blocks.next().unwrap().unwrap(), (4, 4));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -5,6 +5,7 @@ use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
 use crate::bitcoin::common::utils;
 use crate::bitcoin::proto::header::BlockHeader;
+use crate::bitcoin::proto::script::AddressEncoding;
 use crate::bitcoin::proto::tx::{EvaluatedTx, RawTx};
 use crate::bitcoin::proto::varuint::VarUint;
 use crate::bitcoin::proto::{Hashed, MerkleBranch};
@@ -18,13 +19,14 @@ pub struct Block {
     pub txs: Vec<Hashed<EvaluatedTx>>,
 }
 
-impl From<bitcoin::Block> for Block {
-    fn from(value: bitcoin::Block) -> Block {
-        let tx_count = (value.txdata.len() as u32).into();
+impl Block {
+    /// Converts a block of `rust_bitcoin`, encoding output addresses with `address_encoding`.
+    pub fn from_bitcoin(value: bitcoin::Block, address_encoding: AddressEncoding) -> Block {
+        let tx_count = VarUint::compact(value.txdata.len() as u64);
         let txs = value
             .txdata
             .into_par_iter()
-            .map(|raw| Hashed::double_sha256(EvaluatedTx::from(raw)))
+            .map(|raw| Hashed::double_sha256(EvaluatedTx::from_transaction(raw, address_encoding)))
             .collect();
 
         Block {
@@ -35,9 +37,7 @@ impl From<bitcoin::Block> for Block {
             txs,
         }
     }
-}
 
-impl Block {
     pub fn new(
         size: u32,
         header: BlockHeader,
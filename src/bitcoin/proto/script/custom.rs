@@ -2,11 +2,36 @@
 use std::fmt;
 
 use bitcoin::base58;
+use bitcoin::bech32::{self, u5, ToBase32, Variant};
 use bitcoin::hashes::{hash160, sha256d, Hash};
 use bitcoin::opcodes::{all, All, Class, ClassifyContext};
 
 use crate::bitcoin::common::utils;
-use crate::bitcoin::proto::script::{EvaluatedScript, ScriptError, ScriptPattern};
+use crate::bitcoin::proto::script::{
+    is_public_key, pushnum, EvaluatedScript, ScriptError, ScriptPattern,
+};
+
+/// Address prefixes of a coin `rust_bitcoin` does not know.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressPrefixes {
+    /// Base58 version byte of pubkey hash addresses.
+    pub version_id: u8,
+    /// Base58 version byte of script hash addresses.
+    pub script_version_id: u8,
+    /// Human readable part of witness program addresses, for coins with segwit.
+    pub bech32_hrp: Option<&'static str>,
+}
+
+/// Prefixes of a coin with Bitcoin's script hash version and no segwit.
+impl From<u8> for AddressPrefixes {
+    fn from(version_id: u8) -> Self {
+        AddressPrefixes {
+            version_id,
+            script_version_id: 0x05,
+            bech32_hrp: None,
+        }
+    }
+}
 
 pub enum StackElement {
     Op(All),
@@ -197,17 +222,29 @@ impl<'a> ScriptEvaluator<'a> {
             };
         }
 
-        //TODO: implement n to m multisig
-        let multisig_2n3 = [
-            StackElement::Op(all::OP_PUSHNUM_2),
-            StackElement::Data(Vec::new()),
-            StackElement::Data(Vec::new()),
-            StackElement::Data(Vec::new()),
-            StackElement::Op(all::OP_PUSHNUM_3),
-            StackElement::Op(all::OP_CHECKMULTISIG),
-        ];
-        if ScriptEvaluator::match_stack_pattern(elements, &multisig_2n3) {
-            return ScriptPattern::Pay2MultiSig;
+        // Witness program: <version> <2 to 40 bytes>
+        if let [StackElement::Op(version), StackElement::Data(program)] = elements {
+            if let (Some(version), 2..=40) = (witness_version(*version), program.len()) {
+                return match (version, program.len()) {
+                    (0, 20) => ScriptPattern::Pay2WitnessPublicKeyHash,
+                    (0, 32) => ScriptPattern::Pay2WitnessScriptHash,
+                    (1, 32) => ScriptPattern::Pay2Taproot,
+                    _ => ScriptPattern::WitnessProgram,
+                };
+            }
+        }
+
+        // Multisig: <m> <pubkey>... <n> OP_CHECKMULTISIG
+        if let [StackElement::Op(required), keys @ .., StackElement::Op(count), StackElement::Op(all::OP_CHECKMULTISIG)] =
+            elements
+        {
+            let is_key =
+                |key: &StackElement| matches!(key, StackElement::Data(key) if is_public_key(key));
+            if let (Some(required), Some(count)) = (pushnum(*required), pushnum(*count)) {
+                if required <= count && count as usize == keys.len() && keys.iter().all(is_key) {
+                    return ScriptPattern::Pay2MultiSig(required, count);
+                }
+            }
         }
         /* TODO:
         // The Genesis Block, self-payments, and pay-by-IP-address payments look like:
@@ -249,9 +286,23 @@ impl<'a> ScriptEvaluator<'a> {
     }
 }
 
-pub fn eval_from_bytes_custom(bytes: &[u8], version_id: u8) -> EvaluatedScript {
+/// Version of a witness program pushed by `opcode`.
+fn witness_version(opcode: All) -> Option<u8> {
+    match opcode {
+        all::OP_PUSHBYTES_0 => Some(0),
+        opcode
+            if (all::OP_PUSHNUM_1.to_u8()..=all::OP_PUSHNUM_16.to_u8())
+                .contains(&opcode.to_u8()) =>
+        {
+            Some(opcode.to_u8() - all::OP_PUSHNUM_1.to_u8() + 1)
+        }
+        _ => None,
+    }
+}
+
+pub fn eval_from_bytes_custom(bytes: &[u8], prefixes: AddressPrefixes) -> EvaluatedScript {
     match ScriptEvaluator::new(bytes).eval() {
-        Ok(stack) => eval_from_stack(stack, version_id),
+        Ok(stack) => eval_from_stack(stack, prefixes),
         Err(ScriptError::UnexpectedEof) => EvaluatedScript {
             address: None,
             pattern: ScriptPattern::NotRecognised,
@@ -264,7 +315,8 @@ pub fn eval_from_bytes_custom(bytes: &[u8], version_id: u8) -> EvaluatedScript {
 }
 
 /// Extracts evaluated address from script stack
-fn compute_stack(stack: Stack, version_id: u8) -> Result<EvaluatedScript, ScriptError> {
+fn compute_stack(stack: Stack, prefixes: AddressPrefixes) -> Result<EvaluatedScript, ScriptError> {
+    let version_id = prefixes.version_id;
     let script = match stack.pattern {
         ref p @ ScriptPattern::Pay2PublicKey => {
             let pub_key = stack.elements[0].data()?;
@@ -283,7 +335,22 @@ fn compute_stack(stack: Stack, version_id: u8) -> Result<EvaluatedScript, Script
         ref p @ ScriptPattern::Pay2ScriptHash => {
             let h160 = stack.elements[1].data()?;
             EvaluatedScript {
-                address: Some(hash_160_to_address(&h160, 5)),
+                address: Some(hash_160_to_address(&h160, prefixes.script_version_id)),
+                pattern: p.clone(),
+            }
+        }
+        ref p @ (ScriptPattern::Pay2WitnessPublicKeyHash
+        | ScriptPattern::Pay2WitnessScriptHash
+        | ScriptPattern::Pay2Taproot
+        | ScriptPattern::WitnessProgram) => {
+            let StackElement::Op(version) = stack.elements[0] else {
+                return Err(ScriptError::InvalidFormat);
+            };
+            let program = stack.elements[1].data()?;
+            EvaluatedScript {
+                address: prefixes.bech32_hrp.and_then(|hrp| {
+                    witness_program_to_address(hrp, witness_version(version)?, &program)
+                }),
                 pattern: p.clone(),
             }
         }
@@ -291,7 +358,7 @@ fn compute_stack(stack: Stack, version_id: u8) -> Result<EvaluatedScript, Script
             address: None,
             pattern: ScriptPattern::OpReturn(data.clone()),
         },
-        ref p @ ScriptPattern::Pay2MultiSig => {
+        ref p @ ScriptPattern::Pay2MultiSig(..) => {
             stack.elements[1].data()?;
             EvaluatedScript {
                 address: None,
@@ -311,8 +378,8 @@ fn compute_stack(stack: Stack, version_id: u8) -> Result<EvaluatedScript, Script
 }
 
 /// Extracts evaluated address from script stack
-fn eval_from_stack(stack: Stack, version_id: u8) -> EvaluatedScript {
-    match compute_stack(stack, version_id) {
+fn eval_from_stack(stack: Stack, prefixes: AddressPrefixes) -> EvaluatedScript {
+    match compute_stack(stack, prefixes) {
         Ok(script) => script,
         Err(ScriptError::UnexpectedEof) => EvaluatedScript {
             address: None,
@@ -342,9 +409,22 @@ fn hash_160_to_address(h160: &[u8], version: u8) -> String {
     base58::encode(&hash)
 }
 
+/// Bech32 address of a version 0 witness program, bech32m of later versions (BIP350).
+fn witness_program_to_address(hrp: &str, version: u8, program: &[u8]) -> Option<String> {
+    let variant = match version {
+        0 => Variant::Bech32,
+        _ => Variant::Bech32m,
+    };
+    let mut data = vec![u5::try_from_u8(version).ok()?];
+    data.extend(program.to_base32());
+    bech32::encode(hrp, data, variant).ok()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{eval_from_bytes_custom, eval_from_stack, ScriptEvaluator, ScriptPattern};
+    use super::{
+        eval_from_bytes_custom, eval_from_stack, AddressPrefixes, ScriptEvaluator, ScriptPattern,
+    };
     use crate::bitcoin::common::utils;
 
     #[test]
@@ -362,7 +442,7 @@ mod tests {
             format!("{:?}", stack)
         );
 
-        let script = eval_from_stack(stack, 0x00);
+        let script = eval_from_stack(stack, 0x00.into());
         assert_eq!(
             script.address,
             Some(String::from("12higDjoCCNXSA95xZMWUdPvXNmkAduhWv"))
@@ -388,7 +468,7 @@ mod tests {
         assert_eq!("044bca633a91de10df85a63d0a24cb09783148fe0e16c92e937fc4491580c860757148effa0595a955f44078b48ba67fa198782e8bb68115da0daa8fde5301f7f9 OP_CHECKSIG",
             format!("{:?}", stack));
 
-        let script = eval_from_stack(stack, 0x00);
+        let script = eval_from_stack(stack, 0x00.into());
         assert_eq!(
             script.address,
             Some(String::from("1LEWwJkDj8xriE87ALzQYcHjTmD8aqDj1f"))
@@ -402,7 +482,6 @@ mod tests {
         // OP_2 33 0x022df8750480ad5b26950b25c7ba79d3e37d75f640f8e5d9bcd5b150a0f85014da
         // 33 0x03e3818b65bcc73a7d64064106a859cc1a5a728c4345ff0b641209fba0d90de6e9
         // 33 0x021f2f6e1e50cb6a953935c3601284925decd3fd21bc445712576873fb8c6ebc18 OP_3 OP_CHECKMULTISIG
-        //TODO: complete this test
         let bytes = [
             0x52, 0x21, 0x02, 0x2d, 0xf8, 0x75, 0x04, 0x80, 0xad, 0x5b, 0x26, 0x95, 0x0b, 0x25,
             0xc7, 0xba, 0x79, 0xd3, 0xe3, 0x7d, 0x75, 0xf6, 0x40, 0xf8, 0xe5, 0xd9, 0xbc, 0xd5,
@@ -421,7 +500,19 @@ mod tests {
                    03e3818b65bcc73a7d64064106a859cc1a5a728c4345ff0b641209fba0d90de6e9 \
                    021f2f6e1e50cb6a953935c3601284925decd3fd21bc445712576873fb8c6ebc18 OP_PUSHNUM_3 OP_CHECKMULTISIG",
                    format!("{:?}", stack));
-        assert_eq!(stack.pattern, ScriptPattern::Pay2MultiSig);
+        assert_eq!(stack.pattern, ScriptPattern::Pay2MultiSig(2, 3));
+
+        // 1-of-3, as Stamps encode data.
+        let mut bytes = bytes.to_vec();
+        bytes[0] = 0x51;
+        let script = eval_from_bytes_custom(&bytes, 0x00.into());
+        assert_eq!(script.address, None);
+        assert_eq!(script.pattern, ScriptPattern::Pay2MultiSig(1, 3));
+
+        // More required signatures than keys.
+        bytes[0] = 0x54;
+        let script = eval_from_bytes_custom(&bytes, 0x00.into());
+        assert_eq!(script.pattern, ScriptPattern::NotRecognised);
     }
 
     #[test]
@@ -440,7 +531,7 @@ mod tests {
             format!("{:?}", stack)
         );
 
-        let script = eval_from_stack(stack, 0x00);
+        let script = eval_from_stack(stack, 0x00.into());
         assert_eq!(
             script.address,
             Some(String::from("3P14159f73E4gFr7JterCCQh9QjiTjiZrG"))
@@ -463,7 +554,7 @@ mod tests {
             format!("{:?}", stack)
         );
 
-        let script = eval_from_stack(stack, 0x00);
+        let script = eval_from_stack(stack, 0x00.into());
         assert_eq!(script.address, None);
         assert_eq!(
             script.pattern,
@@ -483,7 +574,7 @@ mod tests {
             format!("{:?}", stack)
         );
 
-        let script = eval_from_stack(stack, 0x00);
+        let script = eval_from_stack(stack, 0x00.into());
         assert_eq!(script.address, None);
         assert_eq!(script.pattern, ScriptPattern::NotRecognised);
     }
@@ -491,7 +582,7 @@ mod tests {
     #[test]
     fn test_bitcoin_bogus_script() {
         let bytes = [0x4c, 0xFF, 0x00];
-        let script = eval_from_bytes_custom(&bytes, 0x00);
+        let script = eval_from_bytes_custom(&bytes, 0x00.into());
         assert_eq!(script.address, None);
         assert_eq!(script.pattern, ScriptPattern::NotRecognised);
     }
@@ -503,7 +594,7 @@ mod tests {
     #[test]
     fn test_namecoin_coinbase_script() {
         let script_pubkey = utils::hex_to_vec("41046a77fa46493d61985c1157a6e3e498b3b97c878c9c23e5b4729d354b574eb33a20c0483551308e2bd08295ce238e8ad09a7a2477732eb2e995a3e20455e9d137ac");
-        let script = eval_from_bytes_custom(&script_pubkey, 0x34);
+        let script = eval_from_bytes_custom(&script_pubkey, 0x34.into());
         assert_eq!(
             script.address,
             Some(String::from("N3Jpya157nc2d48EPaxtcsbRr9V19U4hfW")),
@@ -514,19 +605,46 @@ mod tests {
     #[test]
     fn test_litecoin_coinbase_script() {
         let script_pubkey = utils::hex_to_vec("4104458bf7d944ce58c007d0f16fa54c0640694568954e162c06be0a0cba7275714b6672c589e7393fa48f8a5f6b6259061d394e9db005651d1bb28349d31339daa8ac");
-        let script = eval_from_bytes_custom(&script_pubkey, 0x30);
+        let script = eval_from_bytes_custom(&script_pubkey, 0x30.into());
         assert_eq!(
             script.address,
             Some(String::from("LfcUcxALy1gSeqZLrixAm4ETZbEWA7GLat")),
         );
     }
 
+    #[test]
+    fn test_litecoin_witness_scripts() {
+        let prefixes = AddressPrefixes {
+            version_id: 0x30,
+            script_version_id: 0x32,
+            bech32_hrp: Some("ltc"),
+        };
+        let p2wpkh = utils::hex_to_vec("0014751e76e8199196d454941c45d1b3a323f1433bd6");
+        let script = eval_from_bytes_custom(&p2wpkh, prefixes);
+        assert_eq!(script.pattern, ScriptPattern::Pay2WitnessPublicKeyHash);
+        assert_eq!(
+            script.address,
+            Some(String::from("ltc1qw508d6qejxtdg4y5r3zarvary0c5xw7kgmn4n9")),
+        );
+
+        let p2tr = utils::hex_to_vec(
+            "5120a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c",
+        );
+        let script = eval_from_bytes_custom(&p2tr, prefixes);
+        assert_eq!(script.pattern, ScriptPattern::Pay2Taproot);
+        assert!(script.address.unwrap().starts_with("ltc1p"));
+
+        let script = eval_from_bytes_custom(&p2wpkh, 0x30.into());
+        assert_eq!(script.pattern, ScriptPattern::Pay2WitnessPublicKeyHash);
+        assert_eq!(script.address, None);
+    }
+
     #[test]
     fn test_dogecoin_coinbase_script() {
         let script_pubkey = utils::hex_to_vec(
             "210338bf57d51a50184cf5ef0dc42ecd519fb19e24574c057620262cc1df94da2ae5ac",
         );
-        let script = eval_from_bytes_custom(&script_pubkey, 0x1e);
+        let script = eval_from_bytes_custom(&script_pubkey, 0x1e.into());
         assert_eq!(
             script.address,
             Some(String::from("DLAznsPDLDRgsVcTFWRMYMG5uH6GddDtv8")),
@@ -7,9 +7,11 @@ use std::fmt;
 use bitcoin::address::Payload;
 use bitcoin::blockdata::script::Instruction;
 use bitcoin::hashes::{hash160, Hash};
+use bitcoin::opcodes::{all, All, Class, ClassifyContext};
 use bitcoin::{address, Address, Network, PubkeyHash, Script};
 
 use crate::bitcoin::proto::script::custom::eval_from_bytes_custom;
+pub use crate::bitcoin::proto::script::custom::AddressPrefixes;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum ScriptError {
@@ -39,8 +41,8 @@ pub enum ScriptPattern {
     /// Pay to Multisig [BIP11]
     /// Pubkey script: <m> <A pubkey>[B pubkey][C pubkey...] <n> OP_CHECKMULTISIG
     /// Signature script: OP_0 <A sig>[B sig][C sig...]
-    /// TODO: Implement Pay2MultiSig: For now only 2n3 MultiSigs are detected
-    Pay2MultiSig,
+    /// Holds m and n, the required signatures and the public keys.
+    Pay2MultiSig(u8, u8),
 
     /// Pay to Public Key (p2pk) scripts are a simplified form of the p2pkh,
     /// but aren't commonly used in new transactions anymore,
@@ -82,7 +84,7 @@ impl fmt::Display for ScriptPattern {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             ScriptPattern::OpReturn(_) => write!(f, "OpReturn"),
-            ScriptPattern::Pay2MultiSig => write!(f, "Pay2MultiSig"),
+            ScriptPattern::Pay2MultiSig(..) => write!(f, "Pay2MultiSig"),
             ScriptPattern::Pay2PublicKey => write!(f, "Pay2PublicKey"),
             ScriptPattern::Pay2PublicKeyHash => write!(f, "Pay2PublicKeyHash"),
             ScriptPattern::Pay2ScriptHash => write!(f, "Pay2ScriptHash"),
@@ -109,22 +111,25 @@ impl EvaluatedScript {
     }
 }
 
+/// How addresses of output scripts are encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressEncoding {
+    /// By `rust_bitcoin`, for the networks it knows.
+    Bitcoin(Network),
+    /// From the prefixes of base58 and bech32 addresses, for other coins.
+    Prefixes(AddressPrefixes),
+}
+
 /// Extracts evaluated address from ScriptPubKey
-pub fn eval_from_bytes(bytes: &[u8], version_id: u8) -> EvaluatedScript {
-    match version_id {
-        0x00 | 0x6f => eval_from_bytes_bitcoin(bytes, version_id),
-        _ => eval_from_bytes_custom(bytes, version_id),
+pub fn eval_from_bytes(bytes: &[u8], encoding: AddressEncoding) -> EvaluatedScript {
+    match encoding {
+        AddressEncoding::Bitcoin(network) => eval_from_bytes_bitcoin(bytes, network),
+        AddressEncoding::Prefixes(prefixes) => eval_from_bytes_custom(bytes, prefixes),
     }
 }
 
 /// Extracts evaluated address from script using `rust_bitcoin`
-pub fn eval_from_bytes_bitcoin(bytes: &[u8], version_id: u8) -> EvaluatedScript {
-    let network = match version_id {
-        0x00 => Network::Bitcoin,
-        0x6f => Network::Testnet,
-        _ => panic!("invalid network version"),
-    };
-
+pub fn eval_from_bytes_bitcoin(bytes: &[u8], network: Network) -> EvaluatedScript {
     let script = Script::from_bytes(bytes);
 
     // For OP_RETURN and provably unspendable scripts there is no point in parsing the address
@@ -164,11 +169,59 @@ pub fn eval_from_bytes_bitcoin(bytes: &[u8], version_id: u8) -> EvaluatedScript
         EvaluatedScript::new(address, ScriptPattern::Pay2Taproot)
     } else if script.is_witness_program() {
         EvaluatedScript::new(address, ScriptPattern::WitnessProgram)
+    } else if let Some((required, keys)) = multisig(bytes) {
+        EvaluatedScript::new(
+            None,
+            ScriptPattern::Pay2MultiSig(required, keys.len() as u8),
+        )
     } else {
         EvaluatedScript::new(address, ScriptPattern::NotRecognised)
     }
 }
 
+/// Required signatures and public keys of a bare multisig script,
+/// `<m> <pubkey>... <n> OP_CHECKMULTISIG` with `1 <= m <= n <= 16`.
+pub fn multisig(bytes: &[u8]) -> Option<(u8, Vec<&[u8]>)> {
+    let instructions = Script::from_bytes(bytes)
+        .instructions()
+        .collect::<Result<Vec<Instruction>, _>>()
+        .ok()?;
+    let [Instruction::Op(required), keys @ .., Instruction::Op(count), Instruction::Op(all::OP_CHECKMULTISIG)] =
+        instructions.as_slice()
+    else {
+        return None;
+    };
+
+    let keys = keys
+        .iter()
+        .map(|key| match key {
+            Instruction::PushBytes(key) if is_public_key(key.as_bytes()) => Some(key.as_bytes()),
+            _ => None,
+        })
+        .collect::<Option<Vec<&[u8]>>>()?;
+    let required = pushnum(*required)?;
+    (required as usize <= keys.len() && pushnum(*count)? as usize == keys.len())
+        .then_some((required, keys))
+}
+
+/// Number pushed by `OP_PUSHNUM_1` to `OP_PUSHNUM_16`.
+pub(crate) fn pushnum(opcode: All) -> Option<u8> {
+    match opcode.classify(ClassifyContext::Legacy) {
+        Class::PushNum(n @ 1..=16) => Some(n as u8),
+        _ => None,
+    }
+}
+
+/// Whether `key` has the size its header byte tells, as Bitcoin Core checks
+/// keys of multisig scripts.
+pub(crate) fn is_public_key(key: &[u8]) -> bool {
+    match key.first() {
+        Some(0x02 | 0x03) => key.len() == 33,
+        Some(0x04 | 0x06 | 0x07) => key.len() == 65,
+        _ => false,
+    }
+}
+
 /// Workaround to parse address from p2pk scripts
 /// See issue https://github.com/rust-bitcoin/rust-bitcoin/issues/441
 fn p2pk_to_string(script: &Script, network: Network) -> Option<String> {
@@ -191,6 +244,7 @@ fn p2pk_to_string(script: &Script, network: Network) -> Option<String> {
 mod tests {
     use super::ScriptPattern;
     use crate::bitcoin::proto::script::eval_from_bytes_bitcoin;
+    use bitcoin::Network;
 
     #[test]
     fn test_bitcoin_script_p2pkh() {
@@ -200,7 +254,7 @@ mod tests {
             0x76, 0xa9, 0x14, 0x12, 0xab, 0x8d, 0xc5, 0x88, 0xca, 0x9d, 0x57, 0x87, 0xdd, 0xe7,
             0xeb, 0x29, 0x56, 0x9d, 0xa6, 0x3c, 0x3a, 0x23, 0x8c, 0x88, 0xac,
         ];
-        let result = eval_from_bytes_bitcoin(&bytes, 0x00);
+        let result = eval_from_bytes_bitcoin(&bytes, Network::Bitcoin);
         assert_eq!(
             result.address,
             Some(String::from("12higDjoCCNXSA95xZMWUdPvXNmkAduhWv"))
@@ -221,7 +275,7 @@ mod tests {
             0x40, 0x78, 0xb4, 0x8b, 0xa6, 0x7f, 0xa1, 0x98, 0x78, 0x2e, 0x8b, 0xb6, 0x81, 0x15,
             0xda, 0x0d, 0xaa, 0x8f, 0xde, 0x53, 0x01, 0xf7, 0xf9, 0xac,
         ]; // OP_CHECKSIG
-        let result = eval_from_bytes_bitcoin(&bytes, 0x00);
+        let result = eval_from_bytes_bitcoin(&bytes, Network::Bitcoin);
         assert_eq!(
             result.address,
             Some(String::from("1LEWwJkDj8xriE87ALzQYcHjTmD8aqDj1f"))
@@ -229,15 +283,12 @@ mod tests {
         assert_eq!(result.pattern, ScriptPattern::Pay2PublicKey);
     }
 
-    /*
-    // FIXME: See https://github.com/rust-bitcoin/rust-bitcoin/pull/657/files
     #[test]
     fn test_bitcoin_script_p2ms() {
         // 2-of-3 Multi sig output
         // OP_2 33 0x022df8750480ad5b26950b25c7ba79d3e37d75f640f8e5d9bcd5b150a0f85014da
         // 33 0x03e3818b65bcc73a7d64064106a859cc1a5a728c4345ff0b641209fba0d90de6e9
         // 33 0x021f2f6e1e50cb6a953935c3601284925decd3fd21bc445712576873fb8c6ebc18 OP_3 OP_CHECKMULTISIG
-        //TODO: complete this test
         let bytes = [
             0x52, 0x21, 0x02, 0x2d, 0xf8, 0x75, 0x04, 0x80, 0xad, 0x5b, 0x26, 0x95, 0x0b, 0x25,
             0xc7, 0xba, 0x79, 0xd3, 0xe3, 0x7d, 0x75, 0xf6, 0x40, 0xf8, 0xe5, 0xd9, 0xbc, 0xd5,
@@ -248,10 +299,17 @@ mod tests {
             0x84, 0x92, 0x5d, 0xec, 0xd3, 0xfd, 0x21, 0xbc, 0x44, 0x57, 0x12, 0x57, 0x68, 0x73,
             0xfb, 0x8c, 0x6e, 0xbc, 0x18, 0x53, 0xae,
         ];
-        let result = eval_from_bytes_bitcoin(&bytes, 0x00);
-        assert_eq!(result.pattern, ScriptPattern::Pay2MultiSig);
+        let result = eval_from_bytes_bitcoin(&bytes, Network::Bitcoin);
+        assert_eq!(result.address, None);
+        assert_eq!(result.pattern, ScriptPattern::Pay2MultiSig(2, 3));
+
+        // Key count not matching n.
+        let mut bytes = bytes.to_vec();
+        let count = bytes.len() - 2;
+        bytes[count] = 0x54;
+        let result = eval_from_bytes_bitcoin(&bytes, Network::Bitcoin);
+        assert_eq!(result.pattern, ScriptPattern::NotRecognised);
     }
-    */
 
     #[test]
     fn test_bitcoin_script_p2sh() {
@@ -262,7 +320,7 @@ mod tests {
             0xe9, 0xc3, 0xdd, 0x0c, 0x07, 0xaa, 0xc7, 0x61, 0x79, 0xeb, 0xc7, 0x6a, 0x6c, 0x78,
             0xd4, 0xd6, 0x7c, 0x6c, 0x16, 0x0a, 0x87,
         ]; // OP_EQUAL
-        let result = eval_from_bytes_bitcoin(&bytes, 0x00);
+        let result = eval_from_bytes_bitcoin(&bytes, Network::Bitcoin);
         assert_eq!(
             result.address,
             Some(String::from("3P14159f73E4gFr7JterCCQh9QjiTjiZrG"))
@@ -278,7 +336,7 @@ mod tests {
             0x6a, 0x13, 0x63, 0x68, 0x61, 0x72, 0x6c, 0x65, 0x79, 0x20, 0x6c, 0x6f, 0x76, 0x65,
             0x73, 0x20, 0x68, 0x65, 0x69, 0x64, 0x69,
         ];
-        let result = eval_from_bytes_bitcoin(&bytes, 0x00);
+        let result = eval_from_bytes_bitcoin(&bytes, Network::Bitcoin);
         assert_eq!(result.address, None);
         assert_eq!(
             result.pattern,
@@ -291,7 +349,7 @@ mod tests {
         // Raw output script: 736372697074
         //                    OP_IFDUP OP_IF OP_2SWAP OP_VERIFY OP_2OVER OP_DEPTH
         let bytes = [0x73, 0x63, 0x72, 0x69, 0x70, 0x74];
-        let result = eval_from_bytes_bitcoin(&bytes, 0x00);
+        let result = eval_from_bytes_bitcoin(&bytes, Network::Bitcoin);
         assert_eq!(result.address, None);
         assert_eq!(result.pattern, ScriptPattern::NotRecognised);
     }
@@ -299,7 +357,7 @@ mod tests {
     #[test]
     fn test_bitcoin_bogus_script() {
         let bytes = [0x4c, 0xFF, 0x00];
-        let result = eval_from_bytes_bitcoin(&bytes, 0x00);
+        let result = eval_from_bytes_bitcoin(&bytes, Network::Bitcoin);
         assert_eq!(result.address, None);
         assert_eq!(result.pattern, ScriptPattern::NotRecognised);
     }
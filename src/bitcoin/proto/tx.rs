@@ -5,7 +5,7 @@ use bitcoin::{Transaction, Witness};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
 use crate::bitcoin::common::utils;
-use crate::bitcoin::proto::script;
+use crate::bitcoin::proto::script::{self, AddressEncoding};
 use crate::bitcoin::proto::varuint::VarUint;
 use crate::bitcoin::proto::ToRaw;
 
@@ -17,7 +17,7 @@ pub struct RawTx {
     pub out_count: VarUint,
     pub outputs: Vec<TxOutput>,
     pub locktime: u32,
-    pub version_id: u8,
+    pub address_encoding: AddressEncoding,
 }
 
 /// Simple transaction struct
@@ -40,12 +40,12 @@ impl EvaluatedTx {
         out_count: VarUint,
         outputs: Vec<TxOutput>,
         locktime: u32,
-        version_id: u8,
+        address_encoding: AddressEncoding,
     ) -> Self {
         // Evaluate and wrap all outputs to process them later
         let outputs = outputs
             .into_par_iter()
-            .map(|o| EvaluatedTxOut::eval_script(o, version_id))
+            .map(|o| EvaluatedTxOut::eval_script(o, address_encoding))
             .collect();
         EvaluatedTx {
             version,
@@ -86,13 +86,13 @@ impl From<RawTx> for EvaluatedTx {
             tx.out_count,
             tx.outputs,
             tx.locktime,
-            tx.version_id,
+            tx.address_encoding,
         )
     }
 }
 
-impl From<Transaction> for EvaluatedTx {
-    fn from(tx: Transaction) -> EvaluatedTx {
+impl EvaluatedTx {
+    pub fn from_transaction(tx: Transaction, address_encoding: AddressEncoding) -> EvaluatedTx {
         let inputs = tx
             .input
             .into_par_iter()
@@ -101,7 +101,7 @@ impl From<Transaction> for EvaluatedTx {
                     txid: input.previous_output.txid.to_raw_hash(),
                     index: input.previous_output.vout,
                 },
-                script_len: (input.script_sig.len() as u64).into(),
+                script_len: VarUint::compact(input.script_sig.len() as u64),
                 script_sig: input.script_sig.into_bytes(),
                 seq_no: input.sequence.to_consensus_u32(),
                 witness: if input.witness.len() != 0 {
@@ -117,18 +117,18 @@ impl From<Transaction> for EvaluatedTx {
             .into_par_iter()
             .map(|output| TxOutput {
                 value: output.value,
-                script_len: (output.script_pubkey.len() as u64).into(),
+                script_len: VarUint::compact(output.script_pubkey.len() as u64),
                 script_pubkey: output.script_pubkey.into_bytes(),
             })
             .collect::<Vec<TxOutput>>();
         EvaluatedTx::new(
             tx.version as u32,
-            (inputs.len() as u32).into(),
+            VarUint::compact(inputs.len() as u64),
             inputs,
-            (outputs.len() as u32).into(),
+            VarUint::compact(outputs.len() as u64),
             outputs,
             tx.lock_time.to_consensus_u32(),
-            0,
+            address_encoding,
         )
     }
 }
@@ -237,9 +237,9 @@ pub struct EvaluatedTxOut {
 }
 
 impl EvaluatedTxOut {
-    pub fn eval_script(out: TxOutput, version_id: u8) -> EvaluatedTxOut {
+    pub fn eval_script(out: TxOutput, address_encoding: AddressEncoding) -> EvaluatedTxOut {
         EvaluatedTxOut {
-            script: script::eval_from_bytes(&out.script_pubkey, version_id),
+            script: script::eval_from_bytes(&out.script_pubkey, address_encoding),
             out,
         }
     }
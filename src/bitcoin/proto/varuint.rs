@@ -20,6 +20,16 @@ impl VarUint {
         VarUint { value, buf }
     }
 
+    /// Shortest encoding of `value`, the one used in serialized blocks.
+    pub fn compact(value: u64) -> VarUint {
+        match value {
+            0..=0xfc => VarUint::from(value as u8),
+            0xfd..=0xffff => VarUint::from(value as u16),
+            0x1_0000..=0xffff_ffff => VarUint::from(value as u32),
+            _ => VarUint::from(value),
+        }
+    }
+
     pub fn read_from<R: Read + ?Sized>(reader: &mut R) -> io::Result<VarUint> {
         let first = reader.read_u8()?; // read first length byte
         let vint = match first {
@@ -136,6 +146,14 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_varuint_compact() {
+        assert_eq!(VarUint::compact(0xfc).to_bytes(), vec![0xfc]);
+        assert_eq!(VarUint::compact(0xfd).to_bytes(), vec![0xfd, 0xfd, 0x00]);
+        assert_eq!(VarUint::compact(0x1_0000).to_bytes().len(), 5);
+        assert_eq!(VarUint::compact(0x1_0000_0000).to_bytes().len(), 9);
+    }
+
     #[test]
     fn test_varuint_read() {
         let mut cursor = io::Cursor::new([0xfe, 0x55, 0xa1, 0xae, 0xc6]);
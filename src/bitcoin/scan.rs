@@ -0,0 +1,506 @@
+//! Block index rebuilt by scanning blk files, for when `blocks/index` can't be
+//! read, e.g. while bitcoind is running or after its format changed.
+//!
+//! Headers found in blk files are kept beside the cached chain, with how far
+//! each file was scanned, so later scans only read new blocks. Each new header
+//! is linked to its parent for its height and chain work, headers whose parent
+//! was not scanned yet are kept as orphans until it is. The stored block with
+//! the most work becomes the tip: blocks are not validated, bitcoind only stores
+//! blocks it accepted.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use bitcoin::hashes::{sha256d, Hash};
+use bitcoin::pow::Work;
+use byteorder::{LittleEndian, ReadBytesExt};
+use log::info;
+use seek_bufread::BufReader;
+
+use crate::bitcoin::blk::{read_xor_key, BlkError, XorReader};
+use crate::bitcoin::chain_cache::ChainCache;
+use crate::bitcoin::index::{IndexEntry, IndexError, BLOCK_HAVE_DATA, BLOCK_VALID_SCRIPTS};
+use crate::bitcoin::proto::varuint::VarUint;
+use crate::bitcoin::CoinType;
+use crate::store::{Store, WriteBatch};
+
+/// `f<blk index>` to the scanned length of the blk file.
+const FILE_PREFIX: u8 = b'f';
+/// `b<block hash>` to its `ScannedHeader`, height and chain work.
+const HEADER_PREFIX: u8 = b'b';
+/// `o<block hash>` to the `ScannedHeader` of a block whose parent is unknown.
+const ORPHAN_PREFIX: u8 = b'o';
+
+const HEADER_SIZE: usize = 80;
+const SCANNED_HEADER_SIZE: usize = 32 + 4 + 4 + 8 + 8 + 8;
+const LINKED_HEADER_SIZE: usize = SCANNED_HEADER_SIZE + 8 + 32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ScannedHeader {
+    block_hash: sha256d::Hash,
+    prev_hash: sha256d::Hash,
+    version: u32,
+    bits: u32,
+    blk_index: u64,
+    data_offset: u64,
+    tx_count: u64,
+}
+
+impl ScannedHeader {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SCANNED_HEADER_SIZE);
+        bytes.extend_from_slice(self.prev_hash.as_byte_array());
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes.extend_from_slice(&self.bits.to_le_bytes());
+        bytes.extend_from_slice(&self.blk_index.to_le_bytes());
+        bytes.extend_from_slice(&self.data_offset.to_le_bytes());
+        bytes.extend_from_slice(&self.tx_count.to_le_bytes());
+        bytes
+    }
+
+    fn from_kv(key: &[u8], value: &[u8]) -> Option<ScannedHeader> {
+        if key.len() != 33 || value.len() < SCANNED_HEADER_SIZE {
+            return None;
+        }
+
+        let u32_at = |i: usize| u32::from_le_bytes(value[i..i + 4].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_le_bytes(value[i..i + 8].try_into().unwrap());
+        Some(ScannedHeader {
+            block_hash: sha256d::Hash::from_slice(&key[1..]).ok()?,
+            prev_hash: sha256d::Hash::from_slice(&value[..32]).ok()?,
+            version: u32_at(32),
+            bits: u32_at(36),
+            blk_index: u64_at(40),
+            data_offset: u64_at(48),
+            tx_count: u64_at(56),
+        })
+    }
+
+    fn into_entry(self, height: u64) -> IndexEntry {
+        IndexEntry {
+            block_hash: self.block_hash,
+            prev_hash: self.prev_hash,
+            bits: self.bits,
+            blk_index: self.blk_index,
+            data_offset: self.data_offset,
+            version: self.version as u64,
+            height,
+            status: BLOCK_VALID_SCRIPTS | BLOCK_HAVE_DATA,
+            tx_count: self.tx_count,
+        }
+    }
+}
+
+/// A header linked back to the genesis block.
+#[derive(Debug, Clone)]
+struct LinkedHeader {
+    entry: IndexEntry,
+    chain_work: Work,
+}
+
+impl LinkedHeader {
+    fn to_bytes(&self) -> Vec<u8> {
+        let entry = &self.entry;
+        let mut bytes = Vec::with_capacity(LINKED_HEADER_SIZE);
+        bytes.extend_from_slice(entry.prev_hash.as_byte_array());
+        bytes.extend_from_slice(&(entry.version as u32).to_le_bytes());
+        bytes.extend_from_slice(&entry.bits.to_le_bytes());
+        bytes.extend_from_slice(&entry.blk_index.to_le_bytes());
+        bytes.extend_from_slice(&entry.data_offset.to_le_bytes());
+        bytes.extend_from_slice(&entry.tx_count.to_le_bytes());
+        bytes.extend_from_slice(&entry.height.to_le_bytes());
+        bytes.extend_from_slice(&self.chain_work.to_le_bytes());
+        bytes
+    }
+
+    fn from_kv(key: &[u8], value: &[u8]) -> Option<LinkedHeader> {
+        if value.len() != LINKED_HEADER_SIZE {
+            return None;
+        }
+
+        let header = ScannedHeader::from_kv(key, value)?;
+        let height = u64::from_le_bytes(value[64..72].try_into().unwrap());
+        Some(LinkedHeader {
+            entry: header.into_entry(height),
+            chain_work: Work::from_le_bytes(value[72..].try_into().unwrap()),
+        })
+    }
+}
+
+/// Scans new blocks in blk files into `cache`, and moves its tip to the block
+/// with the most work.
+pub(crate) fn scan(
+    btc_data_dir: &Path,
+    cache: &mut ChainCache,
+    coin: &CoinType,
+) -> Result<(), IndexError> {
+    let xor_key = read_xor_key(btc_data_dir)?;
+    let mut orphans = load_orphans(cache.store())?;
+    let mut best: Option<LinkedHeader> = None;
+
+    let mut scanned_headers = 0;
+    for blk_index in 0.. {
+        let blk_path = btc_data_dir
+            .join("blocks")
+            .join(format!("blk{:0>5}.dat", blk_index));
+        if !blk_path.exists() {
+            break;
+        }
+
+        let store = cache.store();
+        let file_key = file_key(blk_index);
+        let scanned = store
+            .get(&file_key)?
+            .and_then(|v| v.try_into().ok())
+            .map(u64::from_le_bytes)
+            .unwrap_or(0);
+        if scanned >= fs::metadata(&blk_path)?.len() {
+            continue;
+        }
+
+        let file = XorReader::new(File::open(&blk_path)?, xor_key);
+        let (headers, scanned) = scan_blk(file, blk_index, scanned, coin)?;
+        scanned_headers += headers.len();
+
+        let mut wb = WriteBatch::new();
+        for linked in link_headers(store, headers, &mut orphans, coin.genesis_hash, &mut wb)? {
+            if best
+                .as_ref()
+                .is_none_or(|best| linked.chain_work > best.chain_work)
+            {
+                best = Some(linked);
+            }
+        }
+        wb.put(&file_key, &scanned.to_le_bytes());
+        store.write(wb)?;
+    }
+    cache.store().flush()?;
+
+    if scanned_headers > 0 {
+        info!(
+            "Scanned {} new headers in blk files, {} orphans.",
+            scanned_headers,
+            orphans.values().map(Vec::len).sum::<usize>()
+        );
+    }
+
+    let best = match best {
+        Some(best) => best,
+        None => return Ok(()),
+    };
+    if let Some((_, tip_work)) = cache.tip()? {
+        if best.chain_work <= tip_work {
+            return Ok(());
+        }
+    }
+
+    let chain_work = best.chain_work;
+    let mut entries = vec![];
+    let mut entry = best.entry;
+    while !cache.contains(&entry)? {
+        let prev_hash = entry.prev_hash;
+        let genesis = entry.height == 0;
+        entries.push(entry);
+        if genesis {
+            break;
+        }
+        entry = get_linked(cache.store(), &prev_hash)?
+            .ok_or(IndexError::EntryNotFound)?
+            .entry;
+    }
+
+    cache.connect(&entries, chain_work)
+}
+
+/// Links `headers` and the orphans waiting for them, returns the linked ones.
+/// Headers of this batch are looked up before the ones already in `store`.
+fn link_headers(
+    store: &mut dyn Store,
+    headers: Vec<ScannedHeader>,
+    orphans: &mut HashMap<sha256d::Hash, Vec<ScannedHeader>>,
+    genesis_hash: sha256d::Hash,
+    wb: &mut WriteBatch,
+) -> Result<Vec<LinkedHeader>, IndexError> {
+    let mut linked: HashMap<sha256d::Hash, LinkedHeader> = HashMap::new();
+    let mut order = vec![];
+
+    for header in headers {
+        let parent = match header.block_hash == genesis_hash {
+            true => None,
+            false => match linked.get(&header.prev_hash) {
+                Some(parent) => Some(parent.clone()),
+                None => match get_linked(store, &header.prev_hash)? {
+                    Some(parent) => Some(parent),
+                    None => {
+                        wb.put(&orphan_key(&header.block_hash), &header.to_bytes());
+                        orphans.entry(header.prev_hash).or_default().push(header);
+                        continue;
+                    }
+                },
+            },
+        };
+
+        let mut queue = vec![link(header, parent.as_ref())];
+        while let Some(header) = queue.pop() {
+            for child in orphans.remove(&header.entry.block_hash).unwrap_or_default() {
+                wb.delete(&orphan_key(&child.block_hash));
+                queue.push(link(child, Some(&header)));
+            }
+
+            wb.put(&header_key(&header.entry.block_hash), &header.to_bytes());
+            order.push(header.entry.block_hash);
+            linked.insert(header.entry.block_hash, header);
+        }
+    }
+
+    Ok(order
+        .into_iter()
+        .filter_map(|block_hash| linked.remove(&block_hash))
+        .collect())
+}
+
+fn link(header: ScannedHeader, parent: Option<&LinkedHeader>) -> LinkedHeader {
+    let (height, parent_work) = match parent {
+        Some(parent) => (parent.entry.height + 1, Some(parent.chain_work)),
+        None => (0, None),
+    };
+    let entry = header.into_entry(height);
+    let chain_work = match parent_work {
+        Some(parent_work) => parent_work + entry.work(),
+        None => entry.work(),
+    };
+
+    LinkedHeader { entry, chain_work }
+}
+
+fn get_linked(
+    store: &mut dyn Store,
+    block_hash: &sha256d::Hash,
+) -> Result<Option<LinkedHeader>, IndexError> {
+    let key = header_key(block_hash);
+    Ok(store
+        .get(&key)?
+        .and_then(|value| LinkedHeader::from_kv(&key, &value)))
+}
+
+/// Orphans by the hash of the parent they wait for.
+fn load_orphans(
+    store: &mut dyn Store,
+) -> Result<HashMap<sha256d::Hash, Vec<ScannedHeader>>, IndexError> {
+    let mut orphans: HashMap<sha256d::Hash, Vec<ScannedHeader>> = HashMap::new();
+    store.scan(&[ORPHAN_PREFIX], &mut |key, value| {
+        if key.first() != Some(&ORPHAN_PREFIX) {
+            return false;
+        }
+        if let Some(header) = ScannedHeader::from_kv(key, value) {
+            orphans.entry(header.prev_hash).or_default().push(header);
+        }
+        true
+    })?;
+
+    Ok(orphans)
+}
+
+/// Reads the headers of blocks in a blk file from `offset`, returns them with
+/// the offset following the last complete block.
+fn scan_blk<R: Read + Seek>(
+    file: R,
+    blk_index: u64,
+    mut offset: u64,
+    coin: &CoinType,
+) -> Result<(Vec<ScannedHeader>, u64), IndexError> {
+    let mut reader = BufReader::new(file);
+    let len = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(offset))?;
+
+    let mut headers = vec![];
+    // bitcoind preallocates blk files with zeros, and may be writing the last block.
+    while offset + 8 <= len {
+        let magic = reader.read_u32::<LittleEndian>()?;
+        if magic == 0 {
+            break;
+        }
+        if magic != coin.magic {
+            return Err(BlkError::UnexpectedMagic(magic, offset).into());
+        }
+
+        let size = reader.read_u32::<LittleEndian>()? as u64;
+        if size < HEADER_SIZE as u64 || offset + 8 + size > len {
+            break;
+        }
+
+        let mut header = [0u8; HEADER_SIZE];
+        reader.read_exact(&mut header)?;
+        let version = u32::from_le_bytes(header[..4].try_into().unwrap());
+        let tx_count = match coin.aux_pow_activation_version {
+            Some(aux_pow_version) if version >= aux_pow_version => 0,
+            _ => VarUint::read_from(&mut reader)?.value,
+        };
+
+        headers.push(ScannedHeader {
+            block_hash: sha256d::Hash::hash(&header),
+            prev_hash: sha256d::Hash::from_slice(&header[4..36]).unwrap(),
+            version,
+            bits: u32::from_le_bytes(header[72..76].try_into().unwrap()),
+            blk_index,
+            data_offset: offset + 8,
+            tx_count,
+        });
+
+        offset += 8 + size;
+        reader.seek(SeekFrom::Start(offset))?;
+    }
+
+    Ok((headers, offset))
+}
+
+fn file_key(blk_index: u64) -> Vec<u8> {
+    let mut key = vec![FILE_PREFIX];
+    key.extend_from_slice(&(blk_index as u32).to_be_bytes());
+    key
+}
+
+fn header_key(block_hash: &sha256d::Hash) -> Vec<u8> {
+    let mut key = vec![HEADER_PREFIX];
+    key.extend_from_slice(block_hash.as_byte_array());
+    key
+}
+
+fn orphan_key(block_hash: &sha256d::Hash) -> Vec<u8> {
+    let mut key = vec![ORPHAN_PREFIX];
+    key.extend_from_slice(block_hash.as_byte_array());
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::Bitcoin;
+    use crate::store::MemoryStore;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::consensus::serialize;
+    use bitcoin::Network;
+    use std::io::Cursor;
+
+    fn child(parent: &bitcoin::Block, nonce: u32) -> bitcoin::Block {
+        let mut block = parent.clone();
+        block.header.prev_blockhash = parent.block_hash();
+        block.header.nonce = nonce;
+        block
+    }
+
+    fn append(blk: &mut Vec<u8>, block: &bitcoin::Block) {
+        let block = serialize(block);
+        blk.extend_from_slice(&[0xf9, 0xbe, 0xb4, 0xd9]);
+        blk.extend_from_slice(&(block.len() as u32).to_le_bytes());
+        blk.extend_from_slice(&block);
+    }
+
+    #[test]
+    fn test_scan_blk() {
+        let genesis = genesis_block(Network::Bitcoin);
+        let next = child(&genesis, 1);
+
+        let mut blk = vec![];
+        append(&mut blk, &genesis);
+        append(&mut blk, &next);
+        let complete = blk.len() as u64;
+
+        // Preallocated space...
+        let mut padded = blk.clone();
+        padded.extend_from_slice(&[0; 64]);
+        // ...or a block being written.
+        append(&mut blk, &child(&next, 2));
+        blk.truncate(blk.len() - 10);
+
+        let coin = Bitcoin.into();
+        let (headers, offset) = scan_blk(Cursor::new(padded), 3, 0, &coin).unwrap();
+        assert_eq!(offset, complete);
+        assert_eq!(headers.len(), 2);
+
+        let (headers, offset) = scan_blk(Cursor::new(blk.clone()), 3, 0, &coin).unwrap();
+        assert_eq!(offset, complete);
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers[1].block_hash, next.block_hash().to_raw_hash());
+        assert_eq!(headers[1].prev_hash, genesis.block_hash().to_raw_hash());
+        assert_eq!(headers[1].data_offset, complete / 2 + 8);
+        assert_eq!(headers[1].tx_count, 1);
+        assert_eq!(
+            ScannedHeader::from_kv(&header_key(&headers[1].block_hash), &headers[1].to_bytes()),
+            Some(headers[1].clone())
+        );
+
+        let (headers, _) = scan_blk(Cursor::new(blk), 3, offset, &coin).unwrap();
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn test_link_headers() {
+        let genesis = genesis_block(Network::Bitcoin);
+        let a = child(&genesis, 1);
+        let b = child(&genesis, 2);
+        let a2 = child(&a, 3);
+        let b2 = child(&b, 4);
+        let orphan = child(&child(&b, 5), 6);
+
+        let header = |block: &bitcoin::Block| ScannedHeader {
+            block_hash: block.block_hash().to_raw_hash(),
+            prev_hash: block.header.prev_blockhash.to_raw_hash(),
+            version: 1,
+            bits: block.header.bits.to_consensus(),
+            blk_index: 0,
+            data_offset: 0,
+            tx_count: 1,
+        };
+        let genesis_hash = genesis.block_hash().to_raw_hash();
+        let mut store = MemoryStore::new();
+        let mut orphans = HashMap::new();
+
+        // b2 comes before its parent, as blocks downloaded out of order do.
+        let mut wb = WriteBatch::new();
+        let linked = link_headers(
+            &mut store,
+            [&genesis, &a, &b2, &a2, &orphan].map(header).to_vec(),
+            &mut orphans,
+            genesis_hash,
+            &mut wb,
+        )
+        .unwrap();
+        store.write(wb).unwrap();
+        assert_eq!(linked.len(), 3);
+        assert_eq!(linked[2].entry.block_hash, a2.block_hash().to_raw_hash());
+        assert_eq!(linked[2].entry.height, 2);
+        assert!(linked[2].chain_work > linked[1].chain_work);
+        assert_eq!(orphans.len(), 2);
+
+        let mut wb = WriteBatch::new();
+        let linked = link_headers(
+            &mut store,
+            vec![header(&b)],
+            &mut orphans,
+            genesis_hash,
+            &mut wb,
+        )
+        .unwrap();
+        store.write(wb).unwrap();
+        assert_eq!(
+            linked
+                .iter()
+                .map(|linked| linked.entry.height)
+                .collect::<Vec<_>>(),
+            [1, 2]
+        );
+        assert_eq!(orphans.len(), 1);
+        assert!(load_orphans(&mut store)
+            .unwrap()
+            .contains_key(&child(&b, 5).block_hash().to_raw_hash()));
+
+        let b2 = get_linked(&mut store, &b2.block_hash().to_raw_hash())
+            .unwrap()
+            .unwrap();
+        assert_eq!(b2.entry.height, 2);
+        assert_eq!(b2.chain_work, linked[1].chain_work);
+    }
+}
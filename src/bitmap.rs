@@ -0,0 +1,214 @@
+//! Bitmap: block heights claimed by text inscriptions of `<height>.bitmap`,
+//! kept in their own keyspace.
+//!
+//! A claim is valid if its height is not above the block it is inscribed in.
+//! The first claim of a height by inscription number wins, cursed inscriptions
+//! do not claim. The owner of a bitmap follows its inscription.
+
+use serde::{Deserialize, Serialize};
+
+use crate::inscription::Inscription;
+use crate::inscription_id::InscriptionId;
+use crate::store::keyspace::{self, KeyspaceError};
+use crate::store::Store;
+
+const SUFFIX: &str = ".bitmap";
+
+/// `h<height, 8 bytes BE>` -> bitmap, as json.
+const BITMAP_PREFIX: u8 = b'h';
+/// `i<inscription id>` -> height it claimed, 8 bytes BE.
+const INSCRIPTION_PREFIX: u8 = b'i';
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bitmap {
+    pub height: u64,
+    pub inscription_id: InscriptionId,
+    pub number: i64,
+    /// Address holding the inscription, `None` if its output has none.
+    pub owner: Option<String>,
+}
+
+/// Height claimed by a text inscription, written without leading zeros nor
+/// whitespace.
+pub fn parse_claim(inscription: &Inscription) -> Option<u64> {
+    if !inscription
+        .content_type
+        .as_deref()?
+        .starts_with(b"text/plain")
+    {
+        return None;
+    }
+
+    let body = std::str::from_utf8(inscription.body.as_deref()?).ok()?;
+    let height = body.strip_suffix(SUFFIX)?;
+    if height.is_empty()
+        || !height.bytes().all(|b| b.is_ascii_digit())
+        || (height.len() > 1 && height.starts_with('0'))
+    {
+        return None;
+    }
+
+    height.parse().ok()
+}
+
+pub struct BitmapIndex<'a> {
+    store: &'a mut dyn Store,
+}
+
+impl<'a> BitmapIndex<'a> {
+    pub fn new(store: &'a mut dyn Store) -> BitmapIndex<'a> {
+        BitmapIndex { store }
+    }
+
+    /// Records the claim of a new inscription numbered `number`, inscribed at
+    /// `height` to `owner`. Returns the height it claimed, `None` if it is not
+    /// a valid claim or the height is already claimed.
+    pub fn inscribe(
+        &mut self,
+        inscription_id: InscriptionId,
+        number: i64,
+        inscription: &Inscription,
+        owner: Option<&str>,
+        height: u64,
+    ) -> Result<Option<u64>, KeyspaceError> {
+        let Some(claim) = parse_claim(inscription) else {
+            return Ok(None);
+        };
+        if number < 0 || claim > height || self.bitmap(claim)?.is_some() {
+            return Ok(None);
+        }
+
+        self.put_bitmap(&Bitmap {
+            height: claim,
+            inscription_id,
+            number,
+            owner: owner.map(str::to_string),
+        })?;
+        self.store.put(
+            &inscription_key(&inscription_id),
+            claim.to_be_bytes().as_slice(),
+        )?;
+        Ok(Some(claim))
+    }
+
+    /// Moves the bitmap of an inscription, if it claimed one, to `owner`.
+    pub fn transfer(
+        &mut self,
+        inscription_id: InscriptionId,
+        owner: Option<&str>,
+    ) -> Result<(), KeyspaceError> {
+        let Some(height) = self.claimed_by(inscription_id)? else {
+            return Ok(());
+        };
+        let mut bitmap = self
+            .bitmap(height)?
+            .ok_or_else(|| KeyspaceError::MalformedValue(bitmap_key(height)))?;
+        bitmap.owner = owner.map(str::to_string);
+        self.put_bitmap(&bitmap)
+    }
+
+    pub fn bitmap(&mut self, height: u64) -> Result<Option<Bitmap>, KeyspaceError> {
+        keyspace::get_json(self.store, &bitmap_key(height))
+    }
+
+    /// Inscription holding the bitmap of `height`.
+    pub fn inscription(&mut self, height: u64) -> Result<Option<InscriptionId>, KeyspaceError> {
+        Ok(self.bitmap(height)?.map(|bitmap| bitmap.inscription_id))
+    }
+
+    /// Height claimed by an inscription.
+    pub fn claimed_by(
+        &mut self,
+        inscription_id: InscriptionId,
+    ) -> Result<Option<u64>, KeyspaceError> {
+        keyspace::get(self.store, &inscription_key(&inscription_id), |value| {
+            value.try_into().ok().map(u64::from_be_bytes)
+        })
+    }
+
+    fn put_bitmap(&mut self, bitmap: &Bitmap) -> Result<(), KeyspaceError> {
+        keyspace::put_json(self.store, &bitmap_key(bitmap.height), bitmap)
+    }
+}
+
+fn bitmap_key(height: u64) -> Vec<u8> {
+    let mut key = vec![BITMAP_PREFIX];
+    key.extend_from_slice(&height.to_be_bytes());
+    key
+}
+
+fn inscription_key(inscription_id: &InscriptionId) -> Vec<u8> {
+    let mut key = vec![INSCRIPTION_PREFIX];
+    inscription_id.encode_to_vec(&mut key);
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{id, inscription};
+    use crate::store::MemoryStore;
+
+    #[test]
+    fn test_parse_claim() {
+        assert_eq!(parse_claim(&inscription("0.bitmap")), Some(0));
+        assert_eq!(parse_claim(&inscription("792435.bitmap")), Some(792435));
+        assert_eq!(parse_claim(&inscription("0792435.bitmap")), None);
+        assert_eq!(parse_claim(&inscription("792435.bitmap\n")), None);
+        assert_eq!(parse_claim(&inscription(" 792435.bitmap")), None);
+        assert_eq!(parse_claim(&inscription(".bitmap")), None);
+        assert_eq!(parse_claim(&inscription("-1.bitmap")), None);
+        assert_eq!(
+            parse_claim(&Inscription {
+                content_type: Some(b"application/json".to_vec()),
+                ..inscription("1.bitmap")
+            }),
+            None
+        );
+    }
+
+    #[test]
+    fn test_bitmap_index() {
+        let mut store = MemoryStore::new();
+        let mut index = BitmapIndex::new(&mut store);
+        let claim = inscription("100.bitmap");
+
+        // Above the height it is inscribed at, then cursed.
+        assert_eq!(
+            index.inscribe(id(0), 0, &claim, Some("alice"), 99).unwrap(),
+            None
+        );
+        assert_eq!(
+            index
+                .inscribe(id(1), -1, &claim, Some("alice"), 100)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            index
+                .inscribe(id(2), 1, &claim, Some("alice"), 100)
+                .unwrap(),
+            Some(100)
+        );
+        // First claim wins.
+        assert_eq!(
+            index.inscribe(id(3), 2, &claim, Some("bob"), 100).unwrap(),
+            None
+        );
+        assert_eq!(index.inscription(100).unwrap(), Some(id(2)));
+        assert_eq!(index.claimed_by(id(3)).unwrap(), None);
+
+        index.transfer(id(2), Some("bob")).unwrap();
+        index.transfer(id(3), Some("alice")).unwrap();
+        assert_eq!(
+            index.bitmap(100).unwrap(),
+            Some(Bitmap {
+                height: 100,
+                inscription_id: id(2),
+                number: 1,
+                owner: Some("bob".to_string()),
+            })
+        );
+        assert_eq!(index.claimed_by(id(2)).unwrap(), Some(100));
+    }
+}
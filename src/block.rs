@@ -2,16 +2,29 @@ use std::collections::{BTreeMap, HashMap};
 use std::num::TryFromIntError;
 use std::string::FromUtf8Error;
 
+use bitcoin::Txid;
 use bitcoincore_rpc::{Client, RpcApi};
 use log::{debug, info, trace};
-use rusty_leveldb::{Status, WriteBatch, DB};
 use thiserror::Error;
 
 use crate::{
-    bitcoin::proto::{tx::EvaluatedTx, Hashed},
-    height::Height,
-    inscription::{Curse, Inscription},
-    Flotsam, Origin,
+    bitcoin::proto::{script::ScriptPattern, tx::EvaluatedTx, Hashed},
+    bitmap::BitmapIndex,
+    brc20::{Brc20Ledger, Receiver},
+    chain::Chain,
+    doginal::{Envelope, PendingDoginal, PENDING_PREFIX},
+    drc20::{self, Drc20Entry, Drc20EventKind, Drc20Operation, Drc20Updater, TRANSFER_PREFIX},
+    inscription::{Curse, Inscription, TransactionInscription},
+    inscription_id::{InscriptionId, InscriptionIdError},
+    outpoint::OutPoint,
+    runes::{CommitOutputs, RuneIndex, RuneIndexError, RuneUpdater},
+    sat_point::{SatPoint, SatPointError},
+    sns::SnsIndex,
+    src20::{Src20Error, Src20Ledger},
+    store::{KeyspaceError, Store, StoreError, WriteBatch},
+    utxo_cache::UtxoCache,
+    varint::{self, VarintError},
+    Flotsam, Origin, ProtocolStores,
 };
 
 pub type Tx = Hashed<EvaluatedTx>;
@@ -21,28 +34,24 @@ const UNBOUND_INSCRIPTIONS: &str = "unbound_inscriptions";
 const NEXT_CURSED_ID_NUMBER: &str = "next_cursed_id_number";
 const NEXT_ID_NUMBER: &str = "next_id_number";
 const LOST_SATS: &str = "lost_sats";
-const INDEXED_HEIGHT: &str = "indexed_height";
+pub(crate) const INDEXED_HEIGHT: &str = "indexed_height";
 
 pub struct InscribeEntry<'a> {
     pub id: i64,
-    pub inscription_id: &'a String,
+    pub inscription_id: InscriptionId,
     pub inscription: &'a Inscription,
     // Not genesis_tx, first output_tx.
-    pub txid: &'a String,
-    pub vout: u32,
+    pub satpoint: SatPoint,
     pub to_address: &'a Option<String>,
     pub height: u64,
     pub timestamp: u32,
 }
 
 pub struct TransferEntry<'a> {
-    pub inscription_id: &'a String,
-    pub from_output: &'a String,
-    pub from_offset: u64,
+    pub inscription_id: InscriptionId,
+    pub old_satpoint: SatPoint,
+    pub new_satpoint: SatPoint,
     pub to: &'a Option<String>,
-    pub txid: &'a String,
-    pub vout: u32,
-    pub offset: u64,
     pub height: u64,
     pub timestamp: u32,
 }
@@ -54,63 +63,108 @@ pub type TransferUpdater = fn(TransferEntry);
 pub enum BlockUpdaterError {
     #[error("InscriptionUpdater error: `{0}`")]
     InscriptionUpdaterError(#[from] InscriptionUpdaterError),
+    #[error("Keyspace error: `{0}`")]
+    KeyspaceError(#[from] KeyspaceError),
+    #[error("Src20 error: `{0}`")]
+    Src20Error(#[from] Src20Error),
+    #[error("Rune index error: `{0}`")]
+    RuneIndexError(#[from] RuneIndexError),
+}
+
+/// Keyspaces a block is indexed into.
+pub struct Keyspaces<'a> {
+    pub status: &'a mut dyn Store,
+    pub output_value: &'a mut dyn Store,
+    pub id_inscription: &'a mut dyn Store,
+    pub inscription_output: &'a mut dyn Store,
+    pub output_inscription: &'a mut dyn Store,
+    pub protocols: &'a mut ProtocolStores,
+}
+
+impl Keyspaces<'_> {
+    fn reborrow(&mut self) -> Keyspaces<'_> {
+        Keyspaces {
+            status: &mut *self.status,
+            output_value: &mut *self.output_value,
+            id_inscription: &mut *self.id_inscription,
+            inscription_output: &mut *self.inscription_output,
+            output_inscription: &mut *self.output_inscription,
+            protocols: &mut *self.protocols,
+        }
+    }
+}
+
+/// Callbacks notified of what a block indexes.
+#[derive(Clone, Copy)]
+pub struct Updaters<'a> {
+    pub inscribe: &'a [InscribeUpdater],
+    pub transfer: &'a [TransferUpdater],
+    pub drc20: &'a [Drc20Updater],
+    pub rune: &'a [RuneUpdater],
 }
 
 pub struct BlockUpdater<'ordi> {
     pub height: u64,
     pub block: ProtoBlock,
-    pub btc_rpc_client: &'ordi Client,
-    pub status: &'ordi mut DB,
-    pub output_value: &'ordi mut DB,
-    pub id_inscription: &'ordi mut DB,
-    pub inscription_output: &'ordi mut DB,
-    pub output_inscription: &'ordi mut DB,
-    inscribe_updaters: &'ordi Vec<InscribeUpdater>,
-    transfer_updaters: &'ordi Vec<TransferUpdater>,
+    pub btc_rpc_client: Option<&'ordi Client>,
+    pub keyspaces: Keyspaces<'ordi>,
+    pub utxo_cache: &'ordi mut UtxoCache,
+    chain: &'ordi Chain,
+    updaters: Updaters<'ordi>,
 }
 
 impl<'ordi> BlockUpdater<'ordi> {
     pub fn new(
         height: u64,
         block: ProtoBlock,
-        btc_rpc_client: &'ordi Client,
-        status: &'ordi mut DB,
-        output_value: &'ordi mut DB,
-        id_inscription: &'ordi mut DB,
-        inscription_output: &'ordi mut DB,
-        output_inscription: &'ordi mut DB,
-        inscribe_updaters: &'ordi Vec<InscribeUpdater>,
-        transfer_updaters: &'ordi Vec<TransferUpdater>,
+        btc_rpc_client: Option<&'ordi Client>,
+        keyspaces: Keyspaces<'ordi>,
+        utxo_cache: &'ordi mut UtxoCache,
+        chain: &'ordi Chain,
+        updaters: Updaters<'ordi>,
     ) -> BlockUpdater<'ordi> {
         BlockUpdater {
             height,
             block,
             btc_rpc_client,
-            status,
-            output_value,
-            id_inscription,
-            inscription_output,
-            output_inscription,
-            inscribe_updaters,
-            transfer_updaters,
+            keyspaces,
+            utxo_cache,
+            chain,
+            updaters,
         }
     }
 
     pub fn index_transactions(&mut self) -> Result<(), BlockUpdaterError> {
         let start = std::time::Instant::now();
+        // Runes are applied in block order, unlike inscriptions.
+        if let Some(runes) = self.keyspaces.protocols.runes.as_mut() {
+            RuneIndex::new(runes).index_block(
+                self.height,
+                &self.block,
+                self.chain,
+                self.btc_rpc_client
+                    .map(|client| client as &dyn CommitOutputs),
+                self.updaters.rune,
+            )?;
+        }
+        if let Some(src20) = self.keyspaces.protocols.src20.as_mut() {
+            Src20Ledger::new(src20).index_block(
+                self.height,
+                &self.block,
+                self.chain,
+                self.btc_rpc_client,
+            )?;
+        }
+
         let mut inscription_updater = InscriptionUpdater::new(
             self.height,
-            self.block.header.value.timestamp,
             &self.block,
-            &self.btc_rpc_client,
-            &mut self.status,
-            &mut self.output_value,
-            &mut self.id_inscription,
-            &mut self.inscription_output,
-            &mut self.output_inscription,
-            self.inscribe_updaters,
-            self.transfer_updaters,
-        );
+            self.btc_rpc_client,
+            self.keyspaces.reborrow(),
+            self.utxo_cache,
+            self.chain,
+            self.updaters,
+        )?;
 
         for (_, tx) in self
             .block
@@ -141,22 +195,41 @@ pub enum InscriptionUpdaterError {
     BitcoinRpcError(#[from] bitcoincore_rpc::Error),
     #[error("Try from int error: `{0}`")]
     TryFromIntError(#[from] TryFromIntError),
-    #[error("Write leveldb error: `{0}`")]
-    WriteLevelDBError(#[from] Status),
+    #[error("Store error: `{0}`")]
+    StoreError(#[from] StoreError),
+    #[error("Inscription id error: `{0}`")]
+    InscriptionIdError(#[from] InscriptionIdError),
+    #[error("SatPoint error: `{0}`")]
+    SatPointError(#[from] SatPointError),
+    #[error("Varint error: `{0}`")]
+    VarintError(#[from] VarintError),
+    #[error("Malformed output_value of: `{0}`")]
+    MalformedOutputValue(OutPoint),
+    #[error("Malformed status value of: `{0}`")]
+    MalformedStatusValue(String),
+    #[error("Output not found: `{0}`")]
+    OutputNotFound(OutPoint),
+    #[error("Output value of `{0}` not found, output_value is incomplete and rpc is offline")]
+    OutputValueNotFound(OutPoint),
+    #[error("Keyspace error: `{0}`")]
+    KeyspaceError(#[from] KeyspaceError),
 }
 
 pub struct InscriptionUpdater<'block> {
     pub height: u64,
     pub timestamp: u32,
     pub block: &'block ProtoBlock,
-    pub btc_rpc_client: &'block Client,
-    pub status: &'block mut DB,
-    pub output_value: &'block mut DB,
-    pub id_inscription: &'block mut DB,
-    pub inscription_output: &'block mut DB,
-    pub output_inscription: &'block mut DB,
+    pub btc_rpc_client: Option<&'block Client>,
+    pub status: &'block mut dyn Store,
+    pub output_value: &'block mut dyn Store,
+    pub id_inscription: &'block mut dyn Store,
+    pub inscription_output: &'block mut dyn Store,
+    pub output_inscription: &'block mut dyn Store,
+    pub utxo_cache: &'block mut UtxoCache,
+    pub brc20: Option<Brc20Ledger<'block>>,
+    pub bitmap: Option<BitmapIndex<'block>>,
+    pub sns: Option<SnsIndex<'block>>,
     status_wb: WriteBatch,
-    output_value_wb: WriteBatch,
     id_inscription_wb: WriteBatch,
     inscription_output_wb: WriteBatch,
     output_inscription_wb: WriteBatch,
@@ -166,28 +239,34 @@ pub struct InscriptionUpdater<'block> {
     pub next_number: i64,
     pub next_cursed_number: i64,
     pub lost_sats: u64,
-    output_inscription_cache: HashMap<String, String>,
-    inscribe_updaters: &'block Vec<InscribeUpdater>,
-    transfer_updaters: &'block Vec<TransferUpdater>,
+    output_inscription_cache: HashMap<OutPoint, Vec<(InscriptionId, u64)>>,
+    chain: &'block Chain,
+    inscribe_updaters: &'block [InscribeUpdater],
+    transfer_updaters: &'block [TransferUpdater],
+    drc20_updaters: &'block [Drc20Updater],
 }
 
 impl<'block> InscriptionUpdater<'block> {
     pub fn new(
         height: u64,
-        timestamp: u32,
         block: &'block ProtoBlock,
-        btc_rpc_client: &'block Client,
-        status: &'block mut DB,
-        output_value: &'block mut DB,
-        id_inscription: &'block mut DB,
-        inscription_output: &'block mut DB,
-        output_inscription: &'block mut DB,
-        inscribe_updaters: &'block Vec<InscribeUpdater>,
-        transfer_updaters: &'block Vec<TransferUpdater>,
-    ) -> InscriptionUpdater<'block> {
+        btc_rpc_client: Option<&'block Client>,
+        keyspaces: Keyspaces<'block>,
+        utxo_cache: &'block mut UtxoCache,
+        chain: &'block Chain,
+        updaters: Updaters<'block>,
+    ) -> Result<InscriptionUpdater<'block>, InscriptionUpdaterError> {
+        let Keyspaces {
+            status,
+            output_value,
+            id_inscription,
+            inscription_output,
+            output_inscription,
+            protocols,
+        } = keyspaces;
         let mut iu = InscriptionUpdater {
             height,
-            timestamp,
+            timestamp: block.header.value.timestamp,
             block,
             btc_rpc_client,
             status,
@@ -195,40 +274,58 @@ impl<'block> InscriptionUpdater<'block> {
             id_inscription,
             inscription_output,
             output_inscription,
+            utxo_cache,
+            brc20: protocols
+                .brc20
+                .as_mut()
+                .map(|brc20| Brc20Ledger::new(brc20)),
+            bitmap: protocols
+                .bitmap
+                .as_mut()
+                .map(|bitmap| BitmapIndex::new(bitmap)),
+            sns: protocols.sns.as_mut().map(|sns| SnsIndex::new(sns)),
             status_wb: WriteBatch::new(),
-            output_value_wb: WriteBatch::new(),
             id_inscription_wb: WriteBatch::new(),
             inscription_output_wb: WriteBatch::new(),
             output_inscription_wb: WriteBatch::new(),
             flotsam: vec![],
-            reward: Height(height).subsidy(),
+            reward: chain.subsidy(height),
             unbound_inscriptions: 0,
             next_number: 0,
             next_cursed_number: 0,
             lost_sats: 0,
             output_inscription_cache: HashMap::new(),
-            inscribe_updaters,
-            transfer_updaters,
+            chain,
+            inscribe_updaters: updaters.inscribe,
+            transfer_updaters: updaters.transfer,
+            drc20_updaters: updaters.drc20,
         };
 
-        iu.unbound_inscriptions = iu.status_value_u64(UNBOUND_INSCRIPTIONS);
-        let mut next_cursed_number = iu.status_value_i64(NEXT_CURSED_ID_NUMBER);
+        iu.unbound_inscriptions = iu.status_value_u64(UNBOUND_INSCRIPTIONS)?;
+        let mut next_cursed_number = iu.status_value_i64(NEXT_CURSED_ID_NUMBER)?;
         if next_cursed_number == 0 {
             next_cursed_number -= 1;
         }
         iu.next_cursed_number = next_cursed_number;
-        iu.next_number = iu.status_value_i64(NEXT_ID_NUMBER);
-        iu.lost_sats = iu.status_value_u64(LOST_SATS);
+        iu.next_number = iu.status_value_i64(NEXT_ID_NUMBER)?;
+        iu.lost_sats = iu.status_value_u64(LOST_SATS)?;
 
-        iu
+        Ok(iu)
     }
 
     fn index_inscriptions_in_transaction(
         &mut self,
         tx: &Hashed<EvaluatedTx>,
     ) -> Result<(), InscriptionUpdaterError> {
-        debug!("Handle Tx: {}", tx.hash.to_string());
-        let mut new_inscriptions = Inscription::from_transaction(tx).into_iter().peekable();
+        debug!("Handle Tx: {}", tx.hash);
+        let txid = Txid::from_raw_hash(tx.hash);
+        let mut new_inscriptions = if self.chain.inscribes_in_script_sig() {
+            self.doginals_in_transaction(tx)?
+        } else {
+            Inscription::from_transaction(tx)
+        }
+        .into_iter()
+        .peekable();
         let mut floating_inscriptions = vec![];
         let mut inscribed_offsets = BTreeMap::new();
         let mut input_value = 0;
@@ -237,97 +334,50 @@ impl<'block> InscriptionUpdater<'block> {
         let mut wb = WriteBatch::new();
         for (input_index, tx_in) in tx.value.inputs.iter().enumerate() {
             if tx_in.outpoint.is_null() {
-                input_value += Height(self.height).subsidy();
+                input_value += self.chain.subsidy(self.height);
                 continue;
             }
 
-            let previous_output = format!("{}:{}", tx_in.outpoint.txid, tx_in.outpoint.index);
-            let inscriptions_str = match self.output_inscription_cache.get(&previous_output) {
+            let previous_output = OutPoint::from(&tx_in.outpoint);
+            let inscriptions = match self.output_inscription_cache.get(&previous_output) {
                 Some(inscriptions) => inscriptions.clone(),
                 None => {
-                    let value = String::from_utf8(
-                        self.output_inscription
-                            .get(previous_output.as_bytes())
-                            .unwrap_or_default(),
-                    )?;
-                    if value != "" {
+                    let inscriptions = self.stored_output_inscriptions(&previous_output)?;
+                    if !inscriptions.is_empty() {
                         self.output_inscription_cache
-                            .insert(previous_output.clone(), value.clone());
+                            .insert(previous_output, inscriptions.clone());
                     }
 
-                    value
+                    inscriptions
                 }
             };
-            if inscriptions_str != "" {
-                for (inscription_id, inscription_offset) in
-                    inscriptions_str
-                        .split("/")
-                        .skip(1)
-                        .map(|inscription_offset| {
-                            let inscription_offset =
-                                inscription_offset.split(":").collect::<Vec<&str>>();
-                            let inscription_id = inscription_offset[0];
-                            let offset = inscription_offset[1].parse::<u64>().unwrap();
-
-                            (inscription_id, offset)
-                        })
-                {
-                    let offset = input_value + inscription_offset;
-                    floating_inscriptions.push(Flotsam {
-                        inscription_id: inscription_id.to_string(),
-                        offset,
-                        origin: Origin::Old {
-                            old_output: previous_output.clone(),
-                            old_offset: inscription_offset,
-                        },
-                    });
+            for (inscription_id, inscription_offset) in inscriptions {
+                let offset = input_value + inscription_offset;
+                floating_inscriptions.push(Flotsam {
+                    inscription_id,
+                    offset,
+                    origin: Origin::Old {
+                        old_satpoint: SatPoint::new(previous_output, inscription_offset),
+                    },
+                });
 
-                    inscribed_offsets
-                        .entry(offset)
-                        .and_modify(|(_id, count)| *count += 1)
-                        .or_insert((inscription_id.to_string(), 0));
-                }
+                inscribed_offsets
+                    .entry(offset)
+                    .and_modify(|(_id, count)| *count += 1)
+                    .or_insert((inscription_id, 0));
             }
             let offset = input_value;
 
-            input_value += {
-                let k = format!(
-                    "{}:{}",
-                    tx_in.outpoint.txid.to_string(),
-                    tx_in.outpoint.index
-                );
-                match self.output_value.get(k.as_bytes()) {
-                    Some(value_vec) => {
-                        let value = u64::from_le_bytes(value_vec.try_into().unwrap());
-                        trace!(
-                            "Retrieve output_value:{}, output: {}. Raw is from leveldb.",
-                            value,
-                            k
-                        );
-                        value
-                    }
-                    None => {
-                        let previous_tx = self.btc_rpc_client.get_raw_transaction(
-                            &bitcoin::Txid::from_raw_hash(tx_in.outpoint.txid.clone()),
-                            None,
-                        )?;
-                        let value = previous_tx.output[tx_in.outpoint.index as usize].value;
-                        trace!(
-                            "Retrieve output_value:{}, output: {}. Raw is from bitcoin node.",
-                            value,
-                            k
-                        );
-                        value
-                    }
-                }
-            };
+            input_value += self.spent_output_value(previous_output)?;
 
             while let Some(new_inscription) = new_inscriptions.peek_mut() {
                 if new_inscription.tx_in_index != u32::try_from(input_index)? {
                     break;
                 }
 
-                let inscription_id = format!("{}i{}", tx.hash.to_string(), id_counter);
+                let inscription_id = new_inscription
+                    .inscription_id
+                    .unwrap_or_else(|| InscriptionId::new(txid, id_counter));
 
                 let curse = if new_inscription.tx_in_index != 0 {
                     Some(Curse::NotInFirstInput)
@@ -346,12 +396,9 @@ impl<'block> InscriptionUpdater<'block> {
                         .map(|(_id, count)| *count == 0)
                         .unwrap_or(false);
 
-                    let initial_inscription_is_cursed = inscribed_offsets
-                        .get(&offset)
-                        .and_then(|(inscription_id, _count)| {
-                            Some(self.status_value_i64(inscription_id.as_str()) != 0)
-                        })
-                        .unwrap();
+                    let (initial_inscription_id, _count) = inscribed_offsets[&offset];
+                    let initial_inscription_is_cursed =
+                        self.status_value_i64(initial_inscription_id.to_string().as_str())? != 0;
 
                     let cursed = !(first_reinscription && initial_inscription_is_cursed);
                     info!(
@@ -364,6 +411,9 @@ impl<'block> InscriptionUpdater<'block> {
                 } else {
                     curse.is_some()
                 };
+                // Vindicated after the jubilee, numbered like the others.
+                let vindicated = cursed && self.height >= self.chain.jubilee_height();
+                let cursed = cursed && !vindicated;
 
                 let unbound = input_value == 0 || new_inscription.tx_in_offset != 0;
 
@@ -376,6 +426,7 @@ impl<'block> InscriptionUpdater<'block> {
                     offset,
                     origin: Origin::New {
                         cursed,
+                        vindicated,
                         unbound,
                         inscription: new_inscription.inscription.clone(),
                     },
@@ -385,12 +436,7 @@ impl<'block> InscriptionUpdater<'block> {
                 id_counter += 1;
             }
 
-            let k = format!(
-                "{}:{}",
-                tx_in.outpoint.txid.to_string(),
-                tx_in.outpoint.index
-            );
-            wb.delete(k.as_bytes())
+            wb.delete(&previous_output.to_bytes())
         }
 
         //let total_output_value = tx.value.outputs.iter().map(|txout| txout.out.value).sum::<u64>();
@@ -412,8 +458,12 @@ impl<'block> InscriptionUpdater<'block> {
 
         let mut output_value = 0;
         for (vout, tx_out) in tx.value.outputs.iter().enumerate() {
-            let k = format!("{}:{}", tx.hash.to_string(), vout);
-            wb.put(k.as_bytes(), tx_out.out.value.to_le_bytes().as_slice());
+            let outpoint = OutPoint::new(txid, u32::try_from(vout)?);
+            wb.put(
+                &outpoint.to_bytes(),
+                tx_out.out.value.to_le_bytes().as_slice(),
+            );
+            self.utxo_cache.insert(outpoint, tx_out.out.value);
 
             let end = output_value + tx_out.out.value;
 
@@ -423,13 +473,16 @@ impl<'block> InscriptionUpdater<'block> {
                 }
 
                 let offset = flotsam.offset - output_value;
-                let vout = vout as u32;
                 let flotsam = inscriptions.next().unwrap();
+                let receiver = match (&tx_out.script.pattern, &tx_out.script.address) {
+                    (ScriptPattern::OpReturn(_), _) => Receiver::Burn,
+                    (_, Some(address)) => Receiver::Address(address),
+                    (_, None) => Receiver::Sender,
+                };
+                self.update_brc20_state(&flotsam, receiver)?;
                 self.update_inscription_state(
                     flotsam,
-                    tx.hash.to_string(),
-                    vout,
-                    offset,
+                    SatPoint::new(outpoint, offset),
                     &tx_out.script.address,
                 )?;
             }
@@ -437,85 +490,264 @@ impl<'block> InscriptionUpdater<'block> {
             output_value = end;
         }
 
-        self.output_value.write(wb, false)?;
+        self.output_value.write(wb)?;
 
         if is_coinbase {
             for flotsam in inscriptions {
-                let new_txid = null_hash();
                 let new_offset = self.lost_sats + flotsam.offset - output_value;
 
-                self.update_inscription_state(flotsam, new_txid, u32::MAX, new_offset, &None)?;
+                self.update_brc20_state(&flotsam, Receiver::Sender)?;
+                self.update_inscription_state(
+                    flotsam,
+                    SatPoint::new(OutPoint::null(), new_offset),
+                    &None,
+                )?;
             }
 
             self.lost_sats += self.reward - output_value;
         } else {
-            self.flotsam.extend(inscriptions.map(|flotsam| Flotsam {
-                offset: self.reward + flotsam.offset - output_value,
-                ..flotsam
-            }));
+            let inscriptions = inscriptions.collect::<Vec<Flotsam>>();
+            for flotsam in inscriptions.iter() {
+                // Spent as fee, brc20 transfers go back to the sender.
+                if let Origin::Old { .. } = flotsam.origin {
+                    self.update_brc20_state(flotsam, Receiver::Sender)?;
+                }
+            }
+
+            self.flotsam
+                .extend(inscriptions.into_iter().map(|flotsam| Flotsam {
+                    offset: self.reward + flotsam.offset - output_value,
+                    ..flotsam
+                }));
             self.reward += input_value - output_value;
         }
 
         Ok(())
     }
 
+    /// Applies the brc20 operation of a new inscription, or settles a
+    /// transfer inscription on its first move. Inscriptions vindicated by the
+    /// jubilee are still not brc20 operations, like the cursed ones before.
+    fn update_brc20_state(
+        &mut self,
+        flotsam: &Flotsam,
+        receiver: Receiver,
+    ) -> Result<(), InscriptionUpdaterError> {
+        let Some(brc20) = self.brc20.as_mut() else {
+            return Ok(());
+        };
+
+        match &flotsam.origin {
+            Origin::New {
+                cursed: false,
+                vindicated: false,
+                unbound: false,
+                inscription,
+            } => {
+                let owner = match receiver {
+                    Receiver::Address(address) => Some(address),
+                    _ => None,
+                };
+                brc20.inscribe(flotsam.inscription_id, inscription, owner, self.height)?
+            }
+            Origin::New { .. } => {}
+            Origin::Old { .. } => brc20.transfer(flotsam.inscription_id, receiver)?,
+        }
+
+        Ok(())
+    }
+
+    /// Doginals completed by `tx`. Those with parts left wait in `status` for
+    /// the transaction spending an output of `tx`, the n-th of them the n-th
+    /// output, so several can be pending after the same transaction.
+    fn doginals_in_transaction(
+        &mut self,
+        tx: &Tx,
+    ) -> Result<Vec<TransactionInscription>, InscriptionUpdaterError> {
+        let txid = Txid::from_raw_hash(tx.hash);
+        let mut doginals = vec![];
+        let mut started = 0;
+        let mut pending = 0;
+        for (input_index, tx_in) in tx.value.inputs.iter().enumerate() {
+            if tx_in.outpoint.is_null() {
+                continue;
+            }
+
+            let Some(envelope) = Envelope::from_script_sig(&tx_in.script_sig) else {
+                continue;
+            };
+
+            let doginal = match envelope {
+                Envelope::Start {
+                    parts,
+                    content_type,
+                    chunks,
+                } => {
+                    let mut doginal =
+                        PendingDoginal::new(InscriptionId::new(txid, started), parts, content_type);
+                    started += 1;
+                    if !doginal.push(chunks) {
+                        continue;
+                    }
+
+                    doginal
+                }
+                Envelope::Continuation { chunks } => {
+                    let key = pending_doginal_key(&OutPoint::from(&tx_in.outpoint));
+                    let Some(mut doginal) = self
+                        .status
+                        .get(&key)?
+                        .and_then(|bytes| PendingDoginal::from_bytes(&bytes))
+                    else {
+                        continue;
+                    };
+                    self.status.delete(&key)?;
+                    if !doginal.push(chunks) {
+                        continue;
+                    }
+
+                    doginal
+                }
+            };
+
+            if doginal.is_complete() {
+                doginals.push(TransactionInscription {
+                    inscription_id: Some(doginal.inscription_id),
+                    inscription: doginal.into_inscription(),
+                    tx_in_index: u32::try_from(input_index)?,
+                    tx_in_offset: 0,
+                });
+            } else {
+                debug!(
+                    "Doginal {} waits for {} more parts.",
+                    doginal.inscription_id, doginal.countdown
+                );
+                self.status.put(
+                    &pending_doginal_key(&OutPoint::new(txid, pending)),
+                    &doginal.to_bytes(),
+                )?;
+                pending += 1;
+            }
+        }
+
+        Ok(doginals)
+    }
+
+    /// Reports DRC-20 operations of new inscriptions, and transfers on the
+    /// first move of their inscription. Transfer inscriptions are kept in
+    /// `status` whether or not updaters are registered, so ones registered
+    /// later still see their transfers.
+    fn update_drc20_state(
+        &mut self,
+        inscription_id: InscriptionId,
+        inscription: Option<&Inscription>,
+        old_satpoint: Option<SatPoint>,
+        satpoint: SatPoint,
+        address: &Option<String>,
+    ) -> Result<(), InscriptionUpdaterError> {
+        let mut key = TRANSFER_PREFIX.as_bytes().to_vec();
+        key.extend_from_slice(&inscription_id.to_bytes());
+
+        let (kind, operation) = match inscription {
+            Some(inscription) => {
+                let Some(operation) = drc20::operation(inscription) else {
+                    return Ok(());
+                };
+                let kind = match operation {
+                    Drc20Operation::Deploy { .. } => Drc20EventKind::Deploy,
+                    Drc20Operation::Mint { .. } => Drc20EventKind::Mint,
+                    Drc20Operation::Transfer { .. } => {
+                        self.status
+                            .put(&key, inscription.body.as_deref().unwrap_or_default())?;
+                        Drc20EventKind::InscribeTransfer
+                    }
+                };
+
+                (kind, operation)
+            }
+            None => {
+                let Some(body) = self.status.get(&key)? else {
+                    return Ok(());
+                };
+                self.status.delete(&key)?;
+                let Some(operation) = drc20::operation_from_json(&body) else {
+                    return Ok(());
+                };
+
+                (Drc20EventKind::Transfer, operation)
+            }
+        };
+
+        for drc20_updater in self.drc20_updaters.iter() {
+            drc20_updater(Drc20Entry {
+                kind,
+                operation: &operation,
+                inscription_id,
+                old_satpoint,
+                satpoint,
+                to_address: address,
+                height: self.height,
+                timestamp: self.timestamp,
+            });
+        }
+
+        Ok(())
+    }
+
     pub fn update_inscription_state(
         &mut self,
         flotsam: Flotsam,
-        new_txid: String,
-        vout: u32,
-        offset: u64,
+        new_satpoint: SatPoint,
         address: &Option<String>,
     ) -> Result<(), InscriptionUpdaterError> {
         let unbound = match flotsam.origin {
-            Origin::Old {
-                old_output,
-                old_offset,
-            } => {
-                let inscription_value = self
-                    .output_inscription_cache
-                    .entry(old_output.clone())
-                    .or_insert_with(|| {
-                        String::from_utf8(
-                            self.output_inscription
-                                .get(old_output.as_bytes())
-                                .unwrap_or_default(),
-                        )
-                        .unwrap()
-                    });
-
-                let inscription_in_output_inscription =
-                    format!("/{}:{}", &flotsam.inscription_id, old_offset);
-                *inscription_value =
-                    inscription_value.replace(inscription_in_output_inscription.as_str(), "");
+            Origin::Old { old_satpoint } => {
+                self.output_inscriptions(old_satpoint.outpoint)?.retain(
+                    |(inscription_id, offset)| {
+                        !(*inscription_id == flotsam.inscription_id
+                            && *offset == old_satpoint.offset)
+                    },
+                );
 
                 for transfer_updater in self.transfer_updaters.iter() {
                     transfer_updater(TransferEntry {
-                        inscription_id: &flotsam.inscription_id,
-                        from_output: &old_output,
-                        from_offset: old_offset,
+                        inscription_id: flotsam.inscription_id,
+                        old_satpoint,
+                        new_satpoint,
                         to: address,
-                        txid: &new_txid,
-                        vout,
-                        offset,
                         height: self.height,
                         timestamp: self.timestamp,
                     })
                 }
 
+                if let Some(bitmap) = self.bitmap.as_mut() {
+                    bitmap.transfer(flotsam.inscription_id, address.as_deref())?;
+                }
+
+                if self.chain.inscribes_in_script_sig() {
+                    self.update_drc20_state(
+                        flotsam.inscription_id,
+                        None,
+                        Some(old_satpoint),
+                        new_satpoint,
+                        address,
+                    )?;
+                }
+
                 false
             }
             Origin::New {
                 cursed,
                 unbound,
                 inscription,
+                ..
             } => {
                 let number: i64 = if cursed {
                     let next_cursed_number = self.next_cursed_number;
                     self.next_cursed_number -= 1;
 
                     self.status.put(
-                        flotsam.inscription_id.as_bytes(),
+                        flotsam.inscription_id.to_string().as_bytes(),
                         next_cursed_number.to_le_bytes().as_slice(),
                     )?;
 
@@ -529,7 +761,7 @@ impl<'block> InscriptionUpdater<'block> {
 
                 self.id_inscription_wb.put(
                     number.to_le_bytes().as_slice(),
-                    flotsam.inscription_id.as_bytes(),
+                    &flotsam.inscription_id.to_bytes(),
                 );
 
                 // todo, not necessary: sat
@@ -539,54 +771,130 @@ impl<'block> InscriptionUpdater<'block> {
                 for inscribe_updater in self.inscribe_updaters.iter() {
                     inscribe_updater(InscribeEntry {
                         id: number,
-                        inscription_id: &flotsam.inscription_id,
+                        inscription_id: flotsam.inscription_id,
                         inscription: &inscription,
-                        txid: &new_txid,
-                        vout,
+                        satpoint: new_satpoint,
                         to_address: address,
                         height: self.height,
                         timestamp: self.timestamp,
                     });
                 }
 
+                if let Some(bitmap) = self.bitmap.as_mut() {
+                    bitmap.inscribe(
+                        flotsam.inscription_id,
+                        number,
+                        &inscription,
+                        address.as_deref(),
+                        self.height,
+                    )?;
+                }
+
+                if let Some(sns) = self.sns.as_mut() {
+                    sns.inscribe(flotsam.inscription_id, number, &inscription, self.height)?;
+                }
+
+                if self.chain.inscribes_in_script_sig() {
+                    self.update_drc20_state(
+                        flotsam.inscription_id,
+                        Some(&inscription),
+                        None,
+                        new_satpoint,
+                        address,
+                    )?;
+                }
+
                 unbound
             }
         };
 
-        let real_new_txid = if unbound {
-            let new_unbound_satpoint =
-                format!("{}:{}", unbound_outpoint(), self.unbound_inscriptions);
+        let new_satpoint = if unbound {
+            let unbound_satpoint = SatPoint::new(OutPoint::unbound(), self.unbound_inscriptions);
             self.unbound_inscriptions += 1;
 
-            new_unbound_satpoint
+            unbound_satpoint
         } else {
-            format!("{}:{}", new_txid, vout)
+            new_satpoint
         };
 
-        let previous_data = self
-            .output_inscription_cache
-            .entry(real_new_txid.clone())
-            .or_insert_with(|| {
-                String::from_utf8(
-                    self.output_inscription
-                        .get(real_new_txid.as_bytes())
-                        .unwrap_or_default(),
-                )
-                .unwrap()
-            });
-        *previous_data = format!(
-            "{}/{}:{}",
-            previous_data,
-            flotsam.inscription_id.as_str(),
-            offset
-        );
+        self.output_inscriptions(new_satpoint.outpoint)?
+            .push((flotsam.inscription_id, new_satpoint.offset));
 
         self.inscription_output_wb
-            .put(flotsam.inscription_id.as_bytes(), real_new_txid.as_bytes());
+            .put(&flotsam.inscription_id.to_bytes(), &new_satpoint.to_bytes());
 
         Ok(())
     }
 
+    /// Value of an output spent by this block, from the cache, `output_value`
+    /// or the bitcoin node.
+    fn spent_output_value(&mut self, outpoint: OutPoint) -> Result<u64, InscriptionUpdaterError> {
+        if let Some(value) = self.utxo_cache.take(&outpoint) {
+            trace!(
+                "Retrieve output_value:{}, output: {}. Raw is from cache.",
+                value,
+                outpoint
+            );
+            return Ok(value);
+        }
+
+        match self.output_value.get(&outpoint.to_bytes())? {
+            Some(value_vec) => {
+                let value = u64::from_le_bytes(
+                    value_vec
+                        .try_into()
+                        .map_err(|_| InscriptionUpdaterError::MalformedOutputValue(outpoint))?,
+                );
+                trace!(
+                    "Retrieve output_value:{}, output: {}. Raw is from leveldb.",
+                    value,
+                    outpoint
+                );
+                Ok(value)
+            }
+            None => {
+                let btc_rpc_client = self
+                    .btc_rpc_client
+                    .ok_or(InscriptionUpdaterError::OutputValueNotFound(outpoint))?;
+                let previous_tx = btc_rpc_client.get_raw_transaction(&outpoint.txid, None)?;
+                let value = previous_tx
+                    .output
+                    .get(outpoint.vout as usize)
+                    .ok_or(InscriptionUpdaterError::OutputNotFound(outpoint))?
+                    .value;
+                trace!(
+                    "Retrieve output_value:{}, output: {}. Raw is from bitcoin node.",
+                    value,
+                    outpoint
+                );
+                Ok(value)
+            }
+        }
+    }
+
+    /// Inscriptions in `outpoint` with their offsets, loaded into the cache for updating.
+    fn output_inscriptions(
+        &mut self,
+        outpoint: OutPoint,
+    ) -> Result<&mut Vec<(InscriptionId, u64)>, InscriptionUpdaterError> {
+        if !self.output_inscription_cache.contains_key(&outpoint) {
+            let inscriptions = self.stored_output_inscriptions(&outpoint)?;
+            self.output_inscription_cache.insert(outpoint, inscriptions);
+        }
+
+        Ok(self.output_inscription_cache.get_mut(&outpoint).unwrap())
+    }
+
+    fn stored_output_inscriptions(
+        &mut self,
+        outpoint: &OutPoint,
+    ) -> Result<Vec<(InscriptionId, u64)>, InscriptionUpdaterError> {
+        match self.output_inscription.get(&outpoint.to_bytes())? {
+            Some(value) => decode_output_inscriptions(&value),
+            None => Ok(vec![]),
+        }
+    }
+
     pub fn flush_update(mut self) -> Result<(), InscriptionUpdaterError> {
         self.write_status_wb_str_to_u64(UNBOUND_INSCRIPTIONS, self.unbound_inscriptions);
         self.write_status_wb_str_to_i64(NEXT_ID_NUMBER, self.next_number);
@@ -594,35 +902,30 @@ impl<'block> InscriptionUpdater<'block> {
         self.write_status_wb_str_to_u64(LOST_SATS, self.lost_sats);
         self.write_status_wb_str_to_u64(INDEXED_HEIGHT, self.height);
 
-        if self.output_value_wb.count() > 0 {
-            self.output_value.write(self.output_value_wb, false)?;
-        }
-
         if self.id_inscription_wb.count() > 0 {
-            self.id_inscription.write(self.id_inscription_wb, false)?;
+            self.id_inscription.write(self.id_inscription_wb)?;
         }
 
         if self.inscription_output_wb.count() > 0 {
-            self.inscription_output
-                .write(self.inscription_output_wb, false)?;
+            self.inscription_output.write(self.inscription_output_wb)?;
         }
 
-        for (output, inscriptions) in self.output_inscription_cache {
-            if inscriptions != "" {
+        for (outpoint, inscriptions) in self.output_inscription_cache {
+            let k = outpoint.to_bytes();
+            if !inscriptions.is_empty() {
                 self.output_inscription_wb
-                    .put(output.as_bytes(), inscriptions.as_bytes());
+                    .put(&k, &encode_output_inscriptions(&inscriptions));
             } else {
-                self.output_inscription_wb.delete(output.as_bytes());
+                self.output_inscription_wb.delete(&k);
             }
         }
 
         if self.output_inscription_wb.count() > 0 {
-            self.output_inscription
-                .write(self.output_inscription_wb, false)?;
+            self.output_inscription.write(self.output_inscription_wb)?;
         }
 
         if self.status_wb.count() > 0 {
-            self.status.write(self.status_wb, false)?;
+            self.status.write(self.status_wb)?;
         }
 
         Ok(())
@@ -639,40 +942,468 @@ impl<'block> InscriptionUpdater<'block> {
     }
 
     #[inline]
-    fn status_value_u64(&mut self, k: &str) -> u64 {
-        u64::from_le_bytes(
-            self.status
-                .get(k.as_bytes())
-                .unwrap_or(vec![0; 8])
-                .try_into()
-                .unwrap(),
-        )
+    fn status_value_u64(&mut self, k: &str) -> Result<u64, InscriptionUpdaterError> {
+        Ok(u64::from_le_bytes(self.status_value(k)?))
     }
 
     #[inline]
-    fn status_value_i64(&mut self, k: &str) -> i64 {
-        i64::from_le_bytes(
-            self.status
-                .get(k.as_bytes())
-                .unwrap_or(vec![0; 8])
+    fn status_value_i64(&mut self, k: &str) -> Result<i64, InscriptionUpdaterError> {
+        Ok(i64::from_le_bytes(self.status_value(k)?))
+    }
+
+    /// 8 bytes stored in status at `k`, zeroes if there are none.
+    fn status_value(&mut self, k: &str) -> Result<[u8; 8], InscriptionUpdaterError> {
+        match self.status.get(k.as_bytes())? {
+            Some(value) => value
                 .try_into()
-                .unwrap(),
-        )
+                .map_err(|_| InscriptionUpdaterError::MalformedStatusValue(k.to_string())),
+            None => Ok([0; 8]),
+        }
     }
 }
 
-#[inline]
-fn unbound_outpoint() -> String {
-    "0000000000000000000000000000000000000000000000000000000000000000:0".to_string()
+/// Status key of a doginal waiting for the transaction spending `outpoint`.
+fn pending_doginal_key(outpoint: &OutPoint) -> Vec<u8> {
+    let mut key = PENDING_PREFIX.as_bytes().to_vec();
+    key.extend_from_slice(&outpoint.to_bytes());
+    key
 }
 
-#[inline]
-#[allow(dead_code)]
-fn null_outpoint() -> String {
-    "0000000000000000000000000000000000000000000000000000000000000000:4294967295".to_string()
+/// Decodes `output_inscription` values, a list of inscription ids each followed by its varint offset.
+pub(crate) fn decode_output_inscriptions(
+    mut bytes: &[u8],
+) -> Result<Vec<(InscriptionId, u64)>, InscriptionUpdaterError> {
+    let mut inscriptions = vec![];
+    while !bytes.is_empty() {
+        let (inscription_id, len) = InscriptionId::decode(bytes)?;
+        let (offset, offset_len) = varint::decode(&bytes[len..])?;
+        inscriptions.push((inscription_id, u64::try_from(offset)?));
+        bytes = &bytes[len + offset_len..];
+    }
+
+    Ok(inscriptions)
+}
+
+pub(crate) fn encode_output_inscriptions(inscriptions: &[(InscriptionId, u64)]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(inscriptions.len() * (32 + 5 + 5));
+    for (inscription_id, offset) in inscriptions {
+        inscription_id.encode_to_vec(&mut bytes);
+        varint::encode_to_vec((*offset).into(), &mut bytes);
+    }
+
+    bytes
 }
 
-#[inline]
-fn null_hash() -> String {
-    "0000000000000000000000000000000000000000000000000000000000000000".to_string()
+#[cfg(test)]
+mod tests {
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::blockdata::script::{Builder, PushBytesBuf};
+    use bitcoin::hashes::Hash;
+    use bitcoin::opcodes::all;
+    use bitcoin::{
+        absolute::LockTime, ScriptBuf, Sequence, Transaction, TxIn, TxOut, WPubkeyHash, Witness,
+    };
+
+    use super::*;
+    use crate::fixtures::inscription;
+    use crate::store::{BufferedStore, MemoryStore};
+
+    #[test]
+    fn test_output_inscriptions_round_trip() {
+        let inscriptions = vec![
+            (
+                "6fb976ab49dcec017f1e201e84395983204ae1a7c2abf7ced0a85d692e442799i0"
+                    .parse()
+                    .unwrap(),
+                0,
+            ),
+            (
+                "6fb976ab49dcec017f1e201e84395983204ae1a7c2abf7ced0a85d692e442799i3"
+                    .parse()
+                    .unwrap(),
+                330_000,
+            ),
+        ];
+
+        let bytes = encode_output_inscriptions(&inscriptions);
+        assert_eq!(decode_output_inscriptions(&bytes).unwrap(), inscriptions);
+        assert!(decode_output_inscriptions(&bytes[..40]).is_err());
+    }
+
+    /// Keyspaces of an indexer, in memory.
+    struct Stores {
+        status: MemoryStore,
+        output_value: MemoryStore,
+        id_inscription: MemoryStore,
+        inscription_output: MemoryStore,
+        output_inscription: MemoryStore,
+        utxo_cache: UtxoCache,
+        protocols: ProtocolStores,
+        inscribe_updaters: Vec<InscribeUpdater>,
+        transfer_updaters: Vec<TransferUpdater>,
+        drc20_updaters: Vec<Drc20Updater>,
+    }
+
+    impl Default for Stores {
+        fn default() -> Stores {
+            Stores {
+                status: MemoryStore::new(),
+                output_value: MemoryStore::new(),
+                id_inscription: MemoryStore::new(),
+                inscription_output: MemoryStore::new(),
+                output_inscription: MemoryStore::new(),
+                utxo_cache: UtxoCache::new(0),
+                protocols: ProtocolStores::default(),
+                inscribe_updaters: vec![],
+                transfer_updaters: vec![],
+                drc20_updaters: vec![],
+            }
+        }
+    }
+
+    impl Stores {
+        fn with_brc20() -> Stores {
+            Stores {
+                protocols: ProtocolStores {
+                    brc20: Some(BufferedStore::new(Box::new(MemoryStore::new()))),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }
+        }
+
+        fn inscription_updater<'a>(
+            &'a mut self,
+            height: u64,
+            block: &'a ProtoBlock,
+            chain: &'a Chain,
+        ) -> InscriptionUpdater<'a> {
+            self.try_inscription_updater(height, block, chain).unwrap()
+        }
+
+        fn try_inscription_updater<'a>(
+            &'a mut self,
+            height: u64,
+            block: &'a ProtoBlock,
+            chain: &'a Chain,
+        ) -> Result<InscriptionUpdater<'a>, InscriptionUpdaterError> {
+            InscriptionUpdater::new(
+                height,
+                block,
+                None,
+                Keyspaces {
+                    status: &mut self.status,
+                    output_value: &mut self.output_value,
+                    id_inscription: &mut self.id_inscription,
+                    inscription_output: &mut self.inscription_output,
+                    output_inscription: &mut self.output_inscription,
+                    protocols: &mut self.protocols,
+                },
+                &mut self.utxo_cache,
+                chain,
+                Updaters {
+                    inscribe: &self.inscribe_updaters,
+                    transfer: &self.transfer_updaters,
+                    drc20: &self.drc20_updaters,
+                    rune: &[],
+                },
+            )
+        }
+    }
+
+    /// Pushes of a doginal of two parts, with only the first chunk.
+    fn doginal_start(chunk: &[u8]) -> ScriptBuf {
+        Builder::new()
+            .push_slice(b"ord")
+            .push_int(2)
+            .push_slice(b"text/plain")
+            .push_int(1)
+            .push_slice(<&bitcoin::script::PushBytes>::try_from(chunk).unwrap())
+            .push_slice([0x30; 71])
+            .into_script()
+    }
+
+    #[test]
+    fn test_pending_doginals_of_one_transaction() {
+        let tx = Transaction {
+            version: 1,
+            lock_time: LockTime::ZERO,
+            input: [b"he", b"wo"]
+                .iter()
+                .enumerate()
+                .map(|(vout, chunk)| TxIn {
+                    previous_output: bitcoin::OutPoint::new(
+                        Txid::from_byte_array([1; 32]),
+                        vout as u32,
+                    ),
+                    script_sig: doginal_start(*chunk),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: vec![
+                TxOut {
+                    value: 100_000,
+                    script_pubkey: ScriptBuf::new(),
+                };
+                2
+            ],
+        };
+        let mut block = genesis_block(bitcoin::Network::Bitcoin);
+        block.txdata = vec![tx];
+        let chain = Chain::Dogecoin;
+        let block = ProtoBlock::from_bitcoin(block, chain.coin().address_encoding());
+
+        let mut stores = Stores::default();
+        let mut inscription_updater =
+            stores.inscription_updater(chain.first_inscription_height(), &block, &chain);
+        assert!(inscription_updater
+            .doginals_in_transaction(&block.txs[0])
+            .unwrap()
+            .is_empty());
+
+        // Each waits for the spend of its own output.
+        let txid = Txid::from_raw_hash(block.txs[0].hash);
+        for (vout, body) in [(0, b"he"), (1, b"wo")] {
+            let pending = stores
+                .status
+                .get(&pending_doginal_key(&OutPoint::new(txid, vout)))
+                .unwrap()
+                .and_then(|bytes| PendingDoginal::from_bytes(&bytes))
+                .unwrap();
+            assert_eq!(pending.inscription_id, InscriptionId::new(txid, vout));
+            assert_eq!(pending.body, body);
+        }
+    }
+
+    #[test]
+    fn test_malformed_status_value() {
+        let chain = Chain::Regtest;
+        let block = regtest_block();
+        let mut stores = Stores::default();
+        stores
+            .status
+            .put(NEXT_ID_NUMBER.as_bytes(), &[1, 0, 0])
+            .unwrap();
+        assert!(matches!(
+            stores.try_inscription_updater(chain.first_inscription_height(), &block, &chain),
+            Err(InscriptionUpdaterError::MalformedStatusValue(k)) if k == NEXT_ID_NUMBER
+        ));
+    }
+
+    #[test]
+    fn test_drc20_transfer_kept_without_updaters() {
+        let block = ProtoBlock::from_bitcoin(
+            genesis_block(bitcoin::Network::Bitcoin),
+            Chain::Dogecoin.coin().address_encoding(),
+        );
+        let chain = Chain::Dogecoin;
+        let mut stores = Stores::default();
+        let mut inscription_updater =
+            stores.inscription_updater(chain.first_inscription_height(), &block, &chain);
+
+        let inscription_id = InscriptionId::new(Txid::from_byte_array([1; 32]), 0);
+        let satpoint = SatPoint::new(OutPoint::new(Txid::from_byte_array([2; 32]), 0), 0);
+        let body = r#"{"p":"drc-20","op":"transfer","tick":"dogi","amt":"5"}"#;
+        inscription_updater
+            .update_drc20_state(
+                inscription_id,
+                Some(&inscription(body)),
+                None,
+                satpoint,
+                &None,
+            )
+            .unwrap();
+
+        let mut key = TRANSFER_PREFIX.as_bytes().to_vec();
+        key.extend_from_slice(&inscription_id.to_bytes());
+        assert_eq!(
+            stores.status.get(&key).unwrap(),
+            Some(body.as_bytes().to_vec())
+        );
+    }
+
+    fn p2wpkh(n: u8) -> ScriptBuf {
+        ScriptBuf::new_v0_p2wpkh(&WPubkeyHash::from_byte_array([n; 20]))
+    }
+
+    /// Witness of a script path spend revealing a text inscription.
+    fn reveal(body: &str) -> Witness {
+        let script = Builder::new()
+            .push_opcode(all::OP_PUSHBYTES_0)
+            .push_opcode(all::OP_IF)
+            .push_slice(b"ord")
+            .push_slice([1])
+            .push_slice(b"text/plain;charset=utf-8")
+            .push_opcode(all::OP_PUSHBYTES_0)
+            .push_slice(PushBytesBuf::try_from(body.as_bytes().to_vec()).unwrap())
+            .push_opcode(all::OP_ENDIF)
+            .into_script();
+        Witness::from_slice(&[vec![0x30; 64], script.into_bytes(), vec![0xc0; 33]])
+    }
+
+    /// Regtest transaction spending `inputs`, with an output of each script and value.
+    fn spend(inputs: Vec<(OutPoint, Witness)>, outputs: Vec<(ScriptBuf, u64)>) -> Tx {
+        let tx = Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: inputs
+                .into_iter()
+                .map(|(outpoint, witness)| TxIn {
+                    previous_output: bitcoin::OutPoint::new(outpoint.txid, outpoint.vout),
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness,
+                })
+                .collect(),
+            output: outputs
+                .into_iter()
+                .map(|(script_pubkey, value)| TxOut {
+                    value,
+                    script_pubkey,
+                })
+                .collect(),
+        };
+        Tx::double_sha256(EvaluatedTx::from_transaction(
+            tx,
+            Chain::Regtest.coin().address_encoding(),
+        ))
+    }
+
+    /// An output of 10_000 sats nothing is inscribed on.
+    fn fund(inscription_updater: &mut InscriptionUpdater, n: u8) -> OutPoint {
+        let outpoint = OutPoint::new(Txid::from_byte_array([n; 32]), 0);
+        inscription_updater
+            .output_value
+            .put(&outpoint.to_bytes(), 10_000u64.to_le_bytes().as_slice())
+            .unwrap();
+        outpoint
+    }
+
+    /// Empty regtest block, inscription updaters only read its timestamp.
+    fn regtest_block() -> ProtoBlock {
+        ProtoBlock::from_bitcoin(
+            genesis_block(bitcoin::Network::Regtest),
+            Chain::Regtest.coin().address_encoding(),
+        )
+    }
+
+    const DEPLOY: &str =
+        r#"{"p":"brc-20","op":"deploy","tick":"ordi","max":"1000","lim":"1000","dec":"0"}"#;
+
+    #[test]
+    fn test_brc20_ignores_vindicated_inscriptions() {
+        let chain = Chain::Regtest;
+        let block = regtest_block();
+        for (reveal_input, deployed) in [(0, true), (1, false)] {
+            let mut stores = Stores::with_brc20();
+            let mut inscription_updater =
+                stores.inscription_updater(chain.jubilee_height(), &block, &chain);
+            let mut inputs = vec![
+                (fund(&mut inscription_updater, 1), Witness::new()),
+                (fund(&mut inscription_updater, 2), Witness::new()),
+            ];
+            inputs[reveal_input].1 = reveal(DEPLOY);
+            inscription_updater
+                .index_inscriptions_in_transaction(&spend(inputs, vec![(p2wpkh(1), 20_000)]))
+                .unwrap();
+            // Numbered like any other after the jubilee.
+            assert_eq!(inscription_updater.next_number, 1);
+            drop(inscription_updater);
+
+            let mut brc20 = Brc20Ledger::new(stores.protocols.brc20.as_mut().unwrap());
+            assert_eq!(brc20.token("ordi").unwrap().is_some(), deployed);
+        }
+    }
+
+    #[test]
+    fn test_drc20_only_on_chains_inscribing_in_script_sig() {
+        let chain = Chain::Regtest;
+        let block = regtest_block();
+        let mut stores = Stores::default();
+        let mut inscription_updater =
+            stores.inscription_updater(chain.first_inscription_height(), &block, &chain);
+
+        let body = r#"{"p":"drc-20","op":"transfer","tick":"dogi","amt":"5"}"#;
+        let input = (fund(&mut inscription_updater, 1), reveal(body));
+        inscription_updater
+            .index_inscriptions_in_transaction(&spend(vec![input], vec![(p2wpkh(1), 10_000)]))
+            .unwrap();
+        drop(inscription_updater);
+
+        let mut transfers = 0;
+        stores
+            .status
+            .scan(TRANSFER_PREFIX.as_bytes(), &mut |key, _| {
+                transfers += key.starts_with(TRANSFER_PREFIX.as_bytes()) as usize;
+                false
+            })
+            .unwrap();
+        assert_eq!(transfers, 0);
+    }
+
+    /// Deploys `ordi`, mints 1000 and inscribes a transfer of 10 to the
+    /// address of `p2wpkh(1)`. Returns the address and the output holding the
+    /// transfer inscription.
+    fn inscribe_brc20_transfer(inscription_updater: &mut InscriptionUpdater) -> (String, OutPoint) {
+        let mint = r#"{"p":"brc-20","op":"mint","tick":"ordi","amt":"1000"}"#;
+        let transfer = r#"{"p":"brc-20","op":"transfer","tick":"ordi","amt":"10"}"#;
+        let mut last = None;
+        for (n, body) in [(1, DEPLOY), (2, mint), (3, transfer)] {
+            let input = (fund(inscription_updater, n), reveal(body));
+            let tx = spend(vec![input], vec![(p2wpkh(1), 10_000)]);
+            inscription_updater
+                .index_inscriptions_in_transaction(&tx)
+                .unwrap();
+            last = Some(tx);
+        }
+
+        let tx = last.unwrap();
+        let address = tx.value.outputs[0].script.address.clone().unwrap();
+        (address, OutPoint::new(Txid::from_raw_hash(tx.hash), 0))
+    }
+
+    #[test]
+    fn test_brc20_transfer_receivers() {
+        let chain = Chain::Regtest;
+        let block = regtest_block();
+        let op_return = ScriptBuf::new_op_return(&[0u8; 4]);
+        let bare = Builder::new().push_opcode(all::OP_PUSHNUM_1).into_script();
+        // Receiving output, none when spent as fee, and balances of sender
+        // and receiver with the burned amount after.
+        let cases = [
+            (Some(p2wpkh(2)), 990, 10, 0),
+            (Some(op_return), 990, 0, 10),
+            (Some(bare), 1000, 0, 0),
+            (None, 1000, 0, 0),
+        ];
+        for (output, sent, received, burned) in cases {
+            let mut stores = Stores::with_brc20();
+            let mut inscription_updater =
+                stores.inscription_updater(chain.first_inscription_height(), &block, &chain);
+            let (sender, outpoint) = inscribe_brc20_transfer(&mut inscription_updater);
+            let outputs = output.into_iter().map(|script| (script, 10_000)).collect();
+            let tx = spend(vec![(outpoint, Witness::new())], outputs);
+            inscription_updater
+                .index_inscriptions_in_transaction(&tx)
+                .unwrap();
+            let receiver = tx
+                .value
+                .outputs
+                .first()
+                .and_then(|o| o.script.address.clone());
+            drop(inscription_updater);
+
+            let mut brc20 = Brc20Ledger::new(stores.protocols.brc20.as_mut().unwrap());
+            let balance = brc20.balance("ordi", &sender).unwrap();
+            assert_eq!((balance.available, balance.transferable), (sent, 0));
+            if let Some(receiver) = receiver {
+                assert_eq!(
+                    brc20.balance("ordi", &receiver).unwrap().available,
+                    received
+                );
+            }
+            assert_eq!(brc20.token("ordi").unwrap().unwrap().burned, burned);
+        }
+    }
 }
@@ -0,0 +1,553 @@
+//! BRC-20 ledger: tokens deployed and minted by inscriptions, and the
+//! balances they leave, kept in their own keyspace.
+//!
+//! A transfer inscription moves `amt` of its inscriber's available balance to
+//! the transferable one. On its first move the amount goes to the receiver,
+//! back to the sender when spent as fee, or is burned when sent to OP_RETURN.
+//! Later moves of the inscription do nothing.
+
+use serde::{Deserialize, Serialize};
+
+use crate::inscription::Inscription;
+use crate::inscription_id::InscriptionId;
+use crate::store::keyspace::{self, KeyspaceError};
+use crate::store::Store;
+
+const PROTOCOL: &str = "brc-20";
+const TICK_LEN: usize = 4;
+const MAX_DECIMALS: u8 = 18;
+
+/// `t<tick>` -> token, as json.
+const TOKEN_PREFIX: u8 = b't';
+/// `b<address>\0<tick>` -> available and transferable balances, 16 bytes LE each.
+const BALANCE_PREFIX: u8 = b'b';
+/// `p<inscription id>` -> transfer inscription which did not move yet, as json.
+const TRANSFER_PREFIX: u8 = b'p';
+
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Brc20Operation {
+    Deploy {
+        tick: String,
+        max: String,
+        lim: Option<String>,
+        dec: Option<String>,
+    },
+    Mint {
+        tick: String,
+        amt: String,
+    },
+    Transfer {
+        tick: String,
+        amt: String,
+    },
+}
+
+impl Brc20Operation {
+    /// Operation in a text or json inscription, with numbers written as strings
+    /// and a 4 byte tick.
+    pub fn from_inscription(inscription: &Inscription) -> Option<Brc20Operation> {
+        let operation = Brc20Operation::from_protocol_inscription(inscription, PROTOCOL)?;
+        (operation.tick().len() == TICK_LEN).then_some(operation)
+    }
+
+    /// Operation of another protocol with the same operations, e.g. DRC-20,
+    /// in a text or json inscription. Amounts and ticks are kept as written.
+    pub(crate) fn from_protocol_inscription(
+        inscription: &Inscription,
+        protocol: &str,
+    ) -> Option<Brc20Operation> {
+        let content_type = inscription.content_type.as_deref()?;
+        if !content_type.starts_with(b"text/plain")
+            && !content_type.starts_with(b"application/json")
+        {
+            return None;
+        }
+
+        Brc20Operation::from_protocol_json(inscription.body.as_deref()?, protocol)
+    }
+
+    pub(crate) fn from_protocol_json(body: &[u8], protocol: &str) -> Option<Brc20Operation> {
+        let value = serde_json::from_slice::<serde_json::Value>(body).ok()?;
+        if value.get("p")?.as_str()? != protocol {
+            return None;
+        }
+
+        serde_json::from_value(value).ok()
+    }
+
+    pub fn tick(&self) -> &str {
+        match self {
+            Brc20Operation::Deploy { tick, .. }
+            | Brc20Operation::Mint { tick, .. }
+            | Brc20Operation::Transfer { tick, .. } => tick,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Token {
+    /// Lowercase tick, ticks are case insensitive.
+    pub tick: String,
+    pub inscription_id: InscriptionId,
+    pub deployer: String,
+    pub height: u64,
+    pub decimals: u8,
+    /// Amounts in units of `10^-decimals`.
+    pub max: u128,
+    pub limit: u128,
+    pub minted: u128,
+    pub burned: u128,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Balance {
+    pub available: u128,
+    /// Held by transfer inscriptions which did not move yet.
+    pub transferable: u128,
+}
+
+impl Balance {
+    pub fn overall(&self) -> u128 {
+        self.available + self.transferable
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = self.available.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&self.transferable.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Balance> {
+        Some(Balance {
+            available: u128::from_le_bytes(bytes.get(..16)?.try_into().ok()?),
+            transferable: u128::from_le_bytes(bytes.get(16..32)?.try_into().ok()?),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct PendingTransfer {
+    tick: String,
+    amount: u128,
+    from: String,
+}
+
+/// Where a transfer inscription went on its first move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Receiver<'a> {
+    Address(&'a str),
+    /// Spent as fee or sent to an output without address, the amount goes
+    /// back to the sender.
+    Sender,
+    /// Sent to OP_RETURN, the amount is burned.
+    Burn,
+}
+
+pub struct Brc20Ledger<'a> {
+    store: &'a mut dyn Store,
+}
+
+impl<'a> Brc20Ledger<'a> {
+    pub fn new(store: &'a mut dyn Store) -> Brc20Ledger<'a> {
+        Brc20Ledger { store }
+    }
+
+    /// Applies the operation of a new inscription, whose first owner is
+    /// `owner`. Invalid operations are ignored.
+    pub fn inscribe(
+        &mut self,
+        inscription_id: InscriptionId,
+        inscription: &Inscription,
+        owner: Option<&str>,
+        height: u64,
+    ) -> Result<(), KeyspaceError> {
+        let (Some(operation), Some(owner)) = (Brc20Operation::from_inscription(inscription), owner)
+        else {
+            return Ok(());
+        };
+        let tick = operation.tick().to_lowercase();
+
+        match operation {
+            Brc20Operation::Deploy { max, lim, dec, .. } => {
+                if self.token(&tick)?.is_some() {
+                    return Ok(());
+                }
+
+                let decimals = match dec {
+                    Some(dec) if dec.bytes().all(|b| b.is_ascii_digit()) => {
+                        match dec.parse::<u8>() {
+                            Ok(decimals) if decimals <= MAX_DECIMALS => decimals,
+                            _ => return Ok(()),
+                        }
+                    }
+                    Some(_) => return Ok(()),
+                    None => MAX_DECIMALS,
+                };
+                let Some(max) = parse_amount(&max, decimals).filter(|max| *max > 0) else {
+                    return Ok(());
+                };
+                let limit = match lim {
+                    Some(lim) => match parse_amount(&lim, decimals).filter(|lim| *lim > 0) {
+                        Some(limit) => limit,
+                        None => return Ok(()),
+                    },
+                    None => max,
+                };
+
+                self.put_token(&Token {
+                    tick,
+                    inscription_id,
+                    deployer: owner.to_string(),
+                    height,
+                    decimals,
+                    max,
+                    limit,
+                    minted: 0,
+                    burned: 0,
+                })
+            }
+            Brc20Operation::Mint { amt, .. } => {
+                let Some(mut token) = self.token(&tick)? else {
+                    return Ok(());
+                };
+                let amount = match parse_amount(&amt, token.decimals) {
+                    Some(amount) if amount > 0 && amount <= token.limit => amount,
+                    _ => return Ok(()),
+                };
+                // The last mint gets what is left.
+                let amount = amount.min(token.max - token.minted);
+                if amount == 0 {
+                    return Ok(());
+                }
+
+                token.minted += amount;
+                self.put_token(&token)?;
+                let mut balance = self.balance(&tick, owner)?;
+                balance.available += amount;
+                self.put_balance(&tick, owner, balance)
+            }
+            Brc20Operation::Transfer { amt, .. } => {
+                let Some(token) = self.token(&tick)? else {
+                    return Ok(());
+                };
+                let mut balance = self.balance(&tick, owner)?;
+                let amount = match parse_amount(&amt, token.decimals) {
+                    Some(amount) if amount > 0 && amount <= balance.available => amount,
+                    _ => return Ok(()),
+                };
+
+                balance.available -= amount;
+                balance.transferable += amount;
+                self.put_balance(&tick, owner, balance)?;
+                let transfer = PendingTransfer {
+                    tick,
+                    amount,
+                    from: owner.to_string(),
+                };
+                keyspace::put_json(self.store, &transfer_key(&inscription_id), &transfer)
+            }
+        }
+    }
+
+    /// Settles the transfer inscription `inscription_id` on its first move.
+    pub fn transfer(
+        &mut self,
+        inscription_id: InscriptionId,
+        receiver: Receiver,
+    ) -> Result<(), KeyspaceError> {
+        let key = transfer_key(&inscription_id);
+        let Some(transfer) = keyspace::get_json::<PendingTransfer>(self.store, &key)? else {
+            return Ok(());
+        };
+        self.store.delete(&key)?;
+
+        let mut balance = self.balance(&transfer.tick, &transfer.from)?;
+        balance.transferable -= transfer.amount;
+        if receiver == Receiver::Sender {
+            balance.available += transfer.amount;
+        }
+        self.put_balance(&transfer.tick, &transfer.from, balance)?;
+
+        match receiver {
+            Receiver::Address(to) => {
+                let mut balance = self.balance(&transfer.tick, to)?;
+                balance.available += transfer.amount;
+                self.put_balance(&transfer.tick, to, balance)
+            }
+            Receiver::Sender => Ok(()),
+            Receiver::Burn => {
+                let mut token = self
+                    .token(&transfer.tick)?
+                    .ok_or_else(|| KeyspaceError::MalformedValue(token_key(&transfer.tick)))?;
+                token.burned += transfer.amount;
+                self.put_token(&token)
+            }
+        }
+    }
+
+    /// Deployed token of a tick, in any case.
+    pub fn token(&mut self, tick: &str) -> Result<Option<Token>, KeyspaceError> {
+        keyspace::get_json(self.store, &token_key(&tick.to_lowercase()))
+    }
+
+    /// Deployed tokens, by tick.
+    pub fn tokens(&mut self) -> Result<Vec<Token>, KeyspaceError> {
+        keyspace::scan_json(self.store, &[TOKEN_PREFIX])
+    }
+
+    pub fn balance(&mut self, tick: &str, address: &str) -> Result<Balance, KeyspaceError> {
+        let key = balance_key(address, &tick.to_lowercase());
+        Ok(keyspace::get(self.store, &key, Balance::from_bytes)?.unwrap_or_default())
+    }
+
+    /// Balances of an address, by tick.
+    pub fn balances(&mut self, address: &str) -> Result<Vec<(String, Balance)>, KeyspaceError> {
+        let prefix = balance_key(address, "");
+        let mut balances = vec![];
+        let mut malformed = None;
+        self.store.scan(&prefix, &mut |key, value| {
+            let Some(tick) = key.strip_prefix(prefix.as_slice()) else {
+                return false;
+            };
+            match Balance::from_bytes(value) {
+                Some(balance) => {
+                    balances.push((String::from_utf8_lossy(tick).into_owned(), balance))
+                }
+                None => malformed = Some(key.to_vec()),
+            }
+            malformed.is_none()
+        })?;
+
+        match malformed {
+            Some(key) => Err(KeyspaceError::MalformedValue(key)),
+            None => Ok(balances),
+        }
+    }
+
+    fn put_token(&mut self, token: &Token) -> Result<(), KeyspaceError> {
+        keyspace::put_json(self.store, &token_key(&token.tick), token)
+    }
+
+    fn put_balance(
+        &mut self,
+        tick: &str,
+        address: &str,
+        balance: Balance,
+    ) -> Result<(), KeyspaceError> {
+        keyspace::put(self.store, &balance_key(address, tick), &balance.to_bytes())
+    }
+}
+
+fn token_key(tick: &str) -> Vec<u8> {
+    let mut key = vec![TOKEN_PREFIX];
+    key.extend_from_slice(tick.as_bytes());
+    key
+}
+
+fn balance_key(address: &str, tick: &str) -> Vec<u8> {
+    let mut key = vec![BALANCE_PREFIX];
+    key.extend_from_slice(address.as_bytes());
+    key.push(0);
+    key.extend_from_slice(tick.as_bytes());
+    key
+}
+
+fn transfer_key(inscription_id: &InscriptionId) -> Vec<u8> {
+    let mut key = vec![TRANSFER_PREFIX];
+    inscription_id.encode_to_vec(&mut key);
+    key
+}
+
+/// Decimal amount in units of `10^-decimals`, at most `u64::MAX` whole tokens.
+pub(crate) fn parse_amount(amount: &str, decimals: u8) -> Option<u128> {
+    let (integer, fraction) = amount.split_once('.').unwrap_or((amount, "0"));
+    let is_number = |digits: &str| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit());
+    if !is_number(integer) || !is_number(fraction) {
+        return None;
+    }
+
+    let fraction = fraction.trim_end_matches('0');
+    let decimals = decimals as usize;
+    if fraction.len() > decimals {
+        return None;
+    }
+
+    let scale = 10u128.pow(decimals as u32);
+    let fraction = match fraction.is_empty() {
+        true => 0,
+        false => fraction.parse::<u128>().ok()? * 10u128.pow((decimals - fraction.len()) as u32),
+    };
+    let amount = integer
+        .parse::<u128>()
+        .ok()?
+        .checked_mul(scale)?
+        .checked_add(fraction)?;
+    (amount <= u64::MAX as u128 * scale).then_some(amount)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{id, inscription};
+    use crate::store::MemoryStore;
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(parse_amount("1", 18), Some(10u128.pow(18)));
+        assert_eq!(parse_amount("1.5", 1), Some(15));
+        assert_eq!(parse_amount("1.50", 1), Some(15));
+        assert_eq!(parse_amount("1.55", 1), None);
+        assert_eq!(parse_amount("1.", 1), None);
+        assert_eq!(parse_amount(".5", 1), None);
+        assert_eq!(parse_amount("-1", 1), None);
+        assert_eq!(parse_amount(" 1", 1), None);
+        assert_eq!(
+            parse_amount("18446744073709551615", 0),
+            Some(u64::MAX as u128)
+        );
+        assert_eq!(parse_amount("18446744073709551616", 0), None);
+    }
+
+    #[test]
+    fn test_operation() {
+        assert_eq!(
+            Brc20Operation::from_inscription(&inscription(
+                r#"{"p":"brc-20","op":"mint","tick":"ORDI","amt":"1000"}"#
+            )),
+            Some(Brc20Operation::Mint {
+                tick: "ORDI".into(),
+                amt: "1000".into(),
+            })
+        );
+        // Numbers must be strings, ticks 4 bytes.
+        assert_eq!(
+            Brc20Operation::from_inscription(&inscription(
+                r#"{"p":"brc-20","op":"mint","tick":"ordi","amt":1000}"#
+            )),
+            None
+        );
+        assert_eq!(
+            Brc20Operation::from_inscription(&inscription(
+                r#"{"p":"brc-20","op":"mint","tick":"ord","amt":"1000"}"#
+            )),
+            None
+        );
+    }
+
+    #[test]
+    fn test_ledger() {
+        let mut store = MemoryStore::new();
+        let mut ledger = Brc20Ledger::new(&mut store);
+        let (alice, bob) = ("alice", "bob");
+        let inscribe = |ledger: &mut Brc20Ledger, index, body: &str, owner| {
+            ledger
+                .inscribe(id(index), &inscription(body), Some(owner), 1)
+                .unwrap()
+        };
+
+        inscribe(
+            &mut ledger,
+            0,
+            r#"{"p":"brc-20","op":"deploy","tick":"ordi","max":"100","lim":"60","dec":"0"}"#,
+            alice,
+        );
+        // Deployed once, whatever the case.
+        inscribe(
+            &mut ledger,
+            1,
+            r#"{"p":"brc-20","op":"deploy","tick":"ORDI","max":"1000"}"#,
+            bob,
+        );
+        assert_eq!(ledger.token("ORDI").unwrap().unwrap().max, 100);
+
+        // Over the limit, then capped by the supply left.
+        inscribe(
+            &mut ledger,
+            2,
+            r#"{"p":"brc-20","op":"mint","tick":"ordi","amt":"61"}"#,
+            alice,
+        );
+        inscribe(
+            &mut ledger,
+            3,
+            r#"{"p":"brc-20","op":"mint","tick":"ordi","amt":"60"}"#,
+            alice,
+        );
+        inscribe(
+            &mut ledger,
+            4,
+            r#"{"p":"brc-20","op":"mint","tick":"Ordi","amt":"60"}"#,
+            bob,
+        );
+        inscribe(
+            &mut ledger,
+            5,
+            r#"{"p":"brc-20","op":"mint","tick":"ordi","amt":"1"}"#,
+            bob,
+        );
+        assert_eq!(ledger.token("ordi").unwrap().unwrap().minted, 100);
+        assert_eq!(ledger.balance("ordi", alice).unwrap().available, 60);
+        assert_eq!(ledger.balance("ordi", bob).unwrap().available, 40);
+
+        // More than available is not transferable.
+        inscribe(
+            &mut ledger,
+            6,
+            r#"{"p":"brc-20","op":"transfer","tick":"ordi","amt":"61"}"#,
+            alice,
+        );
+        inscribe(
+            &mut ledger,
+            7,
+            r#"{"p":"brc-20","op":"transfer","tick":"ordi","amt":"10"}"#,
+            alice,
+        );
+        inscribe(
+            &mut ledger,
+            8,
+            r#"{"p":"brc-20","op":"transfer","tick":"ordi","amt":"20"}"#,
+            alice,
+        );
+        inscribe(
+            &mut ledger,
+            9,
+            r#"{"p":"brc-20","op":"transfer","tick":"ordi","amt":"5"}"#,
+            bob,
+        );
+        ledger.transfer(id(6), Receiver::Address(bob)).unwrap();
+        assert_eq!(
+            ledger.balance("ordi", alice).unwrap(),
+            Balance {
+                available: 30,
+                transferable: 30,
+            }
+        );
+
+        ledger.transfer(id(7), Receiver::Address(bob)).unwrap();
+        // Settled on the first move only.
+        ledger.transfer(id(7), Receiver::Address(bob)).unwrap();
+        ledger.transfer(id(8), Receiver::Sender).unwrap();
+        ledger.transfer(id(9), Receiver::Burn).unwrap();
+        assert_eq!(
+            ledger.balance("ordi", alice).unwrap(),
+            Balance {
+                available: 50,
+                transferable: 0,
+            }
+        );
+        assert_eq!(ledger.balance("ordi", bob).unwrap().overall(), 45);
+        assert_eq!(ledger.token("ordi").unwrap().unwrap().burned, 5);
+        assert_eq!(
+            ledger.balances(bob).unwrap(),
+            vec![(
+                "ordi".to_string(),
+                Balance {
+                    available: 45,
+                    transferable: 0,
+                }
+            )]
+        );
+        assert_eq!(ledger.tokens().unwrap().len(), 1);
+    }
+}
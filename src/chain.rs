@@ -0,0 +1,272 @@
+//! Networks ordi indexes: where their blocks are, how they are read, and the
+//! heights the ordinals protocol changes at on each.
+
+use std::str::FromStr;
+
+use ::bitcoin::blockdata::constants::SUBSIDY_HALVING_INTERVAL;
+use ::bitcoin::constants::COIN_VALUE;
+use ::bitcoin::hashes::hex::FromHex;
+use thiserror::Error;
+
+use crate::bitcoin::{
+    Bitcoin, BitcoinRegtest, BitcoinSignet, BitcoinTestnet3, BitcoinTestnet4, CoinType, Dogecoin,
+    Litecoin,
+};
+use crate::height::Height;
+
+#[derive(Error, Debug)]
+pub enum ChainError {
+    #[error("Unknown network: `{0}`")]
+    UnknownNetwork(String),
+    #[error("Signet challenge is not hex: `{0}`")]
+    InvalidSignetChallenge(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Chain {
+    Mainnet,
+    Testnet3,
+    Testnet4,
+    /// Default signet, or a custom one with its block challenge script.
+    Signet(Option<Vec<u8>>),
+    Regtest,
+    /// Dogecoin mainnet, inscribed with doginals.
+    Dogecoin,
+    /// Litecoin mainnet, inscribed like Bitcoin.
+    Litecoin,
+    /// Any other coin, with its protocol parameters.
+    Custom(Box<ChainParams>),
+}
+
+impl Chain {
+    /// `mainnet` (default), `testnet3`, `testnet4`, `signet`, `regtest`, `dogecoin` or `litecoin`.
+    /// `signet_challenge` is the hex challenge of a custom signet.
+    pub fn from_name(name: &str, signet_challenge: &str) -> Result<Chain, ChainError> {
+        let chain = name.parse::<Chain>()?;
+        if signet_challenge.is_empty() {
+            return Ok(chain);
+        }
+
+        match (chain, Vec::from_hex(signet_challenge)) {
+            (Chain::Signet(_), Ok(challenge)) => Ok(Chain::Signet(Some(challenge))),
+            _ => Err(ChainError::InvalidSignetChallenge(
+                signet_challenge.to_string(),
+            )),
+        }
+    }
+
+    pub fn coin(&self) -> CoinType {
+        match self {
+            Chain::Mainnet => Bitcoin.into(),
+            Chain::Testnet3 => BitcoinTestnet3.into(),
+            Chain::Testnet4 => BitcoinTestnet4.into(),
+            Chain::Signet(None) => BitcoinSignet::default().into(),
+            Chain::Signet(Some(challenge)) => BitcoinSignet {
+                challenge: challenge.clone(),
+            }
+            .into(),
+            Chain::Regtest => BitcoinRegtest.into(),
+            Chain::Dogecoin => Dogecoin.into(),
+            Chain::Litecoin => Litecoin.into(),
+            Chain::Custom(params) => params.coin.clone(),
+        }
+    }
+
+    /// Subfolder of bitcoind's data directory holding the network's blocks.
+    pub fn data_subfolder(&self) -> &str {
+        match self {
+            Chain::Mainnet | Chain::Dogecoin | Chain::Litecoin => "",
+            Chain::Testnet3 => "testnet3",
+            Chain::Testnet4 => "testnet4",
+            Chain::Signet(_) => "signet",
+            Chain::Regtest => "regtest",
+            Chain::Custom(params) => &params.data_subfolder,
+        }
+    }
+
+    /// Height of the block with the first inscription, indexing starts there.
+    pub fn first_inscription_height(&self) -> u64 {
+        match self {
+            Chain::Mainnet => 767430,
+            Chain::Testnet3 => 2413343,
+            Chain::Signet(None) => 112402,
+            Chain::Dogecoin => 4609723,
+            Chain::Litecoin => 2424429,
+            Chain::Custom(params) => params.first_inscription_height,
+            Chain::Testnet4 | Chain::Signet(Some(_)) | Chain::Regtest => 0,
+        }
+    }
+
+    /// From this height on, inscriptions that would have been cursed are
+    /// numbered like the others.
+    pub fn jubilee_height(&self) -> u64 {
+        match self {
+            Chain::Mainnet => 824544,
+            Chain::Testnet3 => 2544192,
+            Chain::Signet(None) => 175392,
+            Chain::Regtest => 110,
+            Chain::Custom(params) => params.jubilee_height,
+            Chain::Testnet4 | Chain::Signet(Some(_)) | Chain::Dogecoin | Chain::Litecoin => 0,
+        }
+    }
+
+    /// Height runes are indexed from, `None` on chains without runes.
+    pub fn first_rune_height(&self) -> Option<u64> {
+        match self {
+            Chain::Mainnet => Some(840000),
+            Chain::Testnet3 => Some(2520000),
+            Chain::Testnet4 | Chain::Signet(_) | Chain::Regtest => Some(0),
+            Chain::Dogecoin | Chain::Litecoin => None,
+            Chain::Custom(params) => params.first_rune_height,
+        }
+    }
+
+    /// Whether inscriptions are doginals pushed in scriptSigs rather than
+    /// envelopes in taproot witnesses.
+    pub fn inscribes_in_script_sig(&self) -> bool {
+        match self {
+            Chain::Dogecoin => true,
+            Chain::Custom(params) => params.inscribes_in_script_sig,
+            _ => false,
+        }
+    }
+
+    /// Block subsidy, Dogecoin's is fixed at 10,000 coins since block 600,000,
+    /// Litecoin's halves every 840,000 blocks.
+    pub fn subsidy(&self, height: u64) -> u64 {
+        match self {
+            Chain::Dogecoin => 10_000 * COIN_VALUE,
+            Chain::Litecoin => halved(50 * COIN_VALUE, height / 840_000),
+            Chain::Custom(params) => params.subsidy(height),
+            _ => Height(height).subsidy(),
+        }
+    }
+}
+
+/// Protocol parameters of a coin ordi has no preset for, to index forks and
+/// sidechains.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainParams {
+    pub coin: CoinType,
+    /// Subfolder of the node's data directory holding the blocks.
+    pub data_subfolder: String,
+    /// Height of the block with the first inscription, indexing starts there.
+    pub first_inscription_height: u64,
+    /// From this height on, inscriptions that would have been cursed are
+    /// numbered like the others.
+    pub jubilee_height: u64,
+    /// Height runes are indexed from, `None` if the coin has no runes.
+    pub first_rune_height: Option<u64>,
+    /// Subsidy of the first blocks, before any halving.
+    pub initial_subsidy: u64,
+    /// Blocks between halvings of the subsidy, `None` if it never halves.
+    pub halving_interval: Option<u64>,
+    /// Whether inscriptions are doginals pushed in scriptSigs rather than
+    /// envelopes in taproot witnesses.
+    pub inscribes_in_script_sig: bool,
+}
+
+impl ChainParams {
+    /// Parameters of `coin` with Bitcoin's subsidy schedule, indexed from its
+    /// genesis block.
+    pub fn new(coin: impl Into<CoinType>) -> ChainParams {
+        ChainParams {
+            coin: coin.into(),
+            data_subfolder: String::new(),
+            first_inscription_height: 0,
+            jubilee_height: 0,
+            first_rune_height: None,
+            initial_subsidy: 50 * COIN_VALUE,
+            halving_interval: Some(SUBSIDY_HALVING_INTERVAL as u64),
+            inscribes_in_script_sig: false,
+        }
+    }
+
+    pub fn subsidy(&self, height: u64) -> u64 {
+        match self.halving_interval {
+            Some(interval) => halved(self.initial_subsidy, height / interval.max(1)),
+            None => self.initial_subsidy,
+        }
+    }
+}
+
+fn halved(subsidy: u64, halvings: u64) -> u64 {
+    u32::try_from(halvings)
+        .ok()
+        .and_then(|halvings| subsidy.checked_shr(halvings))
+        .unwrap_or(0)
+}
+
+impl From<ChainParams> for Chain {
+    fn from(params: ChainParams) -> Chain {
+        Chain::Custom(Box::new(params))
+    }
+}
+
+impl FromStr for Chain {
+    type Err = ChainError;
+
+    fn from_str(s: &str) -> Result<Chain, ChainError> {
+        match s {
+            "" | "mainnet" => Ok(Chain::Mainnet),
+            "testnet" | "testnet3" => Ok(Chain::Testnet3),
+            "testnet4" => Ok(Chain::Testnet4),
+            "signet" => Ok(Chain::Signet(None)),
+            "regtest" => Ok(Chain::Regtest),
+            "dogecoin" => Ok(Chain::Dogecoin),
+            "litecoin" => Ok(Chain::Litecoin),
+            _ => Err(ChainError::UnknownNetwork(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain_magic() {
+        let magic = |chain: Chain| chain.coin().magic.to_le_bytes();
+        assert_eq!(magic(Chain::Mainnet), [0xf9, 0xbe, 0xb4, 0xd9]);
+        assert_eq!(magic(Chain::Testnet3), [0x0b, 0x11, 0x09, 0x07]);
+        assert_eq!(magic(Chain::Testnet4), [0x1c, 0x16, 0x3f, 0x28]);
+        assert_eq!(magic(Chain::Signet(None)), [0x0a, 0x03, 0xcf, 0x40]);
+        assert_eq!(magic(Chain::Regtest), [0xfa, 0xbf, 0xb5, 0xda]);
+        assert_eq!(magic(Chain::Dogecoin), [0xc0, 0xc0, 0xc0, 0xc0]);
+        assert_eq!(magic(Chain::Litecoin), [0xfb, 0xc0, 0xb6, 0xdb]);
+
+        let custom = Chain::from_name("signet", "51").unwrap();
+        assert_eq!(custom, Chain::Signet(Some(vec![0x51])));
+        assert_ne!(magic(custom), magic(Chain::Signet(None)));
+    }
+
+    #[test]
+    fn test_custom_chain() {
+        let chain = Chain::from(ChainParams {
+            first_inscription_height: 100,
+            initial_subsidy: 1000,
+            halving_interval: Some(10),
+            ..ChainParams::new(Dogecoin)
+        });
+        assert_eq!(chain.coin().magic, 0xc0c0c0c0);
+        assert_eq!(chain.first_inscription_height(), 100);
+        assert_eq!(chain.first_rune_height(), None);
+        assert_eq!(chain.subsidy(9), 1000);
+        assert_eq!(chain.subsidy(25), 250);
+        assert_eq!(chain.subsidy(10 * 64), 0);
+
+        let bitcoin = Chain::from(ChainParams::new(Bitcoin));
+        for height in [0, 209_999, 210_000, 6_930_000, 7_000_000] {
+            assert_eq!(bitcoin.subsidy(height), Chain::Mainnet.subsidy(height));
+        }
+    }
+
+    #[test]
+    fn test_chain_from_name() {
+        assert_eq!(Chain::from_name("", "").unwrap(), Chain::Mainnet);
+        assert_eq!(Chain::from_name("testnet", "").unwrap(), Chain::Testnet3);
+        assert!(Chain::from_name("regtest", "51").is_err());
+        assert!(Chain::from_name("signet", "5g").is_err());
+        assert!(Chain::from_name("bitcoin", "").is_err());
+    }
+}
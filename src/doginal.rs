@@ -0,0 +1,250 @@
+//! Doginals, inscriptions of Dogecoin, pushed in the scriptSig of P2SH spends.
+//!
+//! The envelope is `"ord" <parts> <content type>` followed by `<countdown> <chunk>`
+//! pairs, the countdown going from `parts - 1` down to 0. Content too large for
+//! one transaction goes on in the scriptSig of the next one, which spends the
+//! first output of the previous: its pairs carry on the countdown. The
+//! inscription is identified by the first transaction, and created by the one
+//! pushing the last chunk. A transaction leaving several doginals pending goes
+//! on with the n-th of them from its n-th output.
+
+use bitcoin::blockdata::opcodes;
+use bitcoin::blockdata::script::Instruction;
+use bitcoin::Script;
+
+use crate::inscription::Inscription;
+use crate::inscription_id::InscriptionId;
+use crate::varint;
+
+const PROTOCOL_ID: [u8; 3] = *b"ord";
+/// Status key prefix of doginals waiting for their next part, by the outpoint
+/// that part spends.
+pub(crate) const PENDING_PREFIX: &str = "doginal_";
+
+/// Doginal pushes found in a scriptSig.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Envelope {
+    /// First part, with the number of chunks and the content type.
+    Start {
+        parts: u64,
+        content_type: Vec<u8>,
+        chunks: Vec<(u64, Vec<u8>)>,
+    },
+    /// Chunks following those of an earlier transaction.
+    Continuation { chunks: Vec<(u64, Vec<u8>)> },
+}
+
+impl Envelope {
+    pub fn from_script_sig(script_sig: &[u8]) -> Option<Envelope> {
+        let mut pushes = vec![];
+        for instruction in Script::from_bytes(script_sig).instructions() {
+            pushes.push(match instruction.ok()? {
+                Instruction::PushBytes(bytes) => bytes.as_bytes().to_vec(),
+                Instruction::Op(op) => {
+                    let op = op.to_u8();
+                    let first = opcodes::all::OP_PUSHNUM_1.to_u8();
+                    let last = opcodes::all::OP_PUSHNUM_16.to_u8();
+                    if !(first..=last).contains(&op) {
+                        break;
+                    }
+                    vec![op - first + 1]
+                }
+            });
+        }
+
+        match pushes.first() {
+            Some(protocol) if protocol.as_slice() == PROTOCOL_ID => {
+                let parts = script_num(pushes.get(1)?)?;
+                let content_type = pushes.get(2)?.clone();
+                let chunks = chunks(&pushes[3..], parts.checked_sub(1)?);
+                Some(Envelope::Start {
+                    parts,
+                    content_type,
+                    chunks,
+                })
+            }
+            Some(countdown) => {
+                let chunks = chunks(&pushes, script_num(countdown)?);
+                match chunks.is_empty() {
+                    true => None,
+                    false => Some(Envelope::Continuation { chunks }),
+                }
+            }
+            None => None,
+        }
+    }
+}
+
+/// `<countdown> <chunk>` pairs counting down from `first`, the rest of the
+/// scriptSig is the spend of the redeem script.
+fn chunks(pushes: &[Vec<u8>], first: u64) -> Vec<(u64, Vec<u8>)> {
+    let mut chunks: Vec<(u64, Vec<u8>)> = vec![];
+    for pair in pushes.chunks_exact(2) {
+        let expected = match chunks.last() {
+            Some((0, _)) => break,
+            Some((countdown, _)) => countdown - 1,
+            None => first,
+        };
+        if script_num(&pair[0]) != Some(expected) {
+            break;
+        }
+        chunks.push((expected, pair[1].clone()));
+    }
+
+    chunks
+}
+
+/// Non-negative script number of up to 4 bytes.
+fn script_num(bytes: &[u8]) -> Option<u64> {
+    if bytes.len() > 4 || bytes.last().is_some_and(|last| last & 0x80 != 0) {
+        return None;
+    }
+
+    Some(
+        bytes
+            .iter()
+            .rev()
+            .fold(0u64, |n, byte| (n << 8) | *byte as u64),
+    )
+}
+
+/// A doginal whose chunks are not all pushed yet.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PendingDoginal {
+    pub inscription_id: InscriptionId,
+    pub content_type: Vec<u8>,
+    pub body: Vec<u8>,
+    /// Countdown of the last chunk pushed.
+    pub countdown: u64,
+}
+
+impl PendingDoginal {
+    /// Starts a doginal, it is complete once the chunk counted down to 0 is pushed.
+    pub fn new(inscription_id: InscriptionId, parts: u64, content_type: Vec<u8>) -> PendingDoginal {
+        PendingDoginal {
+            inscription_id,
+            content_type,
+            body: vec![],
+            countdown: parts,
+        }
+    }
+
+    /// Appends chunks if they carry on the countdown, returns whether they did.
+    pub fn push(&mut self, chunks: Vec<(u64, Vec<u8>)>) -> bool {
+        match chunks.first() {
+            Some((countdown, _)) if countdown + 1 == self.countdown => {}
+            _ => return false,
+        }
+
+        for (countdown, chunk) in chunks {
+            self.body.extend_from_slice(&chunk);
+            self.countdown = countdown;
+        }
+        true
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.countdown == 0
+    }
+
+    pub fn into_inscription(self) -> Inscription {
+        Inscription {
+            body: Some(self.body),
+            content_type: Some(self.content_type),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.inscription_id.to_bytes();
+        varint::encode_to_vec(self.countdown as u128, &mut bytes);
+        varint::encode_to_vec(self.content_type.len() as u128, &mut bytes);
+        bytes.extend_from_slice(&self.content_type);
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<PendingDoginal> {
+        let (inscription_id, mut i) = InscriptionId::decode(bytes).ok()?;
+        let (countdown, n) = varint::decode(&bytes[i..]).ok()?;
+        i += n;
+        let (len, n) = varint::decode(&bytes[i..]).ok()?;
+        i += n;
+        let content_type = bytes.get(i..i + len as usize)?.to_vec();
+        i += len as usize;
+
+        Some(PendingDoginal {
+            inscription_id,
+            content_type,
+            body: bytes[i..].to_vec(),
+            countdown: countdown as u64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::blockdata::script::Builder;
+    use bitcoin::Txid;
+    use std::str::FromStr;
+
+    fn push(builder: Builder, bytes: &[u8]) -> Builder {
+        builder.push_slice(<&bitcoin::script::PushBytes>::try_from(bytes).unwrap())
+    }
+
+    #[test]
+    fn test_multi_part_doginal() {
+        // Three chunks, two in the first transaction, before its signature and redeem script.
+        let mut first = push(Builder::new(), b"ord").push_int(3);
+        first = push(first, b"text/plain");
+        first = push(first.push_int(2), b"he");
+        first = push(first.push_int(1), b"ll");
+        first = push(first, &[0x30; 71]);
+        first = push(first, &[0x51, 0xac]);
+        let first = Envelope::from_script_sig(first.as_bytes()).unwrap();
+
+        let Envelope::Start {
+            parts,
+            content_type,
+            chunks,
+        } = first
+        else {
+            panic!("not a start envelope");
+        };
+        assert_eq!(
+            (parts, content_type.as_slice()),
+            (3, b"text/plain".as_slice())
+        );
+        assert_eq!(chunks.len(), 2);
+
+        let txid =
+            Txid::from_str("1111111111111111111111111111111111111111111111111111111111111111")
+                .unwrap();
+        let mut doginal = PendingDoginal::new(InscriptionId::new(txid, 0), parts, content_type);
+        assert!(doginal.push(chunks));
+        assert!(!doginal.is_complete());
+        assert_eq!(
+            PendingDoginal::from_bytes(&doginal.to_bytes()),
+            Some(doginal.clone())
+        );
+
+        let mut next = push(Builder::new().push_int(0), b"o");
+        next = push(next, &[0x30; 71]);
+        let Some(Envelope::Continuation { chunks }) = Envelope::from_script_sig(next.as_bytes())
+        else {
+            panic!("not a continuation");
+        };
+        assert!(!doginal.clone().push(vec![(1, b"o".to_vec())]));
+        assert!(doginal.push(chunks));
+        assert!(doginal.is_complete());
+        assert_eq!(doginal.into_inscription().body, Some(b"hello".to_vec()));
+    }
+
+    #[test]
+    fn test_script_num() {
+        assert_eq!(script_num(&[]), Some(0));
+        assert_eq!(script_num(&[0x10, 0x01]), Some(272));
+        assert_eq!(script_num(&[0x81]), None);
+        assert_eq!(script_num(&[1, 2, 3, 4, 5]), None);
+    }
+}
@@ -0,0 +1,91 @@
+//! DRC-20, the BRC-20 style tokens of Dogecoin, reported as events of the
+//! inscriptions carrying their operations.
+//!
+//! Operations are not checked against supply or balances: deploys, mints and
+//! transfer inscriptions are reported when inscribed, transfers when their
+//! inscription first moves, as DRC-20 indexers see them.
+
+use crate::brc20::Brc20Operation;
+use crate::inscription::Inscription;
+use crate::inscription_id::InscriptionId;
+use crate::sat_point::SatPoint;
+
+const PROTOCOL: &str = "drc-20";
+/// Status key prefix of transfer inscriptions which did not move yet.
+pub(crate) const TRANSFER_PREFIX: &str = "drc20_transfer_";
+
+/// DRC-20 operations are those of BRC-20, under another protocol tag.
+pub type Drc20Operation = Brc20Operation;
+
+/// Operation in a text or json inscription, amounts are kept as written.
+pub fn operation(inscription: &Inscription) -> Option<Drc20Operation> {
+    Brc20Operation::from_protocol_inscription(inscription, PROTOCOL)
+}
+
+pub(crate) fn operation_from_json(body: &[u8]) -> Option<Drc20Operation> {
+    Brc20Operation::from_protocol_json(body, PROTOCOL)
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Drc20EventKind {
+    Deploy,
+    Mint,
+    /// A transfer inscription was inscribed, its amount is not sent yet.
+    InscribeTransfer,
+    /// A transfer inscription moved for the first time, sending its amount.
+    Transfer,
+}
+
+pub struct Drc20Entry<'a> {
+    pub kind: Drc20EventKind,
+    pub operation: &'a Drc20Operation,
+    pub inscription_id: InscriptionId,
+    /// Where the transfer inscription was, for `Transfer`.
+    pub old_satpoint: Option<SatPoint>,
+    pub satpoint: SatPoint,
+    pub to_address: &'a Option<String>,
+    pub height: u64,
+    pub timestamp: u32,
+}
+
+pub type Drc20Updater = fn(Drc20Entry);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::inscription;
+
+    #[test]
+    fn test_drc20_operation() {
+        assert_eq!(
+            operation(&inscription(
+                r#"{"p":"drc-20","op":"deploy","tick":"dogi","max":"21000000","lim":"1000"}"#
+            )),
+            Some(Drc20Operation::Deploy {
+                tick: "dogi".into(),
+                max: "21000000".into(),
+                lim: Some("1000".into()),
+                dec: None,
+            })
+        );
+        assert_eq!(
+            operation(&inscription(
+                r#"{"p":"drc-20","op":"transfer","tick":"dogi","amt":"5"}"#
+            )),
+            Some(Drc20Operation::Transfer {
+                tick: "dogi".into(),
+                amt: "5".into(),
+            })
+        );
+        assert_eq!(
+            operation(&inscription(
+                r#"{"p":"brc-20","op":"mint","tick":"ordi","amt":"1000"}"#
+            )),
+            None
+        );
+        assert_eq!(
+            operation(&inscription(r#"{"p":"drc-20","op":"burn"}"#)),
+            None
+        );
+    }
+}
@@ -7,28 +7,26 @@ fn main() -> anyhow::Result<()> {
 
     ordi.when_inscribe(|entry| {
         println!(
-            "inscribe {}, {} at {}:{}.",
-            entry.id, &entry.inscription_id, &entry.txid, entry.vout
+            "inscribe {}, {} at {}.",
+            entry.id, entry.inscription_id, entry.satpoint.outpoint
         );
     });
 
     ordi.when_transfer(|entry| {
         println!(
-            "transfer {} from {}:{} to {}:{}:{}.",
-            entry.inscription_id,
-            entry.from_output,
-            entry.from_offset,
-            entry.txid,
-            entry.vout,
-            entry.offset
+            "transfer {} from {} to {}.",
+            entry.inscription_id, entry.old_satpoint, entry.new_satpoint
         );
     });
 
     // If index_previous_output_value is set true,
     // dump-event would reindex utxos until height 767430.
+    // If set chainstate, utxos are read from bitcoind's chainstate at height 767429.
     // else use rpc to get utxo like ord.
-    if std::env::var("index_previous_output_value")? == "true" {
-        ordi.index_output_value()?;
+    match std::env::var("index_previous_output_value")?.as_str() {
+        "true" => ordi.index_output_value()?,
+        "chainstate" => ordi.index_output_value_from_chainstate()?,
+        _ => {}
     }
 
     ordi.start().expect("Error happened when ordi is running.");
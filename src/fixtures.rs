@@ -0,0 +1,22 @@
+//! Values shared by the tests of the protocol indexes.
+
+use crate::inscription::Inscription;
+use crate::inscription_id::InscriptionId;
+
+/// Text inscription of `body`.
+pub(crate) fn inscription(body: &str) -> Inscription {
+    Inscription {
+        body: Some(body.as_bytes().to_vec()),
+        content_type: Some(b"text/plain;charset=utf-8".to_vec()),
+    }
+}
+
+/// Inscription `index` of the same reveal transaction.
+pub(crate) fn id(index: u32) -> InscriptionId {
+    format!(
+        "6fb976ab49dcec017f1e201e84395983204ae1a7c2abf7ced0a85d692e442799i{}",
+        index
+    )
+    .parse()
+    .unwrap()
+}
@@ -10,6 +10,7 @@ use bitcoin::{
 };
 
 use crate::block::Tx;
+use crate::inscription_id::InscriptionId;
 
 const PROTOCOL_ID: [u8; 3] = *b"ord";
 const BODY_TAG: [u8; 0] = [];
@@ -49,6 +50,8 @@ pub struct TransactionInscription {
     pub inscription: Inscription,
     pub tx_in_index: u32,
     pub tx_in_offset: u32,
+    /// Id given by an earlier transaction, for doginals assembled across transactions.
+    pub inscription_id: Option<InscriptionId>,
 }
 
 impl Inscription {
@@ -71,6 +74,7 @@ impl Inscription {
                         inscription,
                         tx_in_index: u32::try_from(index).unwrap(),
                         tx_in_offset: u32::try_from(offset).unwrap(),
+                        inscription_id: None,
                     })
                     .collect::<Vec<TransactionInscription>>(),
             )
@@ -0,0 +1,145 @@
+use std::{fmt, num::ParseIntError, str::FromStr};
+
+use bitcoin::hashes::{hex, Hash};
+use bitcoin::Txid;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::varint::{self, VarintError};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum InscriptionIdError {
+    #[error("Missing separator in: `{0}`")]
+    Separator(String),
+    #[error("Invalid txid: `{0}`")]
+    Txid(hex::Error),
+    #[error("Invalid index: `{0}`")]
+    Index(ParseIntError),
+    #[error("Invalid length: `{0}`")]
+    Length(usize),
+    #[error("Invalid varint: `{0}`")]
+    Varint(#[from] VarintError),
+}
+
+/// Inscription identity, displayed as `<genesis txid>i<index>`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct InscriptionId {
+    pub txid: Txid,
+    pub index: u32,
+}
+
+impl InscriptionId {
+    pub fn new(txid: Txid, index: u32) -> InscriptionId {
+        InscriptionId { txid, index }
+    }
+
+    /// Compact encoding: 32 bytes txid followed by varint index.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(32 + 5);
+        self.encode_to_vec(&mut bytes);
+        bytes
+    }
+
+    pub fn encode_to_vec(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(self.txid.as_byte_array());
+        varint::encode_to_vec(self.index.into(), bytes);
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<InscriptionId, InscriptionIdError> {
+        let (inscription_id, len) = InscriptionId::decode(bytes)?;
+        if len != bytes.len() {
+            return Err(InscriptionIdError::Length(bytes.len()));
+        }
+
+        Ok(inscription_id)
+    }
+
+    /// Decodes an id from the front of `bytes`, returns it with the consumed length.
+    pub fn decode(bytes: &[u8]) -> Result<(InscriptionId, usize), InscriptionIdError> {
+        if bytes.len() < 33 {
+            return Err(InscriptionIdError::Length(bytes.len()));
+        }
+
+        let txid =
+            Txid::from_slice(&bytes[..32]).map_err(|_| InscriptionIdError::Length(bytes.len()))?;
+        let (index, len) = varint::decode(&bytes[32..])?;
+        let index = u32::try_from(index).map_err(|_| VarintError::Overflow)?;
+
+        Ok((InscriptionId { txid, index }, 32 + len))
+    }
+}
+
+impl fmt::Display for InscriptionId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}i{}", self.txid, self.index)
+    }
+}
+
+impl FromStr for InscriptionId {
+    type Err = InscriptionIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (txid, index) = s
+            .split_once('i')
+            .ok_or_else(|| InscriptionIdError::Separator(s.to_string()))?;
+
+        Ok(InscriptionId {
+            txid: txid.parse().map_err(InscriptionIdError::Txid)?,
+            index: index.parse().map_err(InscriptionIdError::Index)?,
+        })
+    }
+}
+
+impl Serialize for InscriptionId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for InscriptionId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INSCRIPTION_ID: &str =
+        "6fb976ab49dcec017f1e201e84395983204ae1a7c2abf7ced0a85d692e442799i0";
+
+    #[test]
+    fn test_inscription_id_display_round_trip() {
+        let inscription_id = INSCRIPTION_ID.parse::<InscriptionId>().unwrap();
+        assert_eq!(inscription_id.index, 0);
+        assert_eq!(inscription_id.to_string(), INSCRIPTION_ID);
+    }
+
+    #[test]
+    fn test_inscription_id_bytes_round_trip() {
+        let inscription_id = INSCRIPTION_ID.parse::<InscriptionId>().unwrap();
+        let bytes = inscription_id.to_bytes();
+        assert_eq!(bytes.len(), 33);
+        assert_eq!(InscriptionId::from_bytes(&bytes), Ok(inscription_id));
+    }
+
+    #[test]
+    fn test_inscription_id_malformed() {
+        assert!(matches!(
+            "6fb976ab".parse::<InscriptionId>(),
+            Err(InscriptionIdError::Separator(_))
+        ));
+        assert!(matches!(
+            "6fb976abi0".parse::<InscriptionId>(),
+            Err(InscriptionIdError::Txid(_))
+        ));
+        assert!(matches!(
+            "6fb976ab49dcec017f1e201e84395983204ae1a7c2abf7ced0a85d692e442799i-1"
+                .parse::<InscriptionId>(),
+            Err(InscriptionIdError::Index(_))
+        ));
+    }
+}
@@ -1,45 +1,139 @@
-use std::{fs, path::PathBuf, thread, time::Duration};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
 
 use bitcoincore_rpc::{Client, RpcApi};
-use log::info;
-use rusty_leveldb::{WriteBatch, DB};
+use log::{info, warn};
 use thiserror::Error;
 
+use crate::bitcoin::chainstate::{Chainstate, ChainstateError};
 use crate::bitcoin::index::IndexError;
-use crate::block::{BlockUpdaterError, InscribeUpdater, TransferUpdater, Tx};
+use crate::bitcoin::prefetch::BlockPrefetcher;
+use crate::bitcoin::proto::script;
+use crate::bitmap::BitmapIndex;
+use crate::block::{
+    decode_output_inscriptions, BlockUpdaterError, InscribeUpdater, ProtoBlock, TransferUpdater,
+    INDEXED_HEIGHT,
+};
+use crate::brc20::Brc20Ledger;
+use crate::drc20::Drc20Updater;
 use crate::inscription::Inscription;
+use crate::inscription_id::{InscriptionId, InscriptionIdError};
+use crate::migration::MigrationError;
+use crate::outpoint::OutPoint;
+use crate::runes::{RuneIndex, RuneUpdater};
+use crate::sat_point::{SatPoint, SatPointError};
+use crate::snapshot::{Manifest, SnapshotError};
+use crate::sns::SnsIndex;
+use crate::source::{BlkBlockSource, BlockSource, BlockSourceError, RpcBlockSource};
+use crate::src20::Src20Ledger;
+use crate::store::{
+    BufferedStore, Journal, KeyspaceError, StorageEngine, Store, StoreError, WriteBatch,
+};
+use crate::utxo_cache::{UtxoCache, UtxoCacheStats};
 use crate::{
-    bitcoin::index::{Index, FIRST_INSCRIPTION_HEIGHT},
-    block::BlockUpdater,
+    bitcoin::index::{Index, IndexMode},
+    block::{BlockUpdater, Keyspaces, Updaters},
+    chain::{Chain, ChainError},
 };
 
 pub mod bitcoin;
 
+pub mod bitmap;
 pub mod block;
+pub mod brc20;
+pub mod chain;
+pub mod doginal;
+pub mod drc20;
 pub mod epoch;
+#[cfg(test)]
+mod fixtures;
 pub mod height;
 pub mod inscription;
+pub mod inscription_id;
+pub mod migration;
+pub mod outpoint;
+pub mod runes;
+pub mod sat_point;
+pub mod snapshot;
+pub mod sns;
+pub mod source;
+pub mod src20;
+pub mod stamps;
+pub mod store;
+pub mod utxo_cache;
+pub mod varint;
 
 const ORDI_STATUS: &str = "status";
 const ORDI_OUTPUT_VALUE: &str = "output_value";
 const ORDI_ID_TO_INSCRIPTION: &str = "id_inscription";
 const ORDI_INSCRIPTION_TO_OUTPUT: &str = "inscription_output";
 const ORDI_OUTPUT_TO_INSCRIPTION: &str = "output_inscription";
+const ORDI_BLOCK_INDEX: &str = "block_index";
+const ORDI_BRC20: &str = "brc20";
+const ORDI_BITMAP: &str = "bitmap";
+const ORDI_SNS: &str = "sns";
+const ORDI_RUNES: &str = "runes";
+const ORDI_SRC20: &str = "src20";
+/// `s` -> height a protocol keyspace is kept from, 8 bytes LE. No protocol
+/// key starts with `s`.
+const PROTOCOL_START_HEIGHT: &[u8] = b"s";
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(30);
+/// Height up to which `output_value` holds every utxo.
+const OUTPUT_VALUE_HEIGHT: &str = "output_value_height";
+/// Wait after a block source failed, doubled on every failure in a row.
+const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+const MAX_RETRY_INTERVAL: Duration = Duration::from_secs(300);
 
 #[derive(Error, Debug)]
 pub enum OrdiError {
     #[error("Var error: `{0}`")]
     VarError(#[from] std::env::VarError),
-    #[error("Open leveldb error: `{0}`")]
-    OpenLevelDBError(#[from] rusty_leveldb::Status),
+    #[error("Store error: `{0}`")]
+    StoreError(#[from] StoreError),
     #[error("Bitcoin rpc errpr: `{0}`")]
     BitcoinRpcError(#[from] bitcoincore_rpc::Error),
     #[error("Index error: `{0}`")]
     IndexError(#[from] IndexError),
+    #[error("Block source error: `{0}`")]
+    BlockSourceError(#[from] BlockSourceError),
+    #[error("Chain error: `{0}`")]
+    ChainError(#[from] ChainError),
     #[error("BlockUpdater error: `{0}`")]
     BlockUpdaterError(#[from] BlockUpdaterError),
     #[error("Create Ordi data directory error: `{0}`")]
     CreateOrdiDataDirError(#[from] std::io::Error),
+    #[error("Inscription id error: `{0}`")]
+    InscriptionIdError(#[from] InscriptionIdError),
+    #[error("SatPoint error: `{0}`")]
+    SatPointError(#[from] SatPointError),
+    #[error("Migration error: `{0}`")]
+    MigrationError(#[from] MigrationError),
+    #[error("Chainstate error: `{0}`")]
+    ChainstateError(#[from] ChainstateError),
+    #[error("Chainstate best block is at height `{0:?}`, expect: `{1}`")]
+    ChainstateHeightMismatch(Option<u64>, u64),
+    #[error("Snapshot error: `{0}`")]
+    SnapshotError(#[from] SnapshotError),
+    #[error("Snapshot block hash is `{0}`, node has: `{1}`")]
+    SnapshotBlockHashMismatch(String, String),
+    #[error("Output values are at height `{0:?}`, can not reach: `{1}`")]
+    OutputValueHeight(Option<u64>, u64),
+    #[error("Inscriptions are indexed until height `{0}`, output values can not be replaced")]
+    AlreadyIndexed(u64),
+    #[error("Block at height `{0}` not found in blk files and rpc is offline")]
+    BlockNotFound(u64),
+    #[error("Keyspace error: `{0}`")]
+    KeyspaceError(#[from] KeyspaceError),
+    #[error("Address of output `{0}` not found, the node does not have it or rpc is offline")]
+    OutputAddressNotFound(OutPoint),
+    #[error("Keyspace `{0}` is kept from height `{1}`, but its protocol starts at `{2}`, enabling it needs a reindex")]
+    ProtocolStartHeight(&'static str, u64, u64),
 }
 
 #[derive(Debug, Clone)]
@@ -49,6 +143,43 @@ pub struct Options {
     pub btc_rpc_host: String,
     pub btc_rpc_user: String,
     pub btc_rpc_pass: String,
+    /// `mainnet` (default), `testnet3`, `testnet4`, `signet` or `regtest`.
+    /// Blocks are read from its subfolder of `btc_data_dir`.
+    pub network: String,
+    /// Hex block challenge of a custom signet.
+    pub signet_challenge: String,
+    /// Chain to index instead of `network`, e.g. `ChainParams::new(coin).into()`
+    /// for a coin ordi has no preset for.
+    pub chain: Option<Chain>,
+    /// Runs without bitcoind's rpc: blocks only come from blk files and
+    /// output values only from `output_value`.
+    pub offline: bool,
+    /// In offline mode, the block index is refreshed every this many seconds.
+    pub blk_rescan_secs: u64,
+    /// `leveldb` (default) reads bitcoind's `blocks/index`, `scan` scans blk
+    /// files instead. The active chain is cached in `ordi_data_dir` either way.
+    pub index_mode: String,
+    /// `leveldb` (default), `memory`, or `redb` with the `redb` feature.
+    pub storage_engine: String,
+    /// While catching up from blk files, writes are kept in memory and
+    /// flushed every this many blocks...
+    pub catch_up_flush_blocks: u64,
+    /// ...or once they take this many megabytes.
+    pub catch_up_flush_mb: usize,
+    /// Blocks read and decoded ahead of the one being indexed.
+    pub prefetch_blocks: usize,
+    /// Memory budget of the cache in front of `output_value`, 0 disables it.
+    pub utxo_cache_mb: usize,
+    /// Keeps the BRC-20 ledger, from the first inscription on.
+    pub brc20: bool,
+    /// Keeps the bitmap claims, from the first inscription on.
+    pub bitmap: bool,
+    /// Keeps the `.sats` names, from the first inscription on.
+    pub sns: bool,
+    /// Keeps rune balances, from the chain's first rune height on.
+    pub runes: bool,
+    /// Keeps the SRC-20 ledger of stamps.
+    pub src20: bool,
 }
 
 impl Default for Options {
@@ -59,68 +190,189 @@ impl Default for Options {
             btc_rpc_host: std::env::var("btc_rpc_host").unwrap_or_default(),
             btc_rpc_user: std::env::var("btc_rpc_user").unwrap_or_default(),
             btc_rpc_pass: std::env::var("btc_rpc_pass").unwrap_or_default(),
+            network: std::env::var("network").unwrap_or_default(),
+            signet_challenge: std::env::var("signet_challenge").unwrap_or_default(),
+            chain: None,
+            offline: std::env::var("offline")
+                .map(|v| v == "true")
+                .unwrap_or_default(),
+            blk_rescan_secs: std::env::var("blk_rescan_secs")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(60),
+            index_mode: std::env::var("index_mode").unwrap_or_default(),
+            storage_engine: std::env::var("storage_engine").unwrap_or_default(),
+            catch_up_flush_blocks: std::env::var("catch_up_flush_blocks")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1000),
+            catch_up_flush_mb: std::env::var("catch_up_flush_mb")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1024),
+            prefetch_blocks: std::env::var("prefetch_blocks")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(16),
+            utxo_cache_mb: std::env::var("utxo_cache_mb")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(512),
+            brc20: std::env::var("brc20")
+                .map(|v| v == "true")
+                .unwrap_or_default(),
+            bitmap: std::env::var("bitmap")
+                .map(|v| v == "true")
+                .unwrap_or_default(),
+            sns: std::env::var("sns")
+                .map(|v| v == "true")
+                .unwrap_or_default(),
+            runes: std::env::var("runes")
+                .map(|v| v == "true")
+                .unwrap_or_default(),
+            src20: std::env::var("src20")
+                .map(|v| v == "true")
+                .unwrap_or_default(),
         }
     }
 }
 
 pub struct Ordi {
-    pub btc_rpc_client: Client,
-    pub status: DB,
-    pub output_value: DB,
-    pub id_inscription: DB,
-    pub inscription_output: DB,
-    pub output_inscription: DB,
-    pub index: Index,
+    /// `None` in offline mode.
+    pub btc_rpc_client: Option<Arc<Client>>,
+    pub status: BufferedStore,
+    pub output_value: BufferedStore,
+    pub id_inscription: BufferedStore,
+    pub inscription_output: BufferedStore,
+    pub output_inscription: BufferedStore,
+    pub utxo_cache: UtxoCache,
+    pub protocols: ProtocolStores,
+    journal: Journal,
+    pub chain: Chain,
+    pub index: Rc<Index>,
+    pub catch_up_flush_blocks: u64,
+    pub catch_up_flush_mb: usize,
+    pub prefetch_blocks: usize,
+    pub blk_rescan_interval: Duration,
     pub inscribe_updaters: Vec<InscribeUpdater>,
     pub transfer_updaters: Vec<TransferUpdater>,
+    pub drc20_updaters: Vec<Drc20Updater>,
+    pub rune_updaters: Vec<RuneUpdater>,
 }
 
 impl Ordi {
     pub fn new(options: Options) -> Result<Ordi, OrdiError> {
-        let ordi_data_dir = PathBuf::from(options.ordi_data_dir);
+        let ordi_data_dir = PathBuf::from(&options.ordi_data_dir);
         if !ordi_data_dir.exists() {
             fs::create_dir(ordi_data_dir.as_path())?;
         }
 
-        let index = Index::new(PathBuf::from(options.btc_data_dir))?;
+        let chain = match &options.chain {
+            Some(chain) => chain.clone(),
+            None => Chain::from_name(&options.network, &options.signet_challenge)?,
+        };
+        let index = Rc::new(Index::open(
+            PathBuf::from(&options.btc_data_dir).join(chain.data_subfolder()),
+            &ordi_data_dir.join(ORDI_BLOCK_INDEX),
+            chain.coin(),
+            IndexMode::from_name(&options.index_mode)?,
+        )?);
 
-        let mut leveldb_options = rusty_leveldb::Options::default();
-        leveldb_options.max_file_size = 2 << 25;
+        let engine = options.storage_engine.parse::<StorageEngine>()?;
+        let journal = open_journal(&engine, &ordi_data_dir)?;
+        let mut status = engine.open(&ordi_data_dir, ORDI_STATUS)?;
+        let mut output_value = engine.open(&ordi_data_dir, ORDI_OUTPUT_VALUE)?;
+        let mut id_inscription = engine.open(&ordi_data_dir, ORDI_ID_TO_INSCRIPTION)?;
+        let mut inscription_output = engine.open(&ordi_data_dir, ORDI_INSCRIPTION_TO_OUTPUT)?;
+        let mut output_inscription = engine.open(&ordi_data_dir, ORDI_OUTPUT_TO_INSCRIPTION)?;
 
-        let status = DB::open(ordi_data_dir.join(ORDI_STATUS), leveldb_options.clone())?;
-        let output_value = DB::open(
-            ordi_data_dir.join(ORDI_OUTPUT_VALUE),
-            leveldb_options.clone(),
-        )?;
-        let id_inscription = DB::open(
-            ordi_data_dir.join(ORDI_ID_TO_INSCRIPTION),
-            leveldb_options.clone(),
-        )?;
-        let inscription_output = DB::open(
-            ordi_data_dir.join(ORDI_INSCRIPTION_TO_OUTPUT),
-            leveldb_options.clone(),
-        )?;
-        let output_inscription = DB::open(
-            ordi_data_dir.join(ORDI_OUTPUT_TO_INSCRIPTION),
-            rusty_leveldb::in_memory(),
+        let reindex = migration::migrate(
+            status.as_mut(),
+            output_value.as_mut(),
+            id_inscription.as_mut(),
+            inscription_output.as_mut(),
+            output_inscription.as_mut(),
+            chain.jubilee_height(),
         )?;
 
-        let btc_rpc_client = Client::new(
-            options.btc_rpc_host.as_str(),
-            bitcoincore_rpc::Auth::UserPass(options.btc_rpc_user, options.btc_rpc_pass),
-        )?;
+        let next_height = match status.get(INDEXED_HEIGHT.as_bytes())? {
+            Some(height) => height
+                .try_into()
+                .map(u64::from_le_bytes)
+                .map_or(u64::MAX, |height| height + 1),
+            None => chain.first_inscription_height(),
+        };
+        if reindex {
+            ProtocolStores::clear(&engine, &ordi_data_dir)?;
+        }
+        let protocols =
+            ProtocolStores::open(&engine, &ordi_data_dir, &options, &chain, next_height)?;
+
+        let btc_rpc_client = match options.offline {
+            true => None,
+            false => Some(Arc::new(Client::new(
+                options.btc_rpc_host.as_str(),
+                bitcoincore_rpc::Auth::UserPass(options.btc_rpc_user, options.btc_rpc_pass),
+            )?)),
+        };
 
         Ok(Ordi {
             btc_rpc_client,
+            status: BufferedStore::new(status),
+            output_value: BufferedStore::new(output_value),
+            id_inscription: BufferedStore::new(id_inscription),
+            inscription_output: BufferedStore::new(inscription_output),
+            output_inscription: BufferedStore::new(output_inscription),
+            utxo_cache: UtxoCache::new(options.utxo_cache_mb << 20),
+            protocols,
+            journal,
+            chain,
+            index,
+            catch_up_flush_blocks: options.catch_up_flush_blocks,
+            catch_up_flush_mb: options.catch_up_flush_mb,
+            prefetch_blocks: options.prefetch_blocks,
+            blk_rescan_interval: Duration::from_secs(options.blk_rescan_secs),
+            inscribe_updaters: vec![],
+            transfer_updaters: vec![],
+            drc20_updaters: vec![],
+            rune_updaters: vec![],
+        })
+    }
+
+    /// Drops everything indexed in `options.ordi_data_dir` for it to be indexed
+    /// again from scratch. Output values restored from a snapshot or a
+    /// chainstate have to be restored again.
+    pub fn reindex(options: &Options) -> Result<(), OrdiError> {
+        let ordi_data_dir = PathBuf::from(&options.ordi_data_dir);
+        let engine = options.storage_engine.parse::<StorageEngine>()?;
+        // A flush left in the journal would be replayed over the dropped keyspaces.
+        open_journal(&engine, &ordi_data_dir)?.close()?;
+        let mut status = engine.open(&ordi_data_dir, ORDI_STATUS)?;
+        let mut output_value = engine.open(&ordi_data_dir, ORDI_OUTPUT_VALUE)?;
+        let mut id_inscription = engine.open(&ordi_data_dir, ORDI_ID_TO_INSCRIPTION)?;
+        let mut inscription_output = engine.open(&ordi_data_dir, ORDI_INSCRIPTION_TO_OUTPUT)?;
+        let mut output_inscription = engine.open(&ordi_data_dir, ORDI_OUTPUT_TO_INSCRIPTION)?;
+
+        migration::reset(
+            status.as_mut(),
+            output_value.as_mut(),
+            id_inscription.as_mut(),
+            inscription_output.as_mut(),
+            output_inscription.as_mut(),
+        )?;
+        ProtocolStores::clear(&engine, &ordi_data_dir)?;
+
+        for mut store in [
             status,
             output_value,
             id_inscription,
             inscription_output,
             output_inscription,
-            index,
-            inscribe_updaters: vec![],
-            transfer_updaters: vec![],
-        })
+        ] {
+            store.close()?;
+        }
+        info!("Dropped everything indexed in {:?}.", ordi_data_dir);
+        Ok(())
     }
 
     pub fn close(&mut self) {
@@ -135,72 +387,600 @@ impl Ordi {
         self.output_inscription
             .close()
             .expect("Close output_inscription db.");
+        self.protocols.close();
+        self.journal.close().expect("Close journal db.");
     }
 
     pub fn start(&mut self) -> Result<(), OrdiError> {
-        // Catch up latest block.
-        let mut next_height = self.index.max_height + 1;
-        for height in FIRST_INSCRIPTION_HEIGHT..next_height {
-            let block = self.index.catch_block(height)?;
-            let mut block_updater = BlockUpdater::new(
-                height,
-                block,
-                &self.btc_rpc_client,
-                &mut self.status,
-                &mut self.output_value,
-                &mut self.id_inscription,
-                &mut self.inscription_output,
-                &mut self.output_inscription,
-                &self.inscribe_updaters,
-                &self.transfer_updaters,
-            );
-
-            block_updater.index_transactions()?;
-        }
-
-        let client = &self.btc_rpc_client;
-        loop {
-            match client.get_block_hash(next_height) {
-                Ok(block_hash) => {
-                    let mut block_updater = BlockUpdater::new(
-                        next_height,
-                        client.get_block(&block_hash)?.into(),
-                        &self.btc_rpc_client,
-                        &mut self.status,
-                        &mut self.output_value,
-                        &mut self.id_inscription,
-                        &mut self.inscription_output,
-                        &mut self.output_inscription,
-                        &self.inscribe_updaters,
-                        &self.transfer_updaters,
-                    );
+        // Catch up latest block in blk files, then follow the tip over rpc,
+        // or in blk files when offline.
+        let mut blk = BlkBlockSource::new(self.index.clone(), self.prefetch_blocks);
+        if self.btc_rpc_client.is_none() {
+            self.ensure_output_value_indexed()?;
+        }
+        self.index_from(&mut blk)?;
 
-                    block_updater.index_transactions()?;
-                    next_height += 1;
+        match self.btc_rpc_client.clone() {
+            Some(btc_rpc_client) => {
+                let mut rpc = RpcBlockSource::new(btc_rpc_client, self.index.coin.clone());
+                let mut retry_interval = RETRY_INTERVAL;
+                loop {
+                    self.follow_retrying(&mut rpc, &mut retry_interval)?;
                 }
-                Err(_) => {
-                    thread::sleep(Duration::from_secs(10));
+            }
+            None => {
+                // Offline, wait for bitcoind to write new blocks into blk files.
+                let mut blk = blk.with_rescan(self.blk_rescan_interval);
+                let mut retry_interval = RETRY_INTERVAL;
+                loop {
+                    self.follow_retrying(&mut blk, &mut retry_interval)?;
                 }
-            };
+            }
+        }
+    }
+
+    /// Offline, output values spent by the next block can only come from
+    /// `output_value`, so it has to be indexed up to the block before.
+    fn ensure_output_value_indexed(&mut self) -> Result<(), OrdiError> {
+        let height = match self.next_height()?.checked_sub(1) {
+            Some(height) => height,
+            None => return Ok(()),
+        };
+        let output_value_height = self.output_value_height()?;
+        match output_value_height {
+            Some(output_value_height) if output_value_height >= height => Ok(()),
+            _ => Err(OrdiError::OutputValueHeight(output_value_height, height)),
+        }
+    }
+
+    fn next_height(&mut self) -> Result<u64, OrdiError> {
+        Ok(match self.indexed_height()? {
+            Some(height) => height + 1,
+            None => self.chain.first_inscription_height(),
+        })
+    }
+
+    /// Indexes blocks of `source` up to its tip, flushing every few blocks.
+    /// Returns the height following the last indexed block.
+    pub fn index_from(&mut self, source: &mut dyn BlockSource) -> Result<u64, OrdiError> {
+        let mut next_height = self.next_height()?;
+        info!("Start indexing from height: {}.", next_height);
+
+        let mut unflushed_blocks = 0;
+        while let Some(block) = source.block(next_height)? {
+            self.index_block(next_height, block)?;
+            next_height += 1;
+            unflushed_blocks += 1;
+
+            if unflushed_blocks >= self.catch_up_flush_blocks
+                || self.pending_size() >= self.catch_up_flush_mb << 20
+            {
+                self.flush(next_height - 1)?;
+                unflushed_blocks = 0;
+            }
+        }
+
+        if unflushed_blocks > 0 {
+            self.flush(next_height - 1)?;
+        }
+
+        Ok(next_height)
+    }
+
+    /// Waits for the next block of `source` and indexes it, flushing after it
+    /// as it is near the tip. Returns whether a block was indexed.
+    pub fn follow(&mut self, source: &mut dyn BlockSource) -> Result<bool, OrdiError> {
+        let height = self.next_height()?;
+        if !source.wait_for_height(height, Duration::from_secs(10))? {
+            return Ok(false);
+        }
+
+        match source.block(height)? {
+            Some(block) => {
+                self.index_block(height, block)?;
+                self.flush(height)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Like `follow`, but when `source` fails, e.g. while bitcoind restarts,
+    /// waits `retry_interval` and returns `Ok(false)` instead. The interval
+    /// doubles on every failure in a row, up to `MAX_RETRY_INTERVAL`.
+    ///
+    /// Nothing is indexed yet when the source fails, errors of indexing a
+    /// block are returned as they may have left partial writes behind.
+    pub fn follow_retrying(
+        &mut self,
+        source: &mut dyn BlockSource,
+        retry_interval: &mut Duration,
+    ) -> Result<bool, OrdiError> {
+        match self.follow(source) {
+            Ok(indexed) => {
+                *retry_interval = RETRY_INTERVAL;
+                Ok(indexed)
+            }
+            Err(OrdiError::BlockSourceError(e)) => {
+                warn!(
+                    "Block source error, retry in {}s: {}",
+                    retry_interval.as_secs(),
+                    e
+                );
+                thread::sleep(*retry_interval);
+                *retry_interval = (*retry_interval * 2).min(MAX_RETRY_INTERVAL);
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn index_block(&mut self, height: u64, block: ProtoBlock) -> Result<(), OrdiError> {
+        let mut block_updater = BlockUpdater::new(
+            height,
+            block,
+            self.btc_rpc_client.as_deref(),
+            Keyspaces {
+                status: &mut self.status,
+                output_value: &mut self.output_value,
+                id_inscription: &mut self.id_inscription,
+                inscription_output: &mut self.inscription_output,
+                output_inscription: &mut self.output_inscription,
+                protocols: &mut self.protocols,
+            },
+            &mut self.utxo_cache,
+            &self.chain,
+            Updaters {
+                inscribe: &self.inscribe_updaters,
+                transfer: &self.transfer_updaters,
+                drc20: &self.drc20_updaters,
+                rune: &self.rune_updaters,
+            },
+        );
+
+        block_updater.index_transactions()?;
+
+        if self.output_value_height()? == height.checked_sub(1) {
+            self.status.put(
+                OUTPUT_VALUE_HEIGHT.as_bytes(),
+                height.to_le_bytes().as_slice(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Commits everything indexed up to `height`, through the journal so a
+    /// crash in between leaves no keyspace ahead of the others.
+    fn flush(&mut self, height: u64) -> Result<(), OrdiError> {
+        let start = std::time::Instant::now();
+        let pending_size = self.pending_size();
+
+        let keyspaces = [
+            (ORDI_STATUS, &mut self.status),
+            (ORDI_OUTPUT_VALUE, &mut self.output_value),
+            (ORDI_ID_TO_INSCRIPTION, &mut self.id_inscription),
+            (ORDI_INSCRIPTION_TO_OUTPUT, &mut self.inscription_output),
+            (ORDI_OUTPUT_TO_INSCRIPTION, &mut self.output_inscription),
+        ];
+        self.journal
+            .flush(keyspaces.into_iter().chain(self.protocols.iter_mut()))?;
+
+        let stats = self.utxo_cache.stats();
+        info!(
+            "Flushed {} bytes until height: {}, cost: {}ms. Utxo cache: {}/{} entries, hit rate: {:.2}%.",
+            pending_size,
+            height,
+            start.elapsed().as_millis(),
+            stats.len,
+            stats.capacity,
+            stats.hit_rate() * 100.0
+        );
+        Ok(())
+    }
+
+    /// Size of writes not flushed yet.
+    pub fn pending_size(&self) -> usize {
+        self.status.pending_size()
+            + self.output_value.pending_size()
+            + self.id_inscription.pending_size()
+            + self.inscription_output.pending_size()
+            + self.output_inscription.pending_size()
+            + self.protocols.pending_size()
+    }
+
+    pub fn utxo_cache_stats(&self) -> UtxoCacheStats {
+        self.utxo_cache.stats()
+    }
+
+    /// Last height whose inscriptions are flushed.
+    pub fn indexed_height(&mut self) -> Result<Option<u64>, OrdiError> {
+        self.status_height(INDEXED_HEIGHT)
+    }
+
+    /// Height up to which `output_value` holds every utxo, if it is complete.
+    pub fn output_value_height(&mut self) -> Result<Option<u64>, OrdiError> {
+        self.status_height(OUTPUT_VALUE_HEIGHT)
+    }
+
+    fn status_height(&mut self, k: &str) -> Result<Option<u64>, OrdiError> {
+        Ok(self
+            .status
+            .get(k.as_bytes())?
+            .and_then(|v| v.try_into().ok())
+            .map(u64::from_le_bytes))
+    }
+
+    fn set_output_value_height(&mut self, height: u64) -> Result<(), OrdiError> {
+        self.status.put(
+            OUTPUT_VALUE_HEIGHT.as_bytes(),
+            height.to_le_bytes().as_slice(),
+        )?;
+        Ok(self.status.flush()?)
+    }
+
+    /// Drops `output_value` to be replaced. Its height goes first, so output
+    /// values left partial by a crash are not taken as complete.
+    fn clear_output_value(&mut self) -> Result<(), OrdiError> {
+        self.status.delete(OUTPUT_VALUE_HEIGHT.as_bytes())?;
+        self.status.flush()?;
+        Ok(store::clear(self.output_value.inner_mut())?)
+    }
+
+    /// Output values are only replaced before any inscription is indexed.
+    fn ensure_not_indexed(&mut self) -> Result<(), OrdiError> {
+        match self.indexed_height()? {
+            Some(height) => Err(OrdiError::AlreadyIndexed(height)),
+            None => Ok(()),
+        }
+    }
+
+    /// Hash of the block at `height` in the node's chain.
+    pub fn block_hash(&self, height: u64) -> Result<String, OrdiError> {
+        match self.index.get_index_entry(height)? {
+            Some(entry) => Ok(::bitcoin::BlockHash::from_raw_hash(entry.block_hash).to_string()),
+            None => match &self.btc_rpc_client {
+                Some(btc_rpc_client) => Ok(btc_rpc_client.get_block_hash(height)?.to_string()),
+                None => Err(OrdiError::BlockNotFound(height)),
+            },
+        }
+    }
+
+    /// Writes `output_value` at `height` into a snapshot in `dir`. Output values
+    /// behind `height` are indexed first, if `height` is before the first inscription.
+    pub fn create_snapshot(&mut self, height: u64, dir: &Path) -> Result<Manifest, OrdiError> {
+        let output_value_height = self.output_value_height()?;
+        if output_value_height != Some(height) {
+            if height >= self.chain.first_inscription_height() || output_value_height > Some(height)
+            {
+                return Err(OrdiError::OutputValueHeight(output_value_height, height));
+            }
+            self.ensure_not_indexed()?;
+            self.index_output_value_until(height)?;
+        }
+
+        let block_hash = self.block_hash(height)?;
+        let manifest = snapshot::create(self.output_value.inner_mut(), height, block_hash, dir)?;
+        info!(
+            "Created snapshot of {} output values at height: {}, sha256: {}.",
+            manifest.entries, manifest.height, manifest.sha256
+        );
+
+        Ok(manifest)
+    }
+
+    /// Replaces `output_value` with a snapshot at the height before the first
+    /// inscription, after checking it against the manifest and the manifest's
+    /// block hash against the node's chain.
+    pub fn restore_snapshot(&mut self, dir: &Path) -> Result<Manifest, OrdiError> {
+        self.ensure_not_indexed()?;
+
+        // Inscriptions are indexed right after, from the first inscription on.
+        let manifest = Manifest::read(dir)?;
+        let expected_height = self.chain.first_inscription_height().checked_sub(1);
+        if Some(manifest.height) != expected_height {
+            return Err(OrdiError::OutputValueHeight(
+                Some(manifest.height),
+                expected_height.unwrap_or_default(),
+            ));
+        }
+
+        let block_hash = self.block_hash(manifest.height)?;
+        if block_hash != manifest.block_hash {
+            return Err(OrdiError::SnapshotBlockHashMismatch(
+                manifest.block_hash,
+                block_hash,
+            ));
         }
+
+        snapshot::verify(dir, &manifest)?;
+        info!("Verified snapshot at height: {}.", manifest.height);
+
+        self.clear_output_value()?;
+        let entries = snapshot::restore(self.output_value.inner_mut(), dir)?;
+        self.set_output_value_height(manifest.height)?;
+        info!("Restored {} output values.", entries);
+
+        Ok(manifest)
     }
 
+    /// Indexes output values until the height before the first inscription.
     pub fn index_output_value(&mut self) -> Result<(), OrdiError> {
-        for height in 0..FIRST_INSCRIPTION_HEIGHT {
-            let block = self.index.catch_block(height)?;
-            for (_tx_index, tx) in block.txs.iter().enumerate() {
-                self.index_output_value_in_transaction(&tx)?;
+        match self.chain.first_inscription_height().checked_sub(1) {
+            Some(height) => self.index_output_value_until(height),
+            None => Ok(()),
+        }
+    }
+
+    /// Indexes output values from blk files until `height`, resuming after `output_value_height`.
+    ///
+    /// Blocks are read and turned into write batches on worker threads, ahead of
+    /// the batches being applied in order.
+    pub fn index_output_value_until(&mut self, height: u64) -> Result<(), OrdiError> {
+        let from = match self.output_value_height()? {
+            Some(output_value_height) => output_value_height + 1,
+            None => 0,
+        };
+        if height >= self.chain.first_inscription_height() {
+            return Err(OrdiError::OutputValueHeight(from.checked_sub(1), height));
+        }
+        if from > height {
+            return Ok(());
+        }
+
+        let locations = self.index.block_locations(from, height)?;
+        let total_txs = locations
+            .iter()
+            .filter_map(|l| self.index.get_index_entry(l.height).ok().flatten())
+            .map(|entry| entry.tx_count)
+            .sum::<u64>();
+        let batches = BlockPrefetcher::new(
+            self.index.files.clone(),
+            locations,
+            self.prefetch_blocks,
+            |_, block| (block.txs.len() as u64, output_value_batch(&block)),
+        );
+
+        let start = std::time::Instant::now();
+        let mut last_report = start;
+        let mut indexed_txs = 0;
+        let mut unflushed_blocks = 0;
+        for batch in batches {
+            let (h, (tx_count, wb)) = batch.map_err(IndexError::from)?;
+            self.output_value.write(wb)?;
+            indexed_txs += tx_count;
+            unflushed_blocks += 1;
+
+            if unflushed_blocks >= self.catch_up_flush_blocks
+                || self.output_value.pending_size() >= self.catch_up_flush_mb << 20
+            {
+                self.output_value.flush()?;
+                self.set_output_value_height(h)?;
+                unflushed_blocks = 0;
+            }
+
+            if last_report.elapsed() >= PROGRESS_INTERVAL {
+                last_report = std::time::Instant::now();
+                // Transaction counts are unknown in scan mode for AuxPoW headers.
+                if total_txs == 0 {
+                    info!("Indexed output values until height: {}/{}.", h, height);
+                } else {
+                    let eta = start.elapsed().as_secs_f64() / indexed_txs.max(1) as f64
+                        * total_txs.saturating_sub(indexed_txs) as f64;
+                    info!(
+                        "Indexed output values until height: {}/{}, {:.2}% of transactions, eta: {}s.",
+                        h,
+                        height,
+                        indexed_txs as f64 / total_txs as f64 * 100.0,
+                        eta as u64
+                    );
+                }
             }
         }
 
+        if unflushed_blocks > 0 {
+            self.output_value.flush()?;
+            self.set_output_value_height(height)?;
+        }
+        if self.output_value_height()? != Some(height) {
+            return Err(OrdiError::OutputValueHeight(
+                self.output_value_height()?,
+                height,
+            ));
+        }
+        info!(
+            "Indexed output values until height: {}, cost: {}s.",
+            height,
+            start.elapsed().as_secs()
+        );
+
         Ok(())
     }
 
-    fn index_output_value_in_transaction(&mut self, tx: &Tx) -> Result<(), OrdiError> {
-        let mut wb = WriteBatch::new();
+    /// Fills `output_value` from the chainstate of a bitcoin node whose best block
+    /// is the one before the first inscription, e.g. stopped with `-stopatheight=767429`.
+    /// Does nothing once output values reach that block.
+    pub fn index_output_value_from_chainstate(&mut self) -> Result<(), OrdiError> {
+        let expected_height = self.chain.first_inscription_height().saturating_sub(1);
+        if self.output_value_height()? >= Some(expected_height) {
+            return Ok(());
+        }
+        self.ensure_not_indexed()?;
+
+        let mut chainstate = Chainstate::open(&self.index.btc_data_dir)?;
+
+        let best_block = chainstate.best_block()?;
+        let best_height = self.index.height_of(&best_block)?;
+        if best_height != Some(expected_height) {
+            return Err(OrdiError::ChainstateHeightMismatch(
+                best_height,
+                expected_height,
+            ));
+        }
+
+        self.clear_output_value()?;
+
+        let mut count = 0u64;
+        for utxo in chainstate.iter()? {
+            let utxo = utxo?;
+            self.output_value.put(
+                &utxo.outpoint.to_bytes(),
+                utxo.value.to_le_bytes().as_slice(),
+            )?;
+            count += 1;
+
+            if self.output_value.pending_size() >= self.catch_up_flush_mb << 20 {
+                self.output_value.flush()?;
+                info!("Indexed {} output values from chainstate.", count);
+            }
+        }
+        self.output_value.flush()?;
+        self.set_output_value_height(expected_height)?;
+        info!("Indexed {} output values from chainstate.", count);
+
+        Ok(())
+    }
+
+    /// Inscription id of inscription `number`, cursed inscriptions have negative numbers.
+    pub fn inscription_id(&mut self, number: i64) -> Result<Option<InscriptionId>, OrdiError> {
+        match self.id_inscription.get(number.to_le_bytes().as_slice())? {
+            Some(value) => Ok(Some(InscriptionId::from_bytes(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Current location of an inscription.
+    pub fn inscription_satpoint(
+        &mut self,
+        inscription_id: &InscriptionId,
+    ) -> Result<Option<SatPoint>, OrdiError> {
+        match self.inscription_output.get(&inscription_id.to_bytes())? {
+            Some(value) => Ok(Some(SatPoint::from_bytes(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Inscriptions held by an output, with their offsets in the output.
+    pub fn output_inscriptions(
+        &mut self,
+        outpoint: &OutPoint,
+    ) -> Result<Vec<(InscriptionId, u64)>, OrdiError> {
+        match self.output_inscription.get(&outpoint.to_bytes())? {
+            Some(value) => Ok(decode_output_inscriptions(&value).map_err(BlockUpdaterError::from)?),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// BRC-20 tokens and balances, `None` unless `Options::brc20` is set.
+    pub fn brc20(&mut self) -> Option<Brc20Ledger<'_>> {
+        self.protocols
+            .brc20
+            .as_mut()
+            .map(|brc20| Brc20Ledger::new(brc20 as &mut dyn Store))
+    }
+
+    /// Bitmap claims and their owners, `None` unless `Options::bitmap` is set.
+    pub fn bitmap(&mut self) -> Option<BitmapIndex<'_>> {
+        self.protocols
+            .bitmap
+            .as_mut()
+            .map(|bitmap| BitmapIndex::new(bitmap as &mut dyn Store))
+    }
+
+    /// `.sats` names, `None` unless `Options::sns` is set.
+    pub fn sns(&mut self) -> Option<SnsIndex<'_>> {
+        self.protocols
+            .sns
+            .as_mut()
+            .map(|sns| SnsIndex::new(sns as &mut dyn Store))
+    }
+
+    /// Address holding a `.sats` name: where `inscription_output` has its
+    /// inscription, with the output's script from the node. `None` if the
+    /// name is not registered or its holder has no address.
+    pub fn sats_name_holder(&mut self, name: &str) -> Result<Option<String>, OrdiError> {
+        let Some(registration) = self
+            .sns()
+            .map(|mut sns| sns.name(name))
+            .transpose()?
+            .flatten()
+        else {
+            return Ok(None);
+        };
+        let Some(satpoint) = self.inscription_satpoint(&registration.inscription_id)? else {
+            return Ok(None);
+        };
+        let outpoint = satpoint.outpoint;
+        if outpoint.is_null() || outpoint == OutPoint::unbound() {
+            return Ok(None);
+        }
+
+        let btc_rpc_client = self
+            .btc_rpc_client
+            .as_ref()
+            .ok_or(OrdiError::OutputAddressNotFound(outpoint))?;
+        let tx = btc_rpc_client.get_raw_transaction(&outpoint.txid, None)?;
+        let output = tx
+            .output
+            .get(outpoint.vout as usize)
+            .ok_or(OrdiError::OutputAddressNotFound(outpoint))?;
+        Ok(script::eval_from_bytes(
+            output.script_pubkey.as_bytes(),
+            self.index.coin.address_encoding(),
+        )
+        .address)
+    }
+
+    /// Runes and their balances, `None` unless `Options::runes` is set.
+    pub fn runes(&mut self) -> Option<RuneIndex<'_>> {
+        self.protocols
+            .runes
+            .as_mut()
+            .map(|runes| RuneIndex::new(runes as &mut dyn Store))
+    }
+
+    /// SRC-20 tokens and balances, `None` unless `Options::src20` is set.
+    pub fn src20(&mut self) -> Option<Src20Ledger<'_>> {
+        self.protocols
+            .src20
+            .as_mut()
+            .map(|src20| Src20Ledger::new(src20 as &mut dyn Store))
+    }
+
+    pub fn when_inscribe(&mut self, f: InscribeUpdater) {
+        self.inscribe_updaters.push(f);
+    }
+
+    pub fn when_transfer(&mut self, f: TransferUpdater) {
+        self.transfer_updaters.push(f);
+    }
+
+    pub fn when_drc20(&mut self, f: Drc20Updater) {
+        self.drc20_updaters.push(f);
+    }
+
+    pub fn when_rune(&mut self, f: RuneUpdater) {
+        self.rune_updaters.push(f);
+    }
+}
+
+/// Opens the journal of `ordi_data_dir`, replaying a flush it still holds.
+fn open_journal(engine: &StorageEngine, ordi_data_dir: &Path) -> Result<Journal, OrdiError> {
+    let (journal, replayed) = Journal::open(engine, ordi_data_dir)?;
+    if replayed > 0 {
+        info!("Replayed {} entries of an interrupted flush.", replayed);
+    }
+    Ok(journal)
+}
+
+/// Output values created and spent by `block`, in transaction order.
+fn output_value_batch(block: &ProtoBlock) -> WriteBatch {
+    let mut wb = WriteBatch::new();
+    for tx in block.txs.iter() {
+        let txid = ::bitcoin::Txid::from_raw_hash(tx.hash);
         for (output_index, output) in tx.value.outputs.iter().enumerate() {
-            let k = format!("{}:{}", tx.hash.to_string(), output_index);
-            wb.put(k.as_bytes(), output.out.value.to_le_bytes().as_slice());
+            let k = OutPoint::new(txid, output_index as u32).to_bytes();
+            wb.put(&k, output.out.value.to_le_bytes().as_slice());
         }
 
         for input in tx.value.inputs.iter() {
@@ -208,27 +988,133 @@ impl Ordi {
                 continue;
             }
 
-            let k = format!(
-                "{}:{}",
-                input.outpoint.txid.to_string(),
-                input.outpoint.index
-            );
-            wb.delete(k.as_bytes())
+            let k = OutPoint::from(&input.outpoint).to_bytes();
+            wb.delete(&k)
         }
+    }
+
+    wb
+}
+
+/// Keyspaces of the protocols indexed along inscriptions, each `None` unless
+/// enabled in `Options`.
+#[derive(Default)]
+pub struct ProtocolStores {
+    pub brc20: Option<BufferedStore>,
+    pub bitmap: Option<BufferedStore>,
+    pub sns: Option<BufferedStore>,
+    pub runes: Option<BufferedStore>,
+    pub src20: Option<BufferedStore>,
+}
+
+impl ProtocolStores {
+    /// Opens the enabled keyspaces, indexing goes on at `next_height`.
+    fn open(
+        engine: &StorageEngine,
+        ordi_data_dir: &Path,
+        options: &Options,
+        chain: &Chain,
+        next_height: u64,
+    ) -> Result<ProtocolStores, OrdiError> {
+        let open = |enabled: bool,
+                    name: &'static str,
+                    start_height: u64|
+         -> Result<Option<BufferedStore>, OrdiError> {
+            if !enabled {
+                return Ok(None);
+            }
+
+            let mut store = engine.open(ordi_data_dir, name)?;
+            ensure_kept_from(store.as_mut(), name, next_height, start_height)?;
+            Ok(Some(BufferedStore::new(store)))
+        };
 
-        self.output_value.write(wb, false)?;
+        let first_inscription_height = chain.first_inscription_height();
+        let first_rune_height = chain
+            .first_rune_height()
+            .map_or(u64::MAX, |height| height.max(first_inscription_height));
+        Ok(ProtocolStores {
+            brc20: open(options.brc20, ORDI_BRC20, first_inscription_height)?,
+            bitmap: open(options.bitmap, ORDI_BITMAP, first_inscription_height)?,
+            sns: open(options.sns, ORDI_SNS, first_inscription_height)?,
+            runes: open(options.runes, ORDI_RUNES, first_rune_height)?,
+            src20: open(options.src20, ORDI_SRC20, first_inscription_height)?,
+        })
+    }
+
+    /// Drops every keyspace, enabled or not, when the inscriptions they are
+    /// derived from are reindexed.
+    fn clear(engine: &StorageEngine, ordi_data_dir: &Path) -> Result<(), StoreError> {
+        for name in [ORDI_BRC20, ORDI_BITMAP, ORDI_SNS, ORDI_RUNES, ORDI_SRC20] {
+            let mut store = engine.open(ordi_data_dir, name)?;
+            store::clear(store.as_mut())?;
+            store.flush()?;
+            store.close()?;
+        }
         Ok(())
     }
 
-    pub fn when_inscribe(&mut self, f: InscribeUpdater) {
-        self.inscribe_updaters.push(f);
+    fn iter_mut(&mut self) -> impl Iterator<Item = (&'static str, &mut BufferedStore)> {
+        [
+            (ORDI_BRC20, self.brc20.as_mut()),
+            (ORDI_BITMAP, self.bitmap.as_mut()),
+            (ORDI_SNS, self.sns.as_mut()),
+            (ORDI_RUNES, self.runes.as_mut()),
+            (ORDI_SRC20, self.src20.as_mut()),
+        ]
+        .into_iter()
+        .filter_map(|(name, store)| store.map(|store| (name, store)))
     }
 
-    pub fn when_transfer(&mut self, f: TransferUpdater) {
-        self.transfer_updaters.push(f);
+    fn close(&mut self) {
+        for (name, store) in self.iter_mut() {
+            store
+                .close()
+                .unwrap_or_else(|e| panic!("Close {} db: {}", name, e));
+        }
+    }
+
+    /// Size of writes not flushed yet.
+    pub fn pending_size(&self) -> usize {
+        [
+            &self.brc20,
+            &self.bitmap,
+            &self.sns,
+            &self.runes,
+            &self.src20,
+        ]
+        .into_iter()
+        .flatten()
+        .map(BufferedStore::pending_size)
+        .sum()
     }
 }
 
+/// Records the height a protocol keyspace is kept from when it is created, and
+/// checks it is kept from `start_height` on, where its protocol starts. A
+/// keyspace enabled after that on an indexed data directory would miss blocks.
+fn ensure_kept_from(
+    store: &mut dyn Store,
+    name: &'static str,
+    next_height: u64,
+    start_height: u64,
+) -> Result<(), OrdiError> {
+    let kept_from = match store.get(PROTOCOL_START_HEIGHT)? {
+        Some(value) => value.try_into().map_or(u64::MAX, u64::from_le_bytes),
+        None => next_height,
+    };
+    if kept_from > start_height {
+        return Err(OrdiError::ProtocolStartHeight(
+            name,
+            kept_from,
+            start_height,
+        ));
+    }
+
+    store.put(PROTOCOL_START_HEIGHT, kept_from.to_le_bytes().as_slice())?;
+    Ok(store.flush()?)
+}
+
 impl Drop for Ordi {
     fn drop(&mut self) {
         info!("Start closing Ordi instance.");
@@ -241,18 +1127,286 @@ impl Drop for Ordi {
 pub enum Origin {
     New {
         cursed: bool,
+        /// Would have been cursed before the jubilee.
+        vindicated: bool,
         unbound: bool,
         inscription: Inscription,
     },
     Old {
-        old_output: String,
-        old_offset: u64,
+        old_satpoint: SatPoint,
     },
 }
 
 #[derive(Clone)]
 pub struct Flotsam {
-    pub inscription_id: String,
+    pub inscription_id: InscriptionId,
     pub offset: u64,
     pub origin: Origin,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::BitcoinRegtest;
+    use crate::chain::ChainParams;
+    use crate::source::MemoryBlockSource;
+    use crate::store::MemoryStore;
+    use ::bitcoin::blockdata::constants::genesis_block;
+    use ::bitcoin::consensus::serialize;
+    use ::bitcoin::hashes::{sha256d, Hash};
+    use ::bitcoin::{BlockHash, Network, Txid};
+
+    /// Fails its first `failures` calls, like bitcoind while it restarts.
+    struct FlakySource {
+        source: MemoryBlockSource,
+        failures: usize,
+    }
+
+    impl FlakySource {
+        fn fail(&mut self) -> Result<(), BlockSourceError> {
+            if self.failures == 0 {
+                return Ok(());
+            }
+            self.failures -= 1;
+            Err(bitcoincore_rpc::Error::ReturnedError("connection refused".to_string()).into())
+        }
+    }
+
+    impl BlockSource for FlakySource {
+        fn tip(&mut self) -> Result<Option<u64>, BlockSourceError> {
+            self.fail()?;
+            self.source.tip()
+        }
+
+        fn block_hash(&mut self, height: u64) -> Result<Option<sha256d::Hash>, BlockSourceError> {
+            self.fail()?;
+            self.source.block_hash(height)
+        }
+
+        fn block(&mut self, height: u64) -> Result<Option<ProtoBlock>, BlockSourceError> {
+            self.fail()?;
+            self.source.block(height)
+        }
+
+        fn block_by_hash(
+            &mut self,
+            block_hash: &sha256d::Hash,
+        ) -> Result<Option<ProtoBlock>, BlockSourceError> {
+            self.fail()?;
+            self.source.block_by_hash(block_hash)
+        }
+    }
+
+    /// Offline regtest indexer over an empty blocks directory.
+    fn regtest_options(dir: &Path, storage_engine: &str) -> Options {
+        fs::create_dir_all(dir.join("btc").join("regtest").join("blocks")).unwrap();
+        Options {
+            btc_data_dir: dir.join("btc").to_string_lossy().to_string(),
+            ordi_data_dir: dir.join("ordi").to_string_lossy().to_string(),
+            chain: Some(Chain::Regtest),
+            offline: true,
+            index_mode: "scan".to_string(),
+            storage_engine: storage_engine.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_follow_retries_failing_source() {
+        let dir = std::env::temp_dir().join(format!("ordi-follow-{}", std::process::id()));
+        let mut ordi = Ordi::new(regtest_options(&dir, "memory")).unwrap();
+
+        let memory = MemoryBlockSource::new(0, Chain::Regtest.coin());
+        memory.push(genesis_block(Network::Regtest));
+        let mut source = FlakySource {
+            source: memory,
+            failures: 2,
+        };
+
+        let mut retry_interval = Duration::from_millis(1);
+        assert!(!ordi
+            .follow_retrying(&mut source, &mut retry_interval)
+            .unwrap());
+        assert_eq!(retry_interval, Duration::from_millis(2));
+        assert!(!ordi
+            .follow_retrying(&mut source, &mut retry_interval)
+            .unwrap());
+        assert_eq!(retry_interval, Duration::from_millis(4));
+        assert_eq!(ordi.indexed_height().unwrap(), None);
+
+        assert!(ordi
+            .follow_retrying(&mut source, &mut retry_interval)
+            .unwrap());
+        assert_eq!(retry_interval, RETRY_INTERVAL);
+        assert_eq!(ordi.indexed_height().unwrap(), Some(0));
+
+        drop(ordi);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_protocol_kept_from_start() {
+        let mut store = MemoryStore::new();
+        ensure_kept_from(&mut store, ORDI_BRC20, 100, 100).unwrap();
+        // Reopened later, it still holds every block from 100.
+        ensure_kept_from(&mut store, ORDI_BRC20, 200, 100).unwrap();
+        assert_eq!(
+            store.get(PROTOCOL_START_HEIGHT).unwrap(),
+            Some(100u64.to_le_bytes().to_vec())
+        );
+
+        // Enabled mid-chain, or kept from a later height.
+        let mut store = MemoryStore::new();
+        assert!(matches!(
+            ensure_kept_from(&mut store, ORDI_BRC20, 200, 100),
+            Err(OrdiError::ProtocolStartHeight(ORDI_BRC20, 200, 100))
+        ));
+        assert_eq!(store.get(PROTOCOL_START_HEIGHT).unwrap(), None);
+
+        // Runes starting after the indexed height can still be enabled.
+        let mut store = MemoryStore::new();
+        ensure_kept_from(&mut store, ORDI_RUNES, 200, 300).unwrap();
+        assert!(matches!(
+            ensure_kept_from(&mut store, ORDI_RUNES, 400, 150),
+            Err(OrdiError::ProtocolStartHeight(ORDI_RUNES, 200, 150))
+        ));
+    }
+
+    #[test]
+    fn test_recover_interrupted_flush() {
+        let dir = std::env::temp_dir().join(format!("ordi-recover-{}", std::process::id()));
+        let options = regtest_options(&dir, "leveldb");
+
+        let mut ordi = Ordi::new(options.clone()).unwrap();
+        let source = MemoryBlockSource::new(0, Chain::Regtest.coin());
+        let genesis = genesis_block(Network::Regtest);
+        let mut next = genesis.clone();
+        next.header.prev_blockhash = genesis.block_hash();
+        next.header.nonce = 1;
+        source.push(genesis);
+        source.push(next.clone());
+        assert_eq!(ordi.index_from(&mut source.clone()).unwrap(), 2);
+
+        // Crashed while flushing height 2, once the journal was written.
+        let mut last = next.clone();
+        last.header.prev_blockhash = next.block_hash();
+        last.header.nonce = 2;
+        source.push(last);
+        let block = source.clone().block(2).unwrap().unwrap();
+        ordi.index_block(2, block).unwrap();
+        let keyspaces = [
+            (ORDI_STATUS, &mut ordi.status),
+            (ORDI_OUTPUT_VALUE, &mut ordi.output_value),
+        ];
+        ordi.journal.record(&keyspaces).unwrap();
+        drop(ordi);
+
+        let mut ordi = Ordi::new(options.clone()).unwrap();
+        assert_eq!(ordi.indexed_height().unwrap(), Some(2));
+        drop(ordi);
+
+        Ordi::reindex(&options).unwrap();
+        let mut ordi = Ordi::new(options).unwrap();
+        assert_eq!(ordi.indexed_height().unwrap(), None);
+
+        drop(ordi);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_output_value_from_chainstate_once() {
+        let dir = std::env::temp_dir().join(format!("ordi-chainstate-{}", std::process::id()));
+        let mut ordi = Ordi::new(regtest_options(&dir, "memory")).unwrap();
+
+        // Output values indexed on from the chainstate along inscriptions, the
+        // chainstate is not read again.
+        let height = Chain::Regtest.first_inscription_height() + 10;
+        ordi.set_output_value_height(height).unwrap();
+        ordi.status
+            .put(INDEXED_HEIGHT.as_bytes(), height.to_le_bytes().as_slice())
+            .unwrap();
+        ordi.index_output_value_from_chainstate().unwrap();
+        assert_eq!(ordi.output_value_height().unwrap(), Some(height));
+
+        drop(ordi);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_restore_snapshot() {
+        let dir = std::env::temp_dir().join(format!("ordi-restore-{}", std::process::id()));
+        let mut options = regtest_options(&dir, "memory");
+        options.chain = Some(
+            ChainParams {
+                data_subfolder: "regtest".to_string(),
+                first_inscription_height: 2,
+                ..ChainParams::new(BitcoinRegtest)
+            }
+            .into(),
+        );
+
+        // Blocks 0 and 1 in blk files, snapshots are restored at 1.
+        let genesis = genesis_block(Network::Regtest);
+        let mut next = genesis.clone();
+        next.header.prev_blockhash = genesis.block_hash();
+        next.header.nonce = 1;
+        let mut blk = vec![];
+        for block in [&genesis, &next] {
+            let block = serialize(block);
+            blk.extend_from_slice(&Chain::Regtest.coin().magic.to_le_bytes());
+            blk.extend_from_slice(&(block.len() as u32).to_le_bytes());
+            blk.extend_from_slice(&block);
+        }
+        fs::write(
+            dir.join("btc")
+                .join("regtest")
+                .join("blocks")
+                .join("blk00000.dat"),
+            blk,
+        )
+        .unwrap();
+        let mut ordi = Ordi::new(options).unwrap();
+
+        let mut output_value = MemoryStore::new();
+        output_value
+            .put(
+                &OutPoint::new(Txid::all_zeros(), 0).to_bytes(),
+                546u64.to_le_bytes().as_slice(),
+            )
+            .unwrap();
+        let mut create = |name: &str, height: u64, block_hash: BlockHash| {
+            let snapshot_dir = dir.join(name);
+            snapshot::create(
+                &mut output_value,
+                height,
+                block_hash.to_string(),
+                &snapshot_dir,
+            )
+            .unwrap();
+            snapshot_dir
+        };
+
+        // Before the block preceding the first inscription.
+        let early = create("early", 0, genesis.block_hash());
+        assert!(matches!(
+            ordi.restore_snapshot(&early),
+            Err(OrdiError::OutputValueHeight(Some(0), 1))
+        ));
+
+        // Of a block which is not in the node's chain.
+        let stale = create("stale", 1, BlockHash::all_zeros());
+        assert!(matches!(
+            ordi.restore_snapshot(&stale),
+            Err(OrdiError::SnapshotBlockHashMismatch(_, block_hash))
+                if block_hash == next.block_hash().to_string()
+        ));
+        assert_eq!(ordi.output_value_height().unwrap(), None);
+
+        let snapshot_dir = create("snapshot", 1, next.block_hash());
+        assert_eq!(ordi.restore_snapshot(&snapshot_dir).unwrap().entries, 1);
+        assert_eq!(ordi.output_value_height().unwrap(), Some(1));
+
+        drop(ordi);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
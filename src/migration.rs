@@ -0,0 +1,545 @@
+//! One-time conversion of data directories written with the textual storage layout.
+//!
+//! Before storage version 1, keys and values were formatted strings:
+//! `output_value` keys were `<txid>:<vout>`, `output_inscription` values were
+//! `/<inscription_id>:<offset>/...`, and `inscription_output`/`id_inscription`
+//! stored displayed ids and locations.
+//!
+//! Before storage version 2, `output_inscription` was kept in memory and
+//! rebuilt on every start, so inscriptions indexed before are reindexed, and
+//! output values with them.
+//!
+//! Before storage version 3, inscriptions that would have been cursed were
+//! numbered as cursed after the jubilee too, so inscriptions indexed past the
+//! jubilee are reindexed.
+
+use log::info;
+use thiserror::Error;
+
+use crate::block::{decode_output_inscriptions, encode_output_inscriptions, INDEXED_HEIGHT};
+use crate::inscription_id::{InscriptionId, InscriptionIdError};
+use crate::outpoint::OutPoint;
+use crate::sat_point::SatPoint;
+use crate::store::{self, Store, StoreError, WriteBatch};
+
+const STORAGE_VERSION: &str = "storage_version";
+pub const CURRENT_STORAGE_VERSION: u64 = 3;
+const MIGRATION_BATCH_SIZE: usize = 100_000;
+
+#[derive(Error, Debug)]
+pub enum MigrationError {
+    #[error("Store error: `{0}`")]
+    StoreError(#[from] StoreError),
+    #[error("Inscription id error: `{0}`")]
+    InscriptionIdError(#[from] InscriptionIdError),
+    #[error("Malformed entry: `{0}`")]
+    MalformedEntry(String),
+    #[error("Unsupported storage version: `{0}`")]
+    UnsupportedVersion(u64),
+    #[error("Offset of inscription `{0}` is unknown, ordi data directory has to be reindexed")]
+    UnknownOffset(InscriptionId),
+}
+
+/// Converts every keyspace to the current layout, once per data directory.
+/// Returns whether indexed inscriptions were dropped to be reindexed, along
+/// with whatever was derived from them.
+pub fn migrate(
+    status: &mut dyn Store,
+    output_value: &mut dyn Store,
+    id_inscription: &mut dyn Store,
+    inscription_output: &mut dyn Store,
+    output_inscription: &mut dyn Store,
+    jubilee_height: u64,
+) -> Result<bool, MigrationError> {
+    let version = status
+        .get(STORAGE_VERSION.as_bytes())?
+        .and_then(|v| v.try_into().ok())
+        .map(u64::from_le_bytes)
+        .unwrap_or(0);
+
+    if version > CURRENT_STORAGE_VERSION {
+        return Err(MigrationError::UnsupportedVersion(version));
+    }
+
+    let indexed_height = status
+        .get(INDEXED_HEIGHT.as_bytes())?
+        .and_then(|v| v.try_into().ok())
+        .map(u64::from_le_bytes);
+    let reindex = match indexed_height {
+        Some(_) if version < 2 => {
+            info!(
+                "Migrating data directory to storage version {}, inscriptions will be reindexed.",
+                CURRENT_STORAGE_VERSION
+            );
+            true
+        }
+        Some(height) if version < 3 && height >= jubilee_height => {
+            info!("Migrating data directory to storage version 3, inscriptions will be reindexed.");
+            true
+        }
+        _ => false,
+    };
+
+    // Entries about to be dropped are not converted, inscriptions indexed in
+    // memory have no `output_inscription` entries to take offsets from.
+    if reindex {
+        reset(
+            status,
+            output_value,
+            id_inscription,
+            inscription_output,
+            output_inscription,
+        )?;
+    } else if version < 1 {
+        info!("Migrating data directory to storage version 1.");
+
+        let count = migrate_entries(output_value, |k, v| {
+            Ok(parse_legacy_outpoint(k).map(|outpoint| (outpoint.to_bytes(), v.to_vec())))
+        })?;
+        info!("Migrated {} output_value entries.", count);
+
+        let count = migrate_entries(output_inscription, |k, v| {
+            let Some(outpoint) = parse_legacy_outpoint(k) else {
+                return Ok(None);
+            };
+            let inscriptions = parse_legacy_output_inscriptions(&String::from_utf8_lossy(v))?;
+            Ok(Some((
+                outpoint.to_bytes(),
+                encode_output_inscriptions(&inscriptions),
+            )))
+        })?;
+        info!("Migrated {} output_inscription entries.", count);
+
+        let count = migrate_entries(inscription_output, |k, v| {
+            let Some(inscription_id) = std::str::from_utf8(k)
+                .ok()
+                .and_then(|k| k.parse::<InscriptionId>().ok())
+            else {
+                return Ok(None);
+            };
+            let Some((outpoint, offset)) = parse_legacy_satpoint(v) else {
+                return Ok(None);
+            };
+            let offset = match offset {
+                Some(offset) => offset,
+                None => bound_offset(output_inscription, outpoint, inscription_id)?,
+            };
+            Ok(Some((
+                inscription_id.to_bytes(),
+                SatPoint::new(outpoint, offset).to_bytes(),
+            )))
+        })?;
+        info!("Migrated {} inscription_output entries.", count);
+
+        let count = migrate_entries(id_inscription, |k, v| {
+            Ok(std::str::from_utf8(v)
+                .ok()
+                .and_then(|v| v.parse::<InscriptionId>().ok())
+                .map(|inscription_id| (k.to_vec(), inscription_id.to_bytes())))
+        })?;
+        info!("Migrated {} id_inscription entries.", count);
+    }
+
+    status.put(
+        STORAGE_VERSION.as_bytes(),
+        CURRENT_STORAGE_VERSION.to_le_bytes().as_slice(),
+    )?;
+    status.flush()?;
+
+    Ok(reindex)
+}
+
+/// Drops indexed inscriptions along with output values, which were updated up
+/// to the same height, so the data directory is indexed again from scratch.
+pub fn reset(
+    status: &mut dyn Store,
+    output_value: &mut dyn Store,
+    id_inscription: &mut dyn Store,
+    inscription_output: &mut dyn Store,
+    output_inscription: &mut dyn Store,
+) -> Result<(), MigrationError> {
+    // Everything in status is inscription state, counters, cursed numbers and
+    // heights, the storage version is kept.
+    store::clear(status)?;
+    store::clear(output_value)?;
+    store::clear(id_inscription)?;
+    store::clear(inscription_output)?;
+    store::clear(output_inscription)?;
+
+    status.put(
+        STORAGE_VERSION.as_bytes(),
+        CURRENT_STORAGE_VERSION.to_le_bytes().as_slice(),
+    )?;
+    status.flush()?;
+    Ok(())
+}
+
+/// Rewrites every entry for which `convert` returns a new key and value.
+/// Entries already in the new layout are expected to return `None`.
+///
+/// Entries are collected in batches by a scan and written between scans,
+/// each scan resuming after the last key visited.
+fn migrate_entries<F>(db: &mut dyn Store, mut convert: F) -> Result<u64, MigrationError>
+where
+    F: FnMut(&[u8], &[u8]) -> Result<Option<(Vec<u8>, Vec<u8>)>, MigrationError>,
+{
+    let mut from = vec![];
+    let mut count = 0;
+
+    loop {
+        let mut wb = WriteBatch::new();
+        let mut last_key = None;
+        let mut visited = 0;
+        let mut result = Ok(());
+
+        db.scan(&from, &mut |key, value| {
+            visited += 1;
+            last_key = Some(key.to_vec());
+            match convert(key, value) {
+                Ok(Some((new_key, new_value))) => {
+                    wb.delete(key);
+                    wb.put(&new_key, &new_value);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    result = Err(e);
+                    return false;
+                }
+            }
+
+            visited < MIGRATION_BATCH_SIZE
+        })?;
+        result?;
+
+        let converted = (wb.count() / 2) as u64;
+        if !wb.is_empty() {
+            db.write(wb)?;
+            count += converted;
+            info!("Migrated {} entries.", count);
+        }
+
+        match last_key {
+            Some(key) if visited >= MIGRATION_BATCH_SIZE => from = store::successor(&key),
+            _ => break,
+        }
+    }
+    db.flush()?;
+
+    Ok(count)
+}
+
+fn parse_legacy_outpoint(bytes: &[u8]) -> Option<OutPoint> {
+    std::str::from_utf8(bytes).ok()?.parse().ok()
+}
+
+/// Locations were stored as `<txid>:<vout>` for bound inscriptions, whose
+/// offset is unknown, and `<txid>:<vout>:<offset>` for unbound ones.
+fn parse_legacy_satpoint(bytes: &[u8]) -> Option<(OutPoint, Option<u64>)> {
+    let s = std::str::from_utf8(bytes).ok()?;
+    match s.parse::<SatPoint>() {
+        Ok(satpoint) => Some((satpoint.outpoint, Some(satpoint.offset))),
+        Err(_) => s.parse::<OutPoint>().ok().map(|outpoint| (outpoint, None)),
+    }
+}
+
+/// Offset of a bound inscription in `outpoint`, as its already migrated
+/// `output_inscription` entry holds it.
+fn bound_offset(
+    output_inscription: &mut dyn Store,
+    outpoint: OutPoint,
+    inscription_id: InscriptionId,
+) -> Result<u64, MigrationError> {
+    let inscriptions = match output_inscription.get(&outpoint.to_bytes())? {
+        Some(value) => decode_output_inscriptions(&value)
+            .map_err(|_| MigrationError::MalformedEntry(outpoint.to_string()))?,
+        None => vec![],
+    };
+    inscriptions
+        .into_iter()
+        .find(|(id, _)| *id == inscription_id)
+        .map(|(_, offset)| offset)
+        .ok_or(MigrationError::UnknownOffset(inscription_id))
+}
+
+fn parse_legacy_output_inscriptions(
+    value: &str,
+) -> Result<Vec<(InscriptionId, u64)>, MigrationError> {
+    value
+        .split('/')
+        .skip(1)
+        .map(|inscription_offset| {
+            let malformed = || MigrationError::MalformedEntry(value.to_string());
+            let (inscription_id, offset) =
+                inscription_offset.split_once(':').ok_or_else(malformed)?;
+
+            Ok((
+                inscription_id.parse::<InscriptionId>()?,
+                offset.parse::<u64>().map_err(|_| malformed())?,
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+
+    const TXID: &str = "6fb976ab49dcec017f1e201e84395983204ae1a7c2abf7ced0a85d692e442799";
+    const JUBILEE_HEIGHT: u64 = 824544;
+
+    #[test]
+    fn test_migrate_output_value() {
+        let mut status = MemoryStore::new();
+        let mut output_value = MemoryStore::new();
+        let mut id_inscription = MemoryStore::new();
+        let mut inscription_output = MemoryStore::new();
+        let mut output_inscription = MemoryStore::new();
+
+        let outpoint = format!("{}:1", TXID);
+        output_value
+            .put(outpoint.as_bytes(), 546u64.to_le_bytes().as_slice())
+            .unwrap();
+        output_inscription
+            .put(outpoint.as_bytes(), format!("/{}i0:10", TXID).as_bytes())
+            .unwrap();
+
+        assert!(!migrate(
+            &mut status,
+            &mut output_value,
+            &mut id_inscription,
+            &mut inscription_output,
+            &mut output_inscription,
+            JUBILEE_HEIGHT,
+        )
+        .unwrap());
+
+        let outpoint = outpoint.parse::<OutPoint>().unwrap();
+        assert_eq!(
+            output_value.get(outpoint.to_string().as_bytes()).unwrap(),
+            None
+        );
+        assert_eq!(
+            output_value.get(&outpoint.to_bytes()).unwrap(),
+            Some(546u64.to_le_bytes().to_vec())
+        );
+        assert_eq!(
+            output_inscription.get(&outpoint.to_bytes()).unwrap(),
+            Some(encode_output_inscriptions(&[(
+                format!("{}i0", TXID).parse().unwrap(),
+                10
+            )]))
+        );
+    }
+
+    #[test]
+    fn test_migrate_resets_inscriptions_indexed_in_memory() {
+        let mut status = MemoryStore::new();
+        let mut output_value = MemoryStore::new();
+        let mut id_inscription = MemoryStore::new();
+        let mut inscription_output = MemoryStore::new();
+        let mut output_inscription = MemoryStore::new();
+
+        let inscription_id = format!("{}i0", TXID).parse::<InscriptionId>().unwrap();
+        status
+            .put(STORAGE_VERSION.as_bytes(), 1u64.to_le_bytes().as_slice())
+            .unwrap();
+        status
+            .put(
+                INDEXED_HEIGHT.as_bytes(),
+                767430u64.to_le_bytes().as_slice(),
+            )
+            .unwrap();
+        id_inscription
+            .put(0i64.to_le_bytes().as_slice(), &inscription_id.to_bytes())
+            .unwrap();
+        output_value.put(b"output", b"value").unwrap();
+
+        assert!(migrate(
+            &mut status,
+            &mut output_value,
+            &mut id_inscription,
+            &mut inscription_output,
+            &mut output_inscription,
+            JUBILEE_HEIGHT,
+        )
+        .unwrap());
+
+        assert_eq!(status.get(INDEXED_HEIGHT.as_bytes()).unwrap(), None);
+        assert_eq!(
+            status.get(STORAGE_VERSION.as_bytes()).unwrap(),
+            Some(CURRENT_STORAGE_VERSION.to_le_bytes().to_vec())
+        );
+        assert_eq!(
+            id_inscription.get(0i64.to_le_bytes().as_slice()).unwrap(),
+            None
+        );
+        // Spent outputs are gone from output values, they are indexed again too.
+        assert_eq!(output_value.get(b"output").unwrap(), None);
+    }
+
+    #[test]
+    fn test_migrate_resets_legacy_inscriptions_without_output_inscription() {
+        let mut status = MemoryStore::new();
+        let mut id_inscription = MemoryStore::new();
+        let mut inscription_output = MemoryStore::new();
+
+        let bound = format!("{}i0", TXID);
+        status
+            .put(
+                INDEXED_HEIGHT.as_bytes(),
+                767430u64.to_le_bytes().as_slice(),
+            )
+            .unwrap();
+        inscription_output
+            .put(bound.as_bytes(), format!("{}:1", TXID).as_bytes())
+            .unwrap();
+        id_inscription
+            .put(0i64.to_le_bytes().as_slice(), bound.as_bytes())
+            .unwrap();
+
+        // Bound offsets are not looked up in the empty `output_inscription`,
+        // the entries are dropped instead.
+        assert!(migrate(
+            &mut status,
+            &mut MemoryStore::new(),
+            &mut id_inscription,
+            &mut inscription_output,
+            &mut MemoryStore::new(),
+            JUBILEE_HEIGHT,
+        )
+        .unwrap());
+
+        assert_eq!(status.get(INDEXED_HEIGHT.as_bytes()).unwrap(), None);
+        assert_eq!(inscription_output.get(bound.as_bytes()).unwrap(), None);
+        assert_eq!(
+            id_inscription.get(0i64.to_le_bytes().as_slice()).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_migrate_resets_inscriptions_indexed_past_jubilee() {
+        let migrate_indexed_to = |height: u64| {
+            let mut status = MemoryStore::new();
+            let mut id_inscription = MemoryStore::new();
+            status
+                .put(STORAGE_VERSION.as_bytes(), 2u64.to_le_bytes().as_slice())
+                .unwrap();
+            status
+                .put(INDEXED_HEIGHT.as_bytes(), height.to_le_bytes().as_slice())
+                .unwrap();
+            id_inscription
+                .put(0i64.to_le_bytes().as_slice(), b"inscription")
+                .unwrap();
+
+            let reindex = migrate(
+                &mut status,
+                &mut MemoryStore::new(),
+                &mut id_inscription,
+                &mut MemoryStore::new(),
+                &mut MemoryStore::new(),
+                JUBILEE_HEIGHT,
+            )
+            .unwrap();
+            assert_eq!(
+                status.get(STORAGE_VERSION.as_bytes()).unwrap(),
+                Some(CURRENT_STORAGE_VERSION.to_le_bytes().to_vec())
+            );
+            let indexed = status.get(INDEXED_HEIGHT.as_bytes()).unwrap().is_some();
+            let kept = id_inscription
+                .get(0i64.to_le_bytes().as_slice())
+                .unwrap()
+                .is_some();
+            assert_eq!(indexed, kept);
+            (reindex, kept)
+        };
+
+        assert_eq!(migrate_indexed_to(JUBILEE_HEIGHT - 1), (false, true));
+        assert_eq!(migrate_indexed_to(JUBILEE_HEIGHT), (true, false));
+    }
+
+    #[test]
+    fn test_migrate_inscription_locations() {
+        let mut status = MemoryStore::new();
+        let mut output_value = MemoryStore::new();
+        let mut id_inscription = MemoryStore::new();
+        let mut inscription_output = MemoryStore::new();
+        let mut output_inscription = MemoryStore::new();
+
+        let bound = format!("{}i0", TXID).parse::<InscriptionId>().unwrap();
+        let unbound = format!("{}i1", TXID).parse::<InscriptionId>().unwrap();
+        let outpoint = format!("{}:1", TXID).parse::<OutPoint>().unwrap();
+        output_inscription
+            .put(
+                outpoint.to_string().as_bytes(),
+                format!("/{}:10", bound).as_bytes(),
+            )
+            .unwrap();
+        inscription_output
+            .put(
+                bound.to_string().as_bytes(),
+                outpoint.to_string().as_bytes(),
+            )
+            .unwrap();
+        inscription_output
+            .put(
+                unbound.to_string().as_bytes(),
+                format!("{}:2:5", TXID).as_bytes(),
+            )
+            .unwrap();
+        id_inscription
+            .put(0i64.to_le_bytes().as_slice(), bound.to_string().as_bytes())
+            .unwrap();
+
+        assert!(!migrate(
+            &mut status,
+            &mut output_value,
+            &mut id_inscription,
+            &mut inscription_output,
+            &mut output_inscription,
+            JUBILEE_HEIGHT,
+        )
+        .unwrap());
+
+        assert_eq!(
+            id_inscription.get(0i64.to_le_bytes().as_slice()).unwrap(),
+            Some(bound.to_bytes())
+        );
+        // The offset of a bound inscription comes from its output.
+        assert_eq!(
+            inscription_output.get(&bound.to_bytes()).unwrap(),
+            Some(SatPoint::new(outpoint, 10).to_bytes())
+        );
+        assert_eq!(
+            inscription_output.get(&unbound.to_bytes()).unwrap(),
+            Some(SatPoint::new(OutPoint::new(outpoint.txid, 2), 5).to_bytes())
+        );
+        assert_eq!(
+            inscription_output
+                .get(bound.to_string().as_bytes())
+                .unwrap(),
+            None
+        );
+
+        // Not in its output, the offset is unknown.
+        let mut inscription_output = MemoryStore::new();
+        inscription_output
+            .put(
+                bound.to_string().as_bytes(),
+                outpoint.to_string().as_bytes(),
+            )
+            .unwrap();
+        assert!(matches!(
+            migrate(
+                &mut MemoryStore::new(),
+                &mut MemoryStore::new(),
+                &mut MemoryStore::new(),
+                &mut inscription_output,
+                &mut MemoryStore::new(),
+                JUBILEE_HEIGHT,
+            ),
+            Err(MigrationError::UnknownOffset(id)) if id == bound
+        ));
+    }
+}
@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail};
+use ordi::*;
+
+const USAGE: &str = "Usage:
+    ordi snapshot create --height <HEIGHT> [--dir <DIR>]
+    ordi snapshot restore --dir <DIR>
+    ordi reindex";
+
+fn main() -> anyhow::Result<()> {
+    let _ = dotenv::dotenv();
+    simplelog::SimpleLogger::init(log::LevelFilter::Info, simplelog::Config::default())?;
+
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+
+    match args.as_slice() {
+        ["snapshot", "create", flags @ ..] => {
+            let height = flag(flags, "--height")?
+                .ok_or_else(|| anyhow!("Missing --height.\n{}", USAGE))?
+                .parse::<u64>()?;
+            let dir = flag(flags, "--dir")?
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from(format!("output_value-{}", height)));
+
+            let mut ordi = Ordi::new(Options::default())?;
+            let manifest = ordi.create_snapshot(height, &dir)?;
+            println!(
+                "Created snapshot {:?}: height {}, block {}, {} entries, sha256 {}.",
+                dir, manifest.height, manifest.block_hash, manifest.entries, manifest.sha256
+            );
+        }
+        ["snapshot", "restore", flags @ ..] => {
+            let dir = flag(flags, "--dir")?
+                .map(PathBuf::from)
+                .ok_or_else(|| anyhow!("Missing --dir.\n{}", USAGE))?;
+
+            let mut ordi = Ordi::new(Options::default())?;
+            let manifest = ordi.restore_snapshot(&dir)?;
+            println!(
+                "Restored snapshot {:?}: height {}, block {}, {} entries.",
+                dir, manifest.height, manifest.block_hash, manifest.entries
+            );
+        }
+        ["reindex"] => {
+            let options = Options::default();
+            Ordi::reindex(&options)?;
+            println!("Dropped everything indexed in {}.", options.ordi_data_dir);
+        }
+        _ => bail!(USAGE),
+    }
+
+    Ok(())
+}
+
+/// Value following `name` in `flags`.
+fn flag<'a>(flags: &[&'a str], name: &str) -> anyhow::Result<Option<&'a str>> {
+    match flags.iter().position(|f| *f == name) {
+        Some(i) => flags
+            .get(i + 1)
+            .map(|v| Some(*v))
+            .ok_or_else(|| anyhow!("Missing value of {}.\n{}", name, USAGE)),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,190 @@
+use std::{fmt, num::ParseIntError, str::FromStr};
+
+use bitcoin::hashes::{hex, Hash};
+use bitcoin::Txid;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::bitcoin::proto::tx::TxOutpoint;
+use crate::varint::{self, VarintError};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum OutPointError {
+    #[error("Missing separator in: `{0}`")]
+    Separator(String),
+    #[error("Invalid txid: `{0}`")]
+    Txid(hex::Error),
+    #[error("Invalid vout: `{0}`")]
+    Vout(ParseIntError),
+    #[error("Invalid length: `{0}`")]
+    Length(usize),
+    #[error("Invalid varint: `{0}`")]
+    Varint(#[from] VarintError),
+}
+
+/// Reference to a transaction output, displayed as `<txid>:<vout>`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OutPoint {
+    pub txid: Txid,
+    pub vout: u32,
+}
+
+impl OutPoint {
+    pub fn new(txid: Txid, vout: u32) -> OutPoint {
+        OutPoint { txid, vout }
+    }
+
+    /// Outpoint of coinbase inputs, also used as the location of lost sats.
+    pub fn null() -> OutPoint {
+        OutPoint {
+            txid: Txid::all_zeros(),
+            vout: u32::MAX,
+        }
+    }
+
+    /// Outpoint holding inscriptions which were never bound to a sat.
+    pub fn unbound() -> OutPoint {
+        OutPoint {
+            txid: Txid::all_zeros(),
+            vout: 0,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == OutPoint::null()
+    }
+
+    /// Compact encoding: 32 bytes txid followed by varint vout.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(32 + 5);
+        self.encode_to_vec(&mut bytes);
+        bytes
+    }
+
+    pub fn encode_to_vec(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(self.txid.as_byte_array());
+        varint::encode_to_vec(self.vout.into(), bytes);
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<OutPoint, OutPointError> {
+        let (outpoint, len) = OutPoint::decode(bytes)?;
+        if len != bytes.len() {
+            return Err(OutPointError::Length(bytes.len()));
+        }
+
+        Ok(outpoint)
+    }
+
+    /// Decodes an outpoint from the front of `bytes`, returns it with the consumed length.
+    pub fn decode(bytes: &[u8]) -> Result<(OutPoint, usize), OutPointError> {
+        if bytes.len() < 33 {
+            return Err(OutPointError::Length(bytes.len()));
+        }
+
+        let txid =
+            Txid::from_slice(&bytes[..32]).map_err(|_| OutPointError::Length(bytes.len()))?;
+        let (vout, len) = varint::decode(&bytes[32..])?;
+        let vout = u32::try_from(vout).map_err(|_| VarintError::Overflow)?;
+
+        Ok((OutPoint { txid, vout }, 32 + len))
+    }
+}
+
+impl fmt::Display for OutPoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.txid, self.vout)
+    }
+}
+
+impl FromStr for OutPoint {
+    type Err = OutPointError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (txid, vout) = s
+            .split_once(':')
+            .ok_or_else(|| OutPointError::Separator(s.to_string()))?;
+
+        Ok(OutPoint {
+            txid: txid.parse().map_err(OutPointError::Txid)?,
+            vout: vout.parse().map_err(OutPointError::Vout)?,
+        })
+    }
+}
+
+impl From<&TxOutpoint> for OutPoint {
+    fn from(outpoint: &TxOutpoint) -> OutPoint {
+        OutPoint {
+            txid: Txid::from_raw_hash(outpoint.txid),
+            vout: outpoint.index,
+        }
+    }
+}
+
+impl From<bitcoin::OutPoint> for OutPoint {
+    fn from(outpoint: bitcoin::OutPoint) -> OutPoint {
+        OutPoint {
+            txid: outpoint.txid,
+            vout: outpoint.vout,
+        }
+    }
+}
+
+impl Serialize for OutPoint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for OutPoint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPOINT: &str = "1111111111111111111111111111111111111111111111111111111111111111:2";
+
+    #[test]
+    fn test_outpoint_display_round_trip() {
+        let outpoint = OUTPOINT.parse::<OutPoint>().unwrap();
+        assert_eq!(outpoint.vout, 2);
+        assert_eq!(outpoint.to_string(), OUTPOINT);
+    }
+
+    #[test]
+    fn test_outpoint_bytes_round_trip() {
+        let outpoint = OUTPOINT.parse::<OutPoint>().unwrap();
+        let bytes = outpoint.to_bytes();
+        assert_eq!(bytes.len(), 33);
+        assert_eq!(OutPoint::from_bytes(&bytes), Ok(outpoint));
+        assert_eq!(
+            OutPoint::from_bytes(&OutPoint::null().to_bytes()),
+            Ok(OutPoint::null())
+        );
+    }
+
+    #[test]
+    fn test_outpoint_malformed() {
+        assert!(matches!(
+            "1111".parse::<OutPoint>(),
+            Err(OutPointError::Separator(_))
+        ));
+        assert!(matches!(
+            "11:0".parse::<OutPoint>(),
+            Err(OutPointError::Txid(_))
+        ));
+        assert!(matches!(
+            format!("{}x", OUTPOINT).parse::<OutPoint>(),
+            Err(OutPointError::Vout(_))
+        ));
+        assert_eq!(
+            OutPoint::from_bytes(&[0; 10]),
+            Err(OutPointError::Length(10))
+        );
+    }
+}
@@ -0,0 +1,939 @@
+use std::collections::HashMap;
+
+use bitcoin::blockdata::script::Script;
+use bitcoin::{ScriptBuf, Txid};
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::block::{ProtoBlock, Tx};
+use crate::chain::Chain;
+use crate::outpoint::OutPoint;
+use crate::runes::{runestone, Artifact, Edict, Rune, RuneId, SpacedRune, Terms};
+use crate::store::keyspace::{self, KeyspaceError};
+use crate::store::Store;
+use crate::varint;
+
+/// `e<rune id>` -> rune, as json.
+const RUNE_PREFIX: u8 = b'e';
+/// `r<rune, 16 bytes BE>` -> rune id.
+const NAME_PREFIX: u8 = b'r';
+/// `o<outpoint>` -> varint block, tx and amount of each rune held.
+const OUTPOINT_PREFIX: u8 = b'o';
+/// Number of runes etched, 8 bytes LE.
+const RUNE_COUNT: &[u8] = b"n";
+/// Confirmations of the output an etching commits with, counting its block.
+pub const COMMIT_CONFIRMATIONS: u64 = 6;
+
+#[derive(Error, Debug)]
+pub enum RuneIndexError {
+    #[error("Keyspace error: `{0}`")]
+    KeyspaceError(#[from] KeyspaceError),
+    #[error("Bitcoin rpc error: `{0}`")]
+    BitcoinRpcError(#[from] bitcoincore_rpc::Error),
+    #[error("Output `{0}` an etching commits with not found, rpc is offline")]
+    CommitOutputNotFound(OutPoint),
+}
+
+/// Outputs etchings commit with, spent by the etching transaction.
+pub trait CommitOutputs {
+    /// Script pubkey of `outpoint` and height of the block confirming it.
+    fn commit_output(&self, outpoint: &OutPoint) -> Result<(ScriptBuf, u64), RuneIndexError>;
+}
+
+impl CommitOutputs for Client {
+    fn commit_output(&self, outpoint: &OutPoint) -> Result<(ScriptBuf, u64), RuneIndexError> {
+        let not_found = || RuneIndexError::CommitOutputNotFound(*outpoint);
+        let tx_info = self.get_raw_transaction_info(&outpoint.txid, None)?;
+        let script_pubkey = tx_info
+            .vout
+            .get(outpoint.vout as usize)
+            .ok_or_else(not_found)?
+            .script_pub_key
+            .hex
+            .clone();
+        let block_hash = tx_info.blockhash.ok_or_else(not_found)?;
+        let height = self.get_block_header_info(&block_hash)?.height;
+        Ok((ScriptBuf::from(script_pubkey), height as u64))
+    }
+}
+
+/// An etched rune and its supply so far.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuneDetails {
+    pub id: RuneId,
+    /// Etching order, from 0.
+    pub number: u64,
+    pub spaced_rune: SpacedRune,
+    #[serde(
+        serialize_with = "serialize_txid",
+        deserialize_with = "deserialize_txid"
+    )]
+    pub etching: Txid,
+    pub divisibility: u8,
+    pub premine: u128,
+    pub symbol: Option<char>,
+    pub terms: Option<Terms>,
+    pub turbo: bool,
+    pub mints: u128,
+    pub burned: u128,
+    pub timestamp: u32,
+}
+
+impl RuneDetails {
+    /// Amount of a mint at `height`, `None` if the rune is not mintable then.
+    pub fn mintable(&self, height: u64) -> Option<u128> {
+        let terms = self.terms?;
+        if self.start().is_some_and(|start| height < start)
+            || self.end().is_some_and(|end| height >= end)
+            || self.mints >= terms.cap.unwrap_or_default()
+        {
+            return None;
+        }
+
+        Some(terms.amount.unwrap_or_default())
+    }
+
+    /// Height mints open at, the latest of the absolute and relative ones.
+    pub fn start(&self) -> Option<u64> {
+        let terms = self.terms?;
+        let relative = terms
+            .offset
+            .0
+            .map(|offset| self.id.block.saturating_add(offset));
+        match (relative, terms.height.0) {
+            (Some(relative), Some(absolute)) => Some(relative.max(absolute)),
+            (relative, absolute) => relative.or(absolute),
+        }
+    }
+
+    /// Height mints close at, the earliest of the absolute and relative ones.
+    pub fn end(&self) -> Option<u64> {
+        let terms = self.terms?;
+        let relative = terms
+            .offset
+            .1
+            .map(|offset| self.id.block.saturating_add(offset));
+        match (relative, terms.height.1) {
+            (Some(relative), Some(absolute)) => Some(relative.min(absolute)),
+            (relative, absolute) => relative.or(absolute),
+        }
+    }
+
+    /// Premine and mints so far.
+    pub fn supply(&self) -> u128 {
+        let amount = self
+            .terms
+            .and_then(|terms| terms.amount)
+            .unwrap_or_default();
+        self.premine
+            .saturating_add(self.mints.saturating_mul(amount))
+    }
+}
+
+fn serialize_txid<S: Serializer>(txid: &Txid, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(txid)
+}
+
+fn deserialize_txid<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Txid, D::Error> {
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(serde::de::Error::custom)
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RuneEventKind {
+    Etched {
+        rune: SpacedRune,
+    },
+    Minted {
+        amount: u128,
+    },
+    /// Allocated to an output, by an edict or as leftover.
+    Transferred {
+        outpoint: OutPoint,
+        amount: u128,
+    },
+    Burned {
+        amount: u128,
+    },
+}
+
+pub struct RuneEntry {
+    pub kind: RuneEventKind,
+    pub rune_id: RuneId,
+    pub txid: Txid,
+    pub height: u64,
+    pub timestamp: u32,
+}
+
+pub type RuneUpdater = fn(RuneEntry);
+
+pub struct RuneIndex<'a> {
+    store: &'a mut dyn Store,
+}
+
+impl<'a> RuneIndex<'a> {
+    pub fn new(store: &'a mut dyn Store) -> RuneIndex<'a> {
+        RuneIndex { store }
+    }
+
+    /// Applies the runestones of a block in transaction order. Nothing is
+    /// indexed before the chain's first rune height.
+    ///
+    /// Etchings must commit to their name in the tapscript of an input, which
+    /// spends a taproot output confirmed `COMMIT_CONFIRMATIONS` blocks. Those
+    /// outputs are looked up in `commit_outputs`, `None` when rpc is offline.
+    pub fn index_block(
+        &mut self,
+        height: u64,
+        block: &ProtoBlock,
+        chain: &Chain,
+        commit_outputs: Option<&dyn CommitOutputs>,
+        updaters: &[RuneUpdater],
+    ) -> Result<(), RuneIndexError> {
+        let Some(first_rune_height) = chain.first_rune_height() else {
+            return Ok(());
+        };
+        if height < first_rune_height {
+            return Ok(());
+        }
+
+        let mut indexer = TxIndexer {
+            index: self,
+            height,
+            timestamp: block.header.value.timestamp,
+            minimum: Rune::minimum_at_height(first_rune_height, height),
+            commit_outputs,
+            updaters,
+        };
+        for (tx_index, tx) in block.txs.iter().enumerate() {
+            indexer.index_transaction(tx_index as u32, tx)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn rune(&mut self, id: RuneId) -> Result<Option<RuneDetails>, KeyspaceError> {
+        keyspace::get_json(self.store, &rune_key(id))
+    }
+
+    pub fn rune_by_name(&mut self, rune: Rune) -> Result<Option<RuneDetails>, KeyspaceError> {
+        match keyspace::get(self.store, &name_key(rune), RuneId::from_bytes)? {
+            Some(id) => self.rune(id),
+            None => Ok(None),
+        }
+    }
+
+    /// Etched runes, by id.
+    pub fn runes(&mut self) -> Result<Vec<RuneDetails>, KeyspaceError> {
+        keyspace::scan_json(self.store, &[RUNE_PREFIX])
+    }
+
+    /// Runes held by an unspent output, by id.
+    pub fn balances(&mut self, outpoint: &OutPoint) -> Result<Vec<(RuneId, u128)>, KeyspaceError> {
+        Ok(
+            keyspace::get(self.store, &outpoint_key(outpoint), decode_balances)?
+                .unwrap_or_default(),
+        )
+    }
+
+    fn put_rune(&mut self, rune: &RuneDetails) -> Result<(), KeyspaceError> {
+        keyspace::put_json(self.store, &rune_key(rune.id), rune)
+    }
+
+    fn rune_count(&mut self) -> Result<u64, KeyspaceError> {
+        let count = keyspace::get(self.store, RUNE_COUNT, |value| {
+            value.try_into().ok().map(u64::from_le_bytes)
+        })?;
+        Ok(count.unwrap_or_default())
+    }
+}
+
+struct TxIndexer<'index, 'a> {
+    index: &'index mut RuneIndex<'a>,
+    height: u64,
+    timestamp: u32,
+    minimum: Rune,
+    commit_outputs: Option<&'index dyn CommitOutputs>,
+    updaters: &'index [RuneUpdater],
+}
+
+impl TxIndexer<'_, '_> {
+    fn index_transaction(&mut self, tx_index: u32, tx: &Tx) -> Result<(), RuneIndexError> {
+        let txid = Txid::from_raw_hash(tx.hash);
+        let artifact = runestone::decipher(&tx.value);
+        let is_op_return = tx
+            .value
+            .outputs
+            .iter()
+            .map(|output| Script::from_bytes(&output.out.script_pubkey).is_op_return())
+            .collect::<Vec<bool>>();
+
+        let mut unallocated = self.unallocated(tx)?;
+        let mut allocated = vec![HashMap::<RuneId, u128>::new(); is_op_return.len()];
+
+        if let Some(artifact) = &artifact {
+            if let Some(id) = artifact.mint() {
+                if let Some(amount) = self.mint(id)? {
+                    *unallocated.entry(id).or_default() += amount;
+                    self.emit(RuneEventKind::Minted { amount }, id, txid);
+                }
+            }
+
+            let etched = self.etched(tx_index, tx, artifact)?;
+
+            if let Artifact::Runestone(runestone) = artifact {
+                if let Some((id, _)) = etched {
+                    *unallocated.entry(id).or_default() += runestone
+                        .etching
+                        .and_then(|etching| etching.premine)
+                        .unwrap_or_default();
+                }
+
+                for Edict { id, amount, output } in runestone.edicts.iter().copied() {
+                    let output = output as usize;
+                    // 0:0 is the rune etched by this runestone.
+                    let id = match (id == RuneId::default(), etched) {
+                        (false, _) => id,
+                        (true, Some((id, _))) => id,
+                        (true, None) => continue,
+                    };
+                    let Some(balance) = unallocated.get_mut(&id) else {
+                        continue;
+                    };
+
+                    let mut allocate = |balance: &mut u128, amount: u128, output: usize| {
+                        if amount > 0 {
+                            *balance -= amount;
+                            *allocated[output].entry(id).or_default() += amount;
+                        }
+                    };
+
+                    if output == is_op_return.len() {
+                        let destinations = (0..is_op_return.len())
+                            .filter(|output| !is_op_return[*output])
+                            .collect::<Vec<usize>>();
+                        if destinations.is_empty() {
+                            continue;
+                        }
+
+                        if amount == 0 {
+                            let count = destinations.len() as u128;
+                            let share = *balance / count;
+                            let remainder = (*balance % count) as usize;
+                            for (i, output) in destinations.into_iter().enumerate() {
+                                allocate(balance, share + u128::from(i < remainder), output);
+                            }
+                        } else {
+                            for output in destinations {
+                                allocate(balance, amount.min(*balance), output);
+                            }
+                        }
+                    } else {
+                        let amount = match amount {
+                            0 => *balance,
+                            amount => amount.min(*balance),
+                        };
+                        allocate(balance, amount, output);
+                    }
+                }
+            }
+
+            if let Some((id, rune)) = etched {
+                self.create_rune(txid, artifact, id, rune)?;
+            }
+        }
+
+        let mut burned = HashMap::<RuneId, u128>::new();
+        match &artifact {
+            Some(Artifact::Cenotaph(_)) => {
+                for (id, balance) in unallocated {
+                    *burned.entry(id).or_default() += balance;
+                }
+            }
+            _ => {
+                let pointer = match &artifact {
+                    Some(Artifact::Runestone(runestone)) => runestone.pointer,
+                    _ => None,
+                };
+                let leftover = pointer
+                    .map(|pointer| pointer as usize)
+                    .or_else(|| is_op_return.iter().position(|is_op_return| !is_op_return));
+                for (id, balance) in unallocated {
+                    if balance == 0 {
+                        continue;
+                    }
+                    match leftover {
+                        Some(output) => *allocated[output].entry(id).or_default() += balance,
+                        None => *burned.entry(id).or_default() += balance,
+                    }
+                }
+            }
+        }
+
+        for (output, balances) in allocated.into_iter().enumerate() {
+            if balances.is_empty() {
+                continue;
+            }
+            if is_op_return[output] {
+                for (id, balance) in balances {
+                    *burned.entry(id).or_default() += balance;
+                }
+                continue;
+            }
+
+            let mut balances = balances.into_iter().collect::<Vec<(RuneId, u128)>>();
+            balances.sort();
+            let outpoint = OutPoint::new(txid, output as u32);
+            keyspace::put(
+                self.index.store,
+                &outpoint_key(&outpoint),
+                &encode_balances(&balances),
+            )?;
+            for (id, amount) in balances {
+                self.emit(RuneEventKind::Transferred { outpoint, amount }, id, txid);
+            }
+        }
+
+        let mut burned = burned.into_iter().collect::<Vec<(RuneId, u128)>>();
+        burned.sort();
+        for (id, amount) in burned {
+            if amount == 0 {
+                continue;
+            }
+            let mut rune = self
+                .index
+                .rune(id)?
+                .ok_or_else(|| KeyspaceError::MalformedValue(rune_key(id).to_vec()))?;
+            rune.burned = rune.burned.saturating_add(amount);
+            self.index.put_rune(&rune)?;
+            self.emit(RuneEventKind::Burned { amount }, id, txid);
+        }
+
+        Ok(())
+    }
+
+    /// Runes held by the outputs the transaction spends, which are removed.
+    fn unallocated(&mut self, tx: &Tx) -> Result<HashMap<RuneId, u128>, KeyspaceError> {
+        let mut unallocated = HashMap::new();
+        for input in tx.value.inputs.iter() {
+            if input.outpoint.is_null() {
+                continue;
+            }
+
+            let key = outpoint_key(&OutPoint::from(&input.outpoint));
+            let Some(value) = self.index.store.get(&key)? else {
+                continue;
+            };
+            for (id, amount) in
+                decode_balances(&value).ok_or(KeyspaceError::MalformedValue(key.clone()))?
+            {
+                *unallocated.entry(id).or_default() += amount;
+            }
+            self.index.store.delete(&key)?;
+        }
+
+        Ok(unallocated)
+    }
+
+    fn mint(&mut self, id: RuneId) -> Result<Option<u128>, KeyspaceError> {
+        let Some(mut rune) = self.index.rune(id)? else {
+            return Ok(None);
+        };
+        let Some(amount) = rune.mintable(self.height) else {
+            return Ok(None);
+        };
+
+        rune.mints += 1;
+        self.index.put_rune(&rune)?;
+        Ok(Some(amount))
+    }
+
+    /// Id and name of the rune etched by the artifact, if its name is valid.
+    fn etched(
+        &mut self,
+        tx_index: u32,
+        tx: &Tx,
+        artifact: &Artifact,
+    ) -> Result<Option<(RuneId, Rune)>, RuneIndexError> {
+        let rune = match artifact {
+            Artifact::Runestone(runestone) => match runestone.etching {
+                Some(etching) => etching.rune,
+                None => return Ok(None),
+            },
+            Artifact::Cenotaph(cenotaph) => match cenotaph.etching {
+                Some(rune) => Some(rune),
+                None => return Ok(None),
+            },
+        };
+
+        let rune = match rune {
+            Some(rune) => {
+                if rune < self.minimum
+                    || rune.is_reserved()
+                    || self.index.rune_by_name(rune)?.is_some()
+                    || !self.commits_to_rune(tx, rune)?
+                {
+                    return Ok(None);
+                }
+                rune
+            }
+            None => Rune::reserved(self.height, tx_index),
+        };
+
+        Ok(Some((
+            RuneId {
+                block: self.height,
+                tx: tx_index,
+            },
+            rune,
+        )))
+    }
+
+    fn create_rune(
+        &mut self,
+        txid: Txid,
+        artifact: &Artifact,
+        id: RuneId,
+        rune: Rune,
+    ) -> Result<(), KeyspaceError> {
+        let number = self.index.rune_count()?;
+        let etching = match artifact {
+            Artifact::Runestone(runestone) => runestone.etching.unwrap_or_default(),
+            // Cenotaphs etch a rune which can not be minted.
+            Artifact::Cenotaph(_) => Default::default(),
+        };
+
+        let details = RuneDetails {
+            id,
+            number,
+            spaced_rune: SpacedRune {
+                rune,
+                spacers: etching.spacers.unwrap_or_default(),
+            },
+            etching: txid,
+            divisibility: etching.divisibility.unwrap_or_default(),
+            premine: etching.premine.unwrap_or_default(),
+            symbol: etching.symbol,
+            terms: etching.terms,
+            turbo: etching.turbo,
+            mints: 0,
+            burned: 0,
+            timestamp: self.timestamp,
+        };
+        self.index.put_rune(&details)?;
+        self.index.store.put(&name_key(rune), &id.to_bytes())?;
+        self.index
+            .store
+            .put(RUNE_COUNT, (number + 1).to_le_bytes().as_slice())?;
+        self.emit(
+            RuneEventKind::Etched {
+                rune: details.spaced_rune,
+            },
+            id,
+            txid,
+        );
+        Ok(())
+    }
+
+    /// Whether an input's tapscript pushes the rune's commitment, spending a
+    /// taproot output with `COMMIT_CONFIRMATIONS` confirmations, like ord.
+    fn commits_to_rune(&self, tx: &Tx, rune: Rune) -> Result<bool, RuneIndexError> {
+        let commitment = rune.commitment();
+        for input in &tx.value.inputs {
+            let Some(tapscript) = input
+                .witness
+                .as_ref()
+                .and_then(|witness| witness.tapscript())
+            else {
+                continue;
+            };
+            let committed = tapscript
+                .instructions()
+                .map_while(Result::ok)
+                .filter_map(|instruction| {
+                    instruction
+                        .push_bytes()
+                        .map(|push| push.as_bytes().to_vec())
+                })
+                .any(|push| push == commitment);
+            if !committed {
+                continue;
+            }
+
+            let outpoint = OutPoint::from(&input.outpoint);
+            let (script_pubkey, commit_height) = self
+                .commit_outputs
+                .ok_or(RuneIndexError::CommitOutputNotFound(outpoint))?
+                .commit_output(&outpoint)?;
+            // The block confirming the commit output is its first confirmation.
+            let confirmations = self.height.saturating_sub(commit_height) + 1;
+            if script_pubkey.is_v1_p2tr() && confirmations >= COMMIT_CONFIRMATIONS {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn emit(&self, kind: RuneEventKind, rune_id: RuneId, txid: Txid) {
+        for updater in self.updaters {
+            updater(RuneEntry {
+                kind,
+                rune_id,
+                txid,
+                height: self.height,
+                timestamp: self.timestamp,
+            });
+        }
+    }
+}
+
+fn rune_key(id: RuneId) -> Vec<u8> {
+    let mut key = vec![RUNE_PREFIX];
+    key.extend_from_slice(&id.to_bytes());
+    key
+}
+
+fn name_key(rune: Rune) -> Vec<u8> {
+    let mut key = vec![NAME_PREFIX];
+    key.extend_from_slice(&rune.0.to_be_bytes());
+    key
+}
+
+fn outpoint_key(outpoint: &OutPoint) -> Vec<u8> {
+    let mut key = vec![OUTPOINT_PREFIX];
+    outpoint.encode_to_vec(&mut key);
+    key
+}
+
+fn encode_balances(balances: &[(RuneId, u128)]) -> Vec<u8> {
+    let mut bytes = vec![];
+    for (id, amount) in balances {
+        varint::encode_to_vec(id.block.into(), &mut bytes);
+        varint::encode_to_vec(id.tx.into(), &mut bytes);
+        varint::encode_to_vec(*amount, &mut bytes);
+    }
+    bytes
+}
+
+fn decode_balances(mut bytes: &[u8]) -> Option<Vec<(RuneId, u128)>> {
+    let mut balances = vec![];
+    while !bytes.is_empty() {
+        let mut next = || {
+            let (value, length) = varint::decode(bytes).ok()?;
+            bytes = &bytes[length..];
+            Some(value)
+        };
+        let block = u64::try_from(next()?).ok()?;
+        let tx = u32::try_from(next()?).ok()?;
+        balances.push((RuneId { block, tx }, next()?));
+    }
+    Some(balances)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::block::{Header, Version};
+    use bitcoin::blockdata::opcodes;
+    use bitcoin::blockdata::script::{Builder, PushBytesBuf};
+    use bitcoin::hash_types::{TxMerkleNode, WScriptHash};
+    use bitcoin::hashes::Hash;
+    use bitcoin::{
+        absolute::LockTime, Block, BlockHash, CompactTarget, ScriptBuf, Sequence, Transaction,
+        TxIn, TxOut, Witness,
+    };
+
+    use super::*;
+    use crate::store::MemoryStore;
+
+    fn block(txs: Vec<Transaction>) -> ProtoBlock {
+        let coinbase = Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![],
+        };
+        let block = Block {
+            header: Header {
+                version: Version::ONE,
+                prev_blockhash: BlockHash::all_zeros(),
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: 1,
+                bits: CompactTarget::from_consensus(0),
+                nonce: 0,
+            },
+            txdata: [coinbase].into_iter().chain(txs).collect(),
+        };
+        ProtoBlock::from_bitcoin(block, Chain::Regtest.coin().address_encoding())
+    }
+
+    fn tx(inputs: Vec<(OutPoint, Witness)>, integers: &[u128], outputs: usize) -> Transaction {
+        let mut payload = vec![];
+        for integer in integers {
+            varint::encode_to_vec(*integer, &mut payload);
+        }
+        let runestone = Builder::new()
+            .push_opcode(opcodes::all::OP_RETURN)
+            .push_opcode(opcodes::all::OP_PUSHNUM_13)
+            .push_slice(PushBytesBuf::try_from(payload).unwrap())
+            .into_script();
+
+        let mut output = vec![
+            TxOut {
+                value: 1,
+                script_pubkey: ScriptBuf::new_v0_p2wsh(&WScriptHash::all_zeros()),
+            };
+            outputs
+        ];
+        output.push(TxOut {
+            value: 0,
+            script_pubkey: runestone,
+        });
+        Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: inputs
+                .into_iter()
+                .map(|(outpoint, witness)| TxIn {
+                    previous_output: bitcoin::OutPoint::new(outpoint.txid, outpoint.vout),
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness,
+                })
+                .collect(),
+            output,
+        }
+    }
+
+    /// Txid of the block's `i`th transaction, as ordi hashes it.
+    fn txid(block: &ProtoBlock, i: usize) -> Txid {
+        Txid::from_raw_hash(block.txs[i].hash)
+    }
+
+    fn spend(outpoint: OutPoint) -> (OutPoint, Witness) {
+        (outpoint, Witness::new())
+    }
+
+    impl CommitOutputs for HashMap<OutPoint, (ScriptBuf, u64)> {
+        fn commit_output(&self, outpoint: &OutPoint) -> Result<(ScriptBuf, u64), RuneIndexError> {
+            self.get(outpoint)
+                .cloned()
+                .ok_or(RuneIndexError::CommitOutputNotFound(*outpoint))
+        }
+    }
+
+    fn p2tr() -> ScriptBuf {
+        Builder::new()
+            .push_opcode(opcodes::all::OP_PUSHNUM_1)
+            .push_slice([0; 32])
+            .into_script()
+    }
+
+    /// Script path spend of a tapscript pushing the commitment of `rune`.
+    fn commit_witness(rune: Rune) -> Witness {
+        let tapscript = Builder::new()
+            .push_slice(PushBytesBuf::try_from(rune.commitment()).unwrap())
+            .push_opcode(opcodes::all::OP_DROP)
+            .into_script();
+        Witness::from_slice(&[tapscript.into_bytes(), vec![0xc0; 33]])
+    }
+
+    /// Etches `rune` in a transaction spending `input`, returns it if etched.
+    fn etch(
+        index: &mut RuneIndex,
+        height: u64,
+        rune: Rune,
+        input: (OutPoint, Witness),
+        commit_outputs: Option<&dyn CommitOutputs>,
+    ) -> Result<Option<RuneDetails>, RuneIndexError> {
+        let etching = tx(vec![input], &[2, 1, 4, rune.0], 1);
+        index.index_block(
+            height,
+            &block(vec![etching]),
+            &Chain::Mainnet,
+            commit_outputs,
+            &[],
+        )?;
+        Ok(index.rune_by_name(rune)?)
+    }
+
+    #[test]
+    fn test_index_block() {
+        let mut store = MemoryStore::new();
+        let mut index = RuneIndex::new(&mut store);
+        let id = RuneId::new(840000, 1).unwrap();
+
+        // Reserved name, premine 1000, two mints of 100, open from 840001.
+        let etching = tx(
+            vec![],
+            &[2, 0b11, 6, 1000, 8, 2, 10, 100, 12, 840001, 0, 0, 0, 400, 1],
+            2,
+        );
+        let etching = block(vec![etching]);
+        let etching_txid = txid(&etching, 1);
+        index
+            .index_block(840000, &etching, &Chain::Mainnet, None, &[])
+            .unwrap();
+
+        let rune = index.rune(id).unwrap().unwrap();
+        assert_eq!(rune.spaced_rune.rune, Rune::reserved(840000, 1));
+        assert_eq!(rune.mintable(840000), None);
+        assert_eq!(rune.mintable(840001), Some(100));
+        assert_eq!(
+            index.rune_by_name(Rune::reserved(840000, 1)).unwrap(),
+            Some(rune)
+        );
+        // The edict sends 400, the rest goes to the first output.
+        assert_eq!(
+            index.balances(&OutPoint::new(etching_txid, 0)).unwrap(),
+            vec![(id, 600)]
+        );
+        assert_eq!(
+            index.balances(&OutPoint::new(etching_txid, 1)).unwrap(),
+            vec![(id, 400)]
+        );
+
+        // Mint to the pointer, then split all of it between both outputs.
+        let mint = tx(
+            vec![spend(OutPoint::new(etching_txid, 0))],
+            &[20, 840000, 20, 1, 22, 1],
+            2,
+        );
+        let mint_txid = txid(&block(vec![mint.clone()]), 1);
+        let split = tx(
+            vec![spend(OutPoint::new(mint_txid, 1))],
+            &[0, 840000, 1, 0, 3],
+            2,
+        );
+        let mint_and_split = block(vec![mint, split]);
+        let split_txid = txid(&mint_and_split, 2);
+        index
+            .index_block(840001, &mint_and_split, &Chain::Mainnet, None, &[])
+            .unwrap();
+        assert_eq!(index.rune(id).unwrap().unwrap().mints, 1);
+        assert_eq!(
+            index.balances(&OutPoint::new(etching_txid, 0)).unwrap(),
+            vec![]
+        );
+        assert_eq!(
+            index.balances(&OutPoint::new(split_txid, 0)).unwrap(),
+            vec![(id, 350)]
+        );
+        assert_eq!(
+            index.balances(&OutPoint::new(split_txid, 1)).unwrap(),
+            vec![(id, 350)]
+        );
+
+        // A cenotaph burns what it spends.
+        let cenotaph = tx(
+            vec![
+                spend(OutPoint::new(split_txid, 0)),
+                spend(OutPoint::new(etching_txid, 1)),
+            ],
+            &[24, 1],
+            1,
+        );
+        let cenotaph = block(vec![cenotaph]);
+        let cenotaph_txid = txid(&cenotaph, 1);
+        index
+            .index_block(840002, &cenotaph, &Chain::Mainnet, None, &[])
+            .unwrap();
+        assert_eq!(
+            index.balances(&OutPoint::new(cenotaph_txid, 0)).unwrap(),
+            vec![]
+        );
+        assert_eq!(index.rune(id).unwrap().unwrap().burned, 750);
+    }
+
+    #[test]
+    fn test_etching_commitment() {
+        let mut store = MemoryStore::new();
+        let mut index = RuneIndex::new(&mut store);
+        let rune = "UNCOMMONGOODS".parse::<Rune>().unwrap();
+        let commit = |vout| OutPoint::new(Txid::all_zeros(), vout);
+        let commit_outputs = HashMap::from([
+            (commit(0), (p2tr(), 839996)),
+            (commit(1), (p2tr(), 839997)),
+            (
+                commit(2),
+                (ScriptBuf::new_v0_p2wsh(&WScriptHash::all_zeros()), 839990),
+            ),
+        ]);
+
+        // Not committed in a tapscript.
+        assert_eq!(
+            etch(
+                &mut index,
+                840001,
+                rune,
+                (commit(0), Witness::new()),
+                Some(&commit_outputs)
+            )
+            .unwrap(),
+            None
+        );
+        // Committed, spending an output which is not taproot.
+        assert_eq!(
+            etch(
+                &mut index,
+                840001,
+                rune,
+                (commit(2), commit_witness(rune)),
+                Some(&commit_outputs)
+            )
+            .unwrap(),
+            None
+        );
+        // Committed, spending a taproot output with 5 confirmations.
+        assert_eq!(
+            etch(
+                &mut index,
+                840001,
+                rune,
+                (commit(1), commit_witness(rune)),
+                Some(&commit_outputs)
+            )
+            .unwrap(),
+            None
+        );
+        // Rpc is offline, the commit output can not be checked.
+        assert!(matches!(
+            etch(
+                &mut index,
+                840001,
+                rune,
+                (commit(0), commit_witness(rune)),
+                None
+            ),
+            Err(RuneIndexError::CommitOutputNotFound(outpoint)) if outpoint == commit(0)
+        ));
+
+        let details = etch(
+            &mut index,
+            840001,
+            rune,
+            (commit(0), commit_witness(rune)),
+            Some(&commit_outputs),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(details.id, RuneId::new(840001, 1).unwrap());
+        assert_eq!(details.number, 0);
+        assert_eq!(index.runes().unwrap(), vec![details]);
+
+        // Too short before its unlock.
+        let short = "AAAA".parse::<Rune>().unwrap();
+        assert_eq!(
+            etch(
+                &mut index,
+                840002,
+                short,
+                (commit(0), commit_witness(short)),
+                Some(&commit_outputs)
+            )
+            .unwrap(),
+            None
+        );
+    }
+}
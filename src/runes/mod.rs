@@ -0,0 +1,231 @@
+//! Runes, fungible tokens etched, minted and transferred by runestones: an
+//! `OP_RETURN OP_13` output whose pushes are LEB128 tags and edicts.
+//!
+//! Balances are kept per outpoint in their own keyspace and follow the rules
+//! of ord: a flawed runestone is a cenotaph, burning the runes sent to its
+//! transaction, and runes no edict allocates go to the pointer output, or the
+//! first output which is not OP_RETURN.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+mod index;
+mod rune;
+pub mod runestone;
+
+pub use index::{
+    CommitOutputs, RuneDetails, RuneEntry, RuneEventKind, RuneIndex, RuneIndexError, RuneUpdater,
+    COMMIT_CONFIRMATIONS,
+};
+pub use rune::{Rune, RuneNameError, SpacedRune};
+
+/// Block height and position of the etching transaction, displayed as
+/// `<block>:<tx>`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RuneId {
+    pub block: u64,
+    pub tx: u32,
+}
+
+impl RuneId {
+    /// `None` for a transaction of block 0 other than the first, as `0:0`
+    /// names the rune etched by the runestone itself.
+    pub fn new(block: u64, tx: u32) -> Option<RuneId> {
+        (block > 0 || tx == 0).then_some(RuneId { block, tx })
+    }
+
+    /// Id of the next edict, whose block and tx are deltas from this one. The
+    /// tx is absolute once the block changes.
+    pub fn next(self, block: u128, tx: u128) -> Option<RuneId> {
+        RuneId::new(
+            self.block.checked_add(u64::try_from(block).ok()?)?,
+            match block {
+                0 => self.tx.checked_add(u32::try_from(tx).ok()?)?,
+                _ => u32::try_from(tx).ok()?,
+            },
+        )
+    }
+
+    /// 12 bytes big endian, so ids are scanned in etching order.
+    pub(crate) fn to_bytes(self) -> [u8; 12] {
+        let mut bytes = [0u8; 12];
+        bytes[..8].copy_from_slice(&self.block.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.tx.to_be_bytes());
+        bytes
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Option<RuneId> {
+        Some(RuneId {
+            block: u64::from_be_bytes(bytes.get(..8)?.try_into().ok()?),
+            tx: u32::from_be_bytes(bytes.get(8..12)?.try_into().ok()?),
+        })
+    }
+}
+
+impl fmt::Display for RuneId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.block, self.tx)
+    }
+}
+
+impl FromStr for RuneId {
+    type Err = RuneNameError;
+
+    fn from_str(s: &str) -> Result<RuneId, RuneNameError> {
+        let (block, tx) = s.split_once(':').ok_or(RuneNameError)?;
+        RuneId::new(
+            block.parse().map_err(|_| RuneNameError)?,
+            tx.parse().map_err(|_| RuneNameError)?,
+        )
+        .ok_or(RuneNameError)
+    }
+}
+
+impl Serialize for RuneId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for RuneId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Sends `amount` of a rune to output `output`. An amount of 0 sends all of
+/// it, an output equal to the output count splits it between the outputs
+/// which are not OP_RETURN.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edict {
+    pub id: RuneId,
+    pub amount: u128,
+    pub output: u32,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Terms {
+    /// Number of mints.
+    pub cap: Option<u128>,
+    /// Amount of each mint.
+    pub amount: Option<u128>,
+    /// Absolute heights mints are open from and until, excluded.
+    pub height: (Option<u64>, Option<u64>),
+    /// Same, relative to the etching block.
+    pub offset: (Option<u64>, Option<u64>),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Etching {
+    pub divisibility: Option<u8>,
+    pub premine: Option<u128>,
+    /// Reserved name from the etching's id when `None`.
+    pub rune: Option<Rune>,
+    pub spacers: Option<u32>,
+    pub symbol: Option<char>,
+    pub terms: Option<Terms>,
+    pub turbo: bool,
+}
+
+impl Etching {
+    /// Premine and every mint, `None` if it overflows.
+    pub fn supply(&self) -> Option<u128> {
+        let premine = self.premine.unwrap_or_default();
+        let cap = self.terms.and_then(|terms| terms.cap).unwrap_or_default();
+        let amount = self
+            .terms
+            .and_then(|terms| terms.amount)
+            .unwrap_or_default();
+        premine.checked_add(cap.checked_mul(amount)?)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Runestone {
+    pub edicts: Vec<Edict>,
+    pub etching: Option<Etching>,
+    pub mint: Option<RuneId>,
+    /// Output of the runes no edict allocates.
+    pub pointer: Option<u32>,
+}
+
+/// A flawed runestone. Its etching and mint still count, but every rune of
+/// its transaction is burned.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Cenotaph {
+    pub flaw: Option<Flaw>,
+    pub etching: Option<Rune>,
+    pub mint: Option<RuneId>,
+}
+
+impl Cenotaph {
+    fn flawed(flaw: Flaw) -> Cenotaph {
+        Cenotaph {
+            flaw: Some(flaw),
+            ..Cenotaph::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Artifact {
+    Runestone(Box<Runestone>),
+    Cenotaph(Cenotaph),
+}
+
+impl Artifact {
+    pub fn mint(&self) -> Option<RuneId> {
+        match self {
+            Artifact::Runestone(runestone) => runestone.mint,
+            Artifact::Cenotaph(cenotaph) => cenotaph.mint,
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flaw {
+    #[error("Edict output greater than the output count")]
+    EdictOutput,
+    #[error("Invalid rune id in edict")]
+    EdictRuneId,
+    #[error("Invalid script in runestone")]
+    InvalidScript,
+    #[error("Opcode in runestone")]
+    Opcode,
+    #[error("Supply overflows u128")]
+    SupplyOverflow,
+    #[error("Edict integers are not a multiple of four")]
+    TrailingIntegers,
+    #[error("Field without value")]
+    TruncatedField,
+    #[error("Unrecognized even tag")]
+    UnrecognizedEvenTag,
+    #[error("Unrecognized flag")]
+    UnrecognizedFlag,
+    #[error("Invalid varint")]
+    Varint,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rune_id() {
+        let id = RuneId::new(840000, 1).unwrap();
+        assert_eq!(id.to_string(), "840000:1");
+        assert_eq!("840000:1".parse::<RuneId>(), Ok(id));
+        assert_eq!(RuneId::from_bytes(&id.to_bytes()), Some(id));
+        assert_eq!(RuneId::new(0, 1), None);
+
+        assert_eq!(id.next(0, 2), RuneId::new(840000, 3));
+        assert_eq!(id.next(1, 2), RuneId::new(840001, 2));
+        assert_eq!(RuneId::default().next(0, 1), None);
+        assert_eq!(id.next(0, u128::from(u32::MAX)), None);
+    }
+}
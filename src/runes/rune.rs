@@ -0,0 +1,185 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// Names at or above this are reserved, given to etchings without one.
+const RESERVED: u128 = 6402364363415443603228541259936211926;
+/// Blocks between unlocks of shorter names, twelve over a halving interval.
+const UNLOCK_INTERVAL: u64 = 210_000 / 12;
+
+/// Name of a rune, a bijective base-26 number written with `A` to `Z`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Rune(pub u128);
+
+impl Rune {
+    /// Name given to an etching without one.
+    pub fn reserved(block: u64, tx: u32) -> Rune {
+        Rune(RESERVED + ((u128::from(block) << 32) | u128::from(tx)))
+    }
+
+    pub fn is_reserved(self) -> bool {
+        self.0 >= RESERVED
+    }
+
+    /// Smallest name which can be etched at `height`. Names of 13 letters and
+    /// more are open from `first_rune_height`, and one letter shorter every
+    /// unlock interval after.
+    pub fn minimum_at_height(first_rune_height: u64, height: u64) -> Rune {
+        // Smallest name of each length.
+        let mut steps = [0u128; 13];
+        for length in 1..steps.len() {
+            steps[length] = steps[length - 1] * 26 + 26;
+        }
+
+        let offset = height.saturating_add(1);
+        if offset < first_rune_height {
+            return Rune(steps[12]);
+        }
+
+        let progress = offset - first_rune_height;
+        if progress >= UNLOCK_INTERVAL * 12 {
+            return Rune(0);
+        }
+
+        let length = 12 - (progress / UNLOCK_INTERVAL) as usize;
+        let start = steps[length];
+        let end = steps[length - 1];
+        let remainder = u128::from(progress % UNLOCK_INTERVAL);
+        Rune(start - (start - end) * remainder / u128::from(UNLOCK_INTERVAL))
+    }
+
+    /// Bytes an etching commits to in the tapscript of one of its inputs.
+    pub fn commitment(self) -> Vec<u8> {
+        let bytes = self.0.to_le_bytes();
+        let end = bytes
+            .iter()
+            .rposition(|byte| *byte != 0)
+            .map_or(0, |i| i + 1);
+        bytes[..end].to_vec()
+    }
+}
+
+impl fmt::Display for Rune {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut n = self.0;
+        if n == u128::MAX {
+            return write!(f, "BCGDENLQRQWDSLRUGSNLBTMFIJAV");
+        }
+
+        n += 1;
+        let mut letters = vec![];
+        while n > 0 {
+            letters.push(b'A' + ((n - 1) % 26) as u8);
+            n = (n - 1) / 26;
+        }
+        letters.reverse();
+        write!(f, "{}", String::from_utf8(letters).unwrap())
+    }
+}
+
+impl FromStr for Rune {
+    type Err = RuneNameError;
+
+    fn from_str(s: &str) -> Result<Rune, RuneNameError> {
+        if s.is_empty() {
+            return Err(RuneNameError);
+        }
+
+        let mut n = 0u128;
+        for (i, c) in s.chars().enumerate() {
+            if i > 0 {
+                n = n.checked_add(1).ok_or(RuneNameError)?;
+            }
+            n = n.checked_mul(26).ok_or(RuneNameError)?;
+            match c {
+                'A'..='Z' => {
+                    n = n
+                        .checked_add(c as u128 - 'A' as u128)
+                        .ok_or(RuneNameError)?
+                }
+                _ => return Err(RuneNameError),
+            }
+        }
+
+        Ok(Rune(n))
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[error("Invalid rune name")]
+pub struct RuneNameError;
+
+/// A rune name with spacers, bit `i` set for a `•` after letter `i`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpacedRune {
+    pub rune: Rune,
+    pub spacers: u32,
+}
+
+impl fmt::Display for SpacedRune {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rune = self.rune.to_string();
+        for (i, c) in rune.chars().enumerate() {
+            write!(f, "{}", c)?;
+            if i < rune.len() - 1 && self.spacers & (1 << i) != 0 {
+                write!(f, "•")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rune_name() {
+        for (n, name) in [(0, "A"), (25, "Z"), (26, "AA"), (701, "ZZ"), (702, "AAA")] {
+            assert_eq!(Rune(n).to_string(), name);
+            assert_eq!(name.parse::<Rune>(), Ok(Rune(n)));
+        }
+        assert_eq!(
+            Rune(u128::MAX).to_string().parse::<Rune>(),
+            Ok(Rune(u128::MAX))
+        );
+        assert!("a".parse::<Rune>().is_err());
+        assert_eq!(
+            SpacedRune {
+                rune: "UNCOMMONGOODS".parse().unwrap(),
+                spacers: 0b1000_0000,
+            }
+            .to_string(),
+            "UNCOMMON•GOODS"
+        );
+    }
+
+    #[test]
+    fn test_minimum_at_height() {
+        let first = 840_000;
+        assert_eq!(
+            Rune::minimum_at_height(first, 0).to_string(),
+            "AAAAAAAAAAAAA"
+        );
+        assert_eq!(
+            Rune::minimum_at_height(first, first - 1).to_string(),
+            "AAAAAAAAAAAAA"
+        );
+        assert_eq!(
+            Rune::minimum_at_height(first, first + UNLOCK_INTERVAL - 1).to_string(),
+            "AAAAAAAAAAAA"
+        );
+        assert_eq!(
+            Rune::minimum_at_height(first, first + 12 * UNLOCK_INTERVAL),
+            Rune(0)
+        );
+    }
+
+    #[test]
+    fn test_commitment() {
+        assert_eq!(Rune(0).commitment(), Vec::<u8>::new());
+        assert_eq!(Rune(0x0102).commitment(), vec![0x02, 0x01]);
+    }
+}
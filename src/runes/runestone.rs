@@ -0,0 +1,413 @@
+use std::collections::{HashMap, VecDeque};
+
+use bitcoin::blockdata::opcodes;
+use bitcoin::blockdata::script::{Instruction, Script};
+
+use crate::bitcoin::proto::tx::EvaluatedTx;
+use crate::runes::{Artifact, Cenotaph, Edict, Etching, Flaw, Rune, RuneId, Terms};
+use crate::varint;
+
+const MAGIC_NUMBER: opcodes::All = opcodes::all::OP_PUSHNUM_13;
+pub const MAX_DIVISIBILITY: u8 = 38;
+pub const MAX_SPACERS: u32 = 0b0000_0111_1111_1111_1111_1111_1111_1111;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tag {
+    Body = 0,
+    Flags = 2,
+    Rune = 4,
+    Premine = 6,
+    Cap = 8,
+    Amount = 10,
+    HeightStart = 12,
+    HeightEnd = 14,
+    OffsetStart = 16,
+    OffsetEnd = 18,
+    Mint = 20,
+    Pointer = 22,
+    Divisibility = 1,
+    Spacers = 3,
+    Symbol = 5,
+}
+
+impl Tag {
+    /// Takes the `N` first values of the field if `with` accepts them, the
+    /// field is left as is otherwise.
+    fn take<const N: usize, T>(
+        self,
+        fields: &mut HashMap<u128, VecDeque<u128>>,
+        with: impl Fn([u128; N]) -> Option<T>,
+    ) -> Option<T> {
+        let tag = self as u128;
+        let field = fields.get(&tag)?;
+
+        let mut values = [0u128; N];
+        for (i, value) in values.iter_mut().enumerate() {
+            *value = *field.get(i)?;
+        }
+
+        let value = with(values)?;
+        let field = fields.get_mut(&tag).unwrap();
+        field.drain(..N);
+        if field.is_empty() {
+            fields.remove(&tag);
+        }
+
+        Some(value)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Flag {
+    Etching = 0,
+    Terms = 1,
+    Turbo = 2,
+}
+
+impl Flag {
+    fn take(self, flags: &mut u128) -> bool {
+        let mask = 1 << self as u128;
+        let set = *flags & mask != 0;
+        *flags &= !mask;
+        set
+    }
+}
+
+/// Runestone of a transaction, in its first output starting with
+/// `OP_RETURN OP_13`. Any flaw in it makes a cenotaph.
+pub fn decipher(tx: &EvaluatedTx) -> Option<Artifact> {
+    let payload = match payload(tx)? {
+        Ok(payload) => payload,
+        Err(flaw) => return Some(Artifact::Cenotaph(Cenotaph::flawed(flaw))),
+    };
+
+    let mut integers = vec![];
+    let mut i = 0;
+    while i < payload.len() {
+        match varint::decode(&payload[i..]) {
+            Ok((integer, length)) => {
+                integers.push(integer);
+                i += length;
+            }
+            Err(_) => return Some(Artifact::Cenotaph(Cenotaph::flawed(Flaw::Varint))),
+        }
+    }
+
+    let mut flaw = None;
+    let mut edicts = vec![];
+    let mut fields = HashMap::<u128, VecDeque<u128>>::new();
+    let mut i = 0;
+    while i < integers.len() {
+        let tag = integers[i];
+        if tag == Tag::Body as u128 {
+            let mut id = RuneId::default();
+            for chunk in integers[i + 1..].chunks(4) {
+                if chunk.len() != 4 {
+                    flaw.get_or_insert(Flaw::TrailingIntegers);
+                    break;
+                }
+                let Some(next) = id.next(chunk[0], chunk[1]) else {
+                    flaw.get_or_insert(Flaw::EdictRuneId);
+                    break;
+                };
+                let output = match u32::try_from(chunk[3]) {
+                    Ok(output) if output as usize <= tx.outputs.len() => output,
+                    _ => {
+                        flaw.get_or_insert(Flaw::EdictOutput);
+                        break;
+                    }
+                };
+                id = next;
+                edicts.push(Edict {
+                    id,
+                    amount: chunk[2],
+                    output,
+                });
+            }
+            break;
+        }
+
+        let Some(&value) = integers.get(i + 1) else {
+            flaw.get_or_insert(Flaw::TruncatedField);
+            break;
+        };
+        fields.entry(tag).or_default().push_back(value);
+        i += 2;
+    }
+
+    let mut flags = Tag::Flags
+        .take(&mut fields, |[flags]| Some(flags))
+        .unwrap_or_default();
+
+    let etching = Flag::Etching.take(&mut flags).then(|| Etching {
+        divisibility: Tag::Divisibility.take(&mut fields, |[divisibility]| {
+            u8::try_from(divisibility)
+                .ok()
+                .filter(|divisibility| *divisibility <= MAX_DIVISIBILITY)
+        }),
+        premine: Tag::Premine.take(&mut fields, |[premine]| Some(premine)),
+        rune: Tag::Rune.take(&mut fields, |[rune]| Some(Rune(rune))),
+        spacers: Tag::Spacers.take(&mut fields, |[spacers]| {
+            u32::try_from(spacers)
+                .ok()
+                .filter(|spacers| *spacers <= MAX_SPACERS)
+        }),
+        symbol: Tag::Symbol.take(&mut fields, |[symbol]| {
+            char::from_u32(u32::try_from(symbol).ok()?)
+        }),
+        terms: Flag::Terms.take(&mut flags).then(|| Terms {
+            cap: Tag::Cap.take(&mut fields, |[cap]| Some(cap)),
+            height: (
+                Tag::HeightStart.take(&mut fields, |[start]| u64::try_from(start).ok()),
+                Tag::HeightEnd.take(&mut fields, |[end]| u64::try_from(end).ok()),
+            ),
+            amount: Tag::Amount.take(&mut fields, |[amount]| Some(amount)),
+            offset: (
+                Tag::OffsetStart.take(&mut fields, |[start]| u64::try_from(start).ok()),
+                Tag::OffsetEnd.take(&mut fields, |[end]| u64::try_from(end).ok()),
+            ),
+        }),
+        turbo: Flag::Turbo.take(&mut flags),
+    });
+
+    let mint = Tag::Mint.take(&mut fields, |[block, tx]| {
+        RuneId::new(u64::try_from(block).ok()?, u32::try_from(tx).ok()?)
+    });
+
+    let pointer = Tag::Pointer.take(&mut fields, |[pointer]| {
+        u32::try_from(pointer)
+            .ok()
+            .filter(|pointer| (*pointer as usize) < tx.outputs.len())
+    });
+
+    if etching.is_some_and(|etching| etching.supply().is_none()) {
+        flaw.get_or_insert(Flaw::SupplyOverflow);
+    }
+    if flags != 0 {
+        flaw.get_or_insert(Flaw::UnrecognizedFlag);
+    }
+    if fields.keys().any(|tag| tag % 2 == 0) {
+        flaw.get_or_insert(Flaw::UnrecognizedEvenTag);
+    }
+
+    match flaw {
+        Some(flaw) => Some(Artifact::Cenotaph(Cenotaph {
+            flaw: Some(flaw),
+            etching: etching.and_then(|etching| etching.rune),
+            mint,
+        })),
+        None => Some(Artifact::Runestone(Box::new(crate::runes::Runestone {
+            edicts,
+            etching,
+            mint,
+            pointer,
+        }))),
+    }
+}
+
+/// Pushed bytes after `OP_RETURN OP_13`, or the flaw of the script.
+fn payload(tx: &EvaluatedTx) -> Option<Result<Vec<u8>, Flaw>> {
+    for output in &tx.outputs {
+        let mut instructions = Script::from_bytes(&output.out.script_pubkey).instructions();
+        if instructions.next() != Some(Ok(Instruction::Op(opcodes::all::OP_RETURN))) {
+            continue;
+        }
+        if instructions.next() != Some(Ok(Instruction::Op(MAGIC_NUMBER))) {
+            continue;
+        }
+
+        let mut payload = vec![];
+        for instruction in instructions {
+            match instruction {
+                Ok(Instruction::PushBytes(push)) => payload.extend_from_slice(push.as_bytes()),
+                Ok(Instruction::Op(_)) => return Some(Err(Flaw::Opcode)),
+                Err(_) => return Some(Err(Flaw::InvalidScript)),
+            }
+        }
+
+        return Some(Ok(payload));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::blockdata::script::{Builder, PushBytesBuf};
+    use bitcoin::{absolute::LockTime, Network, ScriptBuf, Transaction, TxOut};
+
+    use super::*;
+    use crate::bitcoin::proto::script::AddressEncoding;
+    use crate::runes::Runestone;
+
+    fn tx(script_pubkey: ScriptBuf, outputs: usize) -> EvaluatedTx {
+        let mut output = vec![TxOut {
+            value: 0,
+            script_pubkey,
+        }];
+        output.resize(outputs, output[0].clone());
+        let tx = Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output,
+        };
+        EvaluatedTx::from_transaction(tx, AddressEncoding::Bitcoin(Network::Regtest))
+    }
+
+    fn runestone_script(integers: &[u128]) -> ScriptBuf {
+        let mut payload = vec![];
+        for integer in integers {
+            varint::encode_to_vec(*integer, &mut payload);
+        }
+        Builder::new()
+            .push_opcode(opcodes::all::OP_RETURN)
+            .push_opcode(MAGIC_NUMBER)
+            .push_slice(PushBytesBuf::try_from(payload).unwrap())
+            .into_script()
+    }
+
+    fn decipher_integers(integers: &[u128]) -> Option<Artifact> {
+        decipher(&tx(runestone_script(integers), 2))
+    }
+
+    fn flaw(integers: &[u128]) -> Option<Flaw> {
+        match decipher_integers(integers) {
+            Some(Artifact::Cenotaph(cenotaph)) => cenotaph.flaw,
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_decipher() {
+        assert_eq!(
+            decipher_integers(&[
+                2,
+                0b11,
+                4,
+                1000,
+                1,
+                2,
+                3,
+                1,
+                5,
+                'x' as u128,
+                6,
+                10,
+                8,
+                5,
+                10,
+                100,
+                12,
+                840000,
+                20,
+                840000,
+                20,
+                1,
+                22,
+                1,
+                0,
+                840000,
+                1,
+                7,
+                2,
+                0,
+                1,
+                0,
+                0,
+            ]),
+            Some(Artifact::Runestone(Box::new(Runestone {
+                edicts: vec![
+                    Edict {
+                        id: RuneId::new(840000, 1).unwrap(),
+                        amount: 7,
+                        output: 2,
+                    },
+                    Edict {
+                        id: RuneId::new(840000, 2).unwrap(),
+                        amount: 0,
+                        output: 0,
+                    },
+                ],
+                etching: Some(Etching {
+                    divisibility: Some(2),
+                    premine: Some(10),
+                    rune: Some(Rune(1000)),
+                    spacers: Some(1),
+                    symbol: Some('x'),
+                    terms: Some(Terms {
+                        cap: Some(5),
+                        amount: Some(100),
+                        height: (Some(840000), None),
+                        offset: (None, None),
+                    }),
+                    turbo: false,
+                }),
+                mint: RuneId::new(840000, 1),
+                pointer: Some(1),
+            })))
+        );
+
+        // Odd tags are ignored.
+        assert_eq!(
+            decipher_integers(&[1, 39, 9, 1]),
+            Some(Artifact::Runestone(Box::default()))
+        );
+
+        let transfer = |script| decipher(&tx(script, 1));
+        assert_eq!(
+            transfer(
+                Builder::new()
+                    .push_opcode(opcodes::all::OP_RETURN)
+                    .into_script()
+            ),
+            None
+        );
+        assert_eq!(
+            transfer(
+                Builder::new()
+                    .push_opcode(opcodes::all::OP_RETURN)
+                    .push_opcode(MAGIC_NUMBER)
+                    .push_opcode(opcodes::all::OP_VERIFY)
+                    .into_script()
+            ),
+            Some(Artifact::Cenotaph(Cenotaph::flawed(Flaw::Opcode)))
+        );
+    }
+
+    #[test]
+    fn test_cenotaph() {
+        assert_eq!(flaw(&[0, 1, 1, 1]), Some(Flaw::TrailingIntegers));
+        assert_eq!(flaw(&[0, 1, 1, 1, 3]), Some(Flaw::EdictOutput));
+        assert_eq!(flaw(&[0, 0, 1, 1, 0]), Some(Flaw::EdictRuneId));
+        assert_eq!(flaw(&[4]), Some(Flaw::TruncatedField));
+        assert_eq!(flaw(&[24, 1]), Some(Flaw::UnrecognizedEvenTag));
+        assert_eq!(flaw(&[2, 0b1000]), Some(Flaw::UnrecognizedFlag));
+        // An invalid value leaves its even tag unrecognized.
+        assert_eq!(flaw(&[22, 2]), Some(Flaw::UnrecognizedEvenTag));
+        assert_eq!(
+            flaw(&[2, 0b11, 6, 1, 8, u128::MAX, 10, u128::MAX]),
+            Some(Flaw::SupplyOverflow)
+        );
+
+        let unterminated = Builder::new()
+            .push_opcode(opcodes::all::OP_RETURN)
+            .push_opcode(MAGIC_NUMBER)
+            .push_slice([0x80])
+            .into_script();
+        assert_eq!(
+            decipher(&tx(unterminated, 1)),
+            Some(Artifact::Cenotaph(Cenotaph::flawed(Flaw::Varint)))
+        );
+
+        // The etched rune and the mint of a cenotaph are kept.
+        assert_eq!(
+            decipher_integers(&[2, 1, 4, 1000, 20, 840000, 20, 1, 24, 0]),
+            Some(Artifact::Cenotaph(Cenotaph {
+                flaw: Some(Flaw::UnrecognizedEvenTag),
+                etching: Some(Rune(1000)),
+                mint: RuneId::new(840000, 1),
+            }))
+        );
+    }
+}
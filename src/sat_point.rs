@@ -0,0 +1,125 @@
+use std::{fmt, num::ParseIntError, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::outpoint::{OutPoint, OutPointError};
+use crate::varint::{self, VarintError};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum SatPointError {
+    #[error("Missing separator in: `{0}`")]
+    Separator(String),
+    #[error("Invalid outpoint: `{0}`")]
+    OutPoint(#[from] OutPointError),
+    #[error("Invalid offset: `{0}`")]
+    Offset(ParseIntError),
+    #[error("Invalid length: `{0}`")]
+    Length(usize),
+    #[error("Invalid varint: `{0}`")]
+    Varint(#[from] VarintError),
+}
+
+/// A sat inside an output, displayed as `<txid>:<vout>:<offset>`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SatPoint {
+    pub outpoint: OutPoint,
+    pub offset: u64,
+}
+
+impl SatPoint {
+    pub fn new(outpoint: OutPoint, offset: u64) -> SatPoint {
+        SatPoint { outpoint, offset }
+    }
+
+    /// Compact encoding: outpoint encoding followed by varint offset.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(32 + 5 + 10);
+        self.outpoint.encode_to_vec(&mut bytes);
+        varint::encode_to_vec(self.offset.into(), &mut bytes);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<SatPoint, SatPointError> {
+        let (outpoint, len) = OutPoint::decode(bytes)?;
+        let (offset, offset_len) = varint::decode(&bytes[len..])?;
+        if len + offset_len != bytes.len() {
+            return Err(SatPointError::Length(bytes.len()));
+        }
+
+        Ok(SatPoint {
+            outpoint,
+            offset: u64::try_from(offset).map_err(|_| VarintError::Overflow)?,
+        })
+    }
+}
+
+impl fmt::Display for SatPoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.outpoint, self.offset)
+    }
+}
+
+impl FromStr for SatPoint {
+    type Err = SatPointError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (outpoint, offset) = s
+            .rsplit_once(':')
+            .ok_or_else(|| SatPointError::Separator(s.to_string()))?;
+
+        Ok(SatPoint {
+            outpoint: outpoint.parse()?,
+            offset: offset.parse().map_err(SatPointError::Offset)?,
+        })
+    }
+}
+
+impl Serialize for SatPoint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for SatPoint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SATPOINT: &str =
+        "1111111111111111111111111111111111111111111111111111111111111111:2:5000";
+
+    #[test]
+    fn test_satpoint_display_round_trip() {
+        let satpoint = SATPOINT.parse::<SatPoint>().unwrap();
+        assert_eq!(satpoint.outpoint.vout, 2);
+        assert_eq!(satpoint.offset, 5000);
+        assert_eq!(satpoint.to_string(), SATPOINT);
+    }
+
+    #[test]
+    fn test_satpoint_bytes_round_trip() {
+        let satpoint = SATPOINT.parse::<SatPoint>().unwrap();
+        assert_eq!(SatPoint::from_bytes(&satpoint.to_bytes()), Ok(satpoint));
+    }
+
+    #[test]
+    fn test_satpoint_malformed() {
+        assert!(matches!(
+            "1111111111111111111111111111111111111111111111111111111111111111:2"
+                .parse::<SatPoint>(),
+            Err(SatPointError::OutPoint(_))
+        ));
+        assert!(matches!(
+            format!("{}x", SATPOINT).parse::<SatPoint>(),
+            Err(SatPointError::Offset(_))
+        ));
+    }
+}
@@ -0,0 +1,266 @@
+//! `output_value` snapshots.
+//!
+//! A snapshot is a directory with `manifest.json` and `output_value.dat`. The data
+//! file holds every entry in key order, each as a key length byte, the key and the
+//! value. Its sha256 is the commitment to the utxo set recorded in the manifest.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::outpoint::{OutPoint, OutPointError};
+use crate::store::{Store, StoreError, WriteBatch};
+
+const MANIFEST: &str = "manifest.json";
+const DATA: &str = "output_value.dat";
+const SNAPSHOT_VERSION: u32 = 1;
+const RESTORE_BATCH_SIZE: usize = 100_000;
+
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    #[error("IO error: `{0}`")]
+    IOError(#[from] io::Error),
+    #[error("Manifest error: `{0}`")]
+    ManifestError(#[from] serde_json::Error),
+    #[error("Store error: `{0}`")]
+    StoreError(#[from] StoreError),
+    #[error("OutPoint error: `{0}`")]
+    OutPointError(#[from] OutPointError),
+    #[error("Unsupported snapshot version: `{0}`")]
+    UnsupportedVersion(u32),
+    #[error("Snapshot already exists: `{0}`")]
+    AlreadyExists(String),
+    #[error("Malformed entry `{0}` in snapshot")]
+    MalformedEntry(u64),
+    #[error("Output value of key `{0:?}` does not fit in a snapshot entry")]
+    UnsupportedEntry(Vec<u8>),
+    #[error("Snapshot has `{0}` entries, manifest records: `{1}`")]
+    EntryCountMismatch(u64, u64),
+    #[error("Snapshot hash is `{0}`, manifest records: `{1}`")]
+    HashMismatch(String, String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub version: u32,
+    pub height: u64,
+    pub block_hash: String,
+    pub entries: u64,
+    pub sha256: String,
+}
+
+impl Manifest {
+    pub fn read(dir: &Path) -> Result<Manifest, SnapshotError> {
+        let manifest: Manifest = serde_json::from_reader(File::open(dir.join(MANIFEST))?)?;
+        if manifest.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(manifest.version));
+        }
+
+        Ok(manifest)
+    }
+}
+
+/// Writes every entry of `output_value` into a new snapshot in `dir`.
+pub fn create(
+    output_value: &mut dyn Store,
+    height: u64,
+    block_hash: String,
+    dir: &Path,
+) -> Result<Manifest, SnapshotError> {
+    if dir.join(MANIFEST).exists() {
+        return Err(SnapshotError::AlreadyExists(
+            dir.to_string_lossy().to_string(),
+        ));
+    }
+    fs::create_dir_all(dir)?;
+
+    let mut writer = BufWriter::new(File::create(dir.join(DATA))?);
+    let mut engine = sha256::Hash::engine();
+    let mut entries = 0;
+    let mut result = Ok(());
+
+    output_value.snapshot()?.scan(&[], &mut |key, value| {
+        // Entries are read back as a one byte key length, the key and 8 bytes of value.
+        let key_len = match u8::try_from(key.len()) {
+            Ok(key_len) if value.len() == 8 => key_len,
+            _ => {
+                result = Err(SnapshotError::UnsupportedEntry(key.to_vec()));
+                return false;
+            }
+        };
+        let mut record = Vec::with_capacity(1 + key.len() + value.len());
+        record.push(key_len);
+        record.extend_from_slice(key);
+        record.extend_from_slice(value);
+
+        engine.input(&record);
+        entries += 1;
+        result = writer.write_all(&record).map_err(SnapshotError::from);
+        result.is_ok()
+    })?;
+    result?;
+    writer.flush()?;
+
+    let manifest = Manifest {
+        version: SNAPSHOT_VERSION,
+        height,
+        block_hash,
+        entries,
+        sha256: sha256::Hash::from_engine(engine).to_string(),
+    };
+    serde_json::to_writer_pretty(File::create(dir.join(MANIFEST))?, &manifest)?;
+
+    Ok(manifest)
+}
+
+/// Checks the entry count and hash of the data file against the manifest.
+pub fn verify(dir: &Path, manifest: &Manifest) -> Result<(), SnapshotError> {
+    let mut engine = sha256::Hash::engine();
+    let entries = read_entries(dir, |record, _, _| {
+        engine.input(record);
+        Ok(())
+    })?;
+
+    if entries != manifest.entries {
+        return Err(SnapshotError::EntryCountMismatch(entries, manifest.entries));
+    }
+
+    let hash = sha256::Hash::from_engine(engine).to_string();
+    if hash != manifest.sha256 {
+        return Err(SnapshotError::HashMismatch(hash, manifest.sha256.clone()));
+    }
+
+    Ok(())
+}
+
+/// Writes the entries of a verified snapshot into `output_value`.
+pub fn restore(output_value: &mut dyn Store, dir: &Path) -> Result<u64, SnapshotError> {
+    let mut wb = WriteBatch::new();
+    let entries = read_entries(dir, |_, key, value| {
+        wb.put(key, value);
+        if wb.count() >= RESTORE_BATCH_SIZE {
+            output_value.write(std::mem::take(&mut wb))?;
+        }
+        Ok(())
+    })?;
+    output_value.write(wb)?;
+    output_value.flush()?;
+
+    Ok(entries)
+}
+
+/// Calls `f` with every record, its key and its value.
+fn read_entries<F>(dir: &Path, mut f: F) -> Result<u64, SnapshotError>
+where
+    F: FnMut(&[u8], &[u8], &[u8]) -> Result<(), SnapshotError>,
+{
+    let mut reader = BufReader::new(File::open(dir.join(DATA))?);
+    let mut record = Vec::with_capacity(64);
+    let mut entries = 0;
+
+    loop {
+        let mut key_len = [0u8];
+        if reader.read(&mut key_len)? == 0 {
+            break;
+        }
+
+        record.clear();
+        record.push(key_len[0]);
+        let key_len = key_len[0] as usize;
+        record.resize(1 + key_len + 8, 0);
+        reader
+            .read_exact(&mut record[1..])
+            .map_err(|_| SnapshotError::MalformedEntry(entries))?;
+
+        let (key, value) = record[1..].split_at(key_len);
+        OutPoint::from_bytes(key)?;
+        f(&record, key, value)?;
+        entries += 1;
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+
+    const TXID: &str = "1111111111111111111111111111111111111111111111111111111111111111";
+
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("ordi-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn output_value() -> MemoryStore {
+        let mut output_value = MemoryStore::new();
+        for vout in 0..3u32 {
+            let outpoint = OutPoint::new(TXID.parse().unwrap(), vout);
+            output_value
+                .put(
+                    &outpoint.to_bytes(),
+                    (546 * vout as u64).to_le_bytes().as_slice(),
+                )
+                .unwrap();
+        }
+        output_value
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let dir = test_dir("snapshot-round-trip");
+        let manifest = create(&mut output_value(), 100, "hash".into(), &dir).unwrap();
+        assert_eq!(manifest.entries, 3);
+        assert_eq!(Manifest::read(&dir).unwrap(), manifest);
+        verify(&dir, &manifest).unwrap();
+
+        let mut restored = MemoryStore::new();
+        assert_eq!(restore(&mut restored, &dir).unwrap(), 3);
+        let outpoint = OutPoint::new(TXID.parse().unwrap(), 2);
+        assert_eq!(
+            restored.get(&outpoint.to_bytes()).unwrap(),
+            Some(1092u64.to_le_bytes().to_vec())
+        );
+
+        assert!(matches!(
+            create(&mut output_value(), 100, "hash".into(), &dir),
+            Err(SnapshotError::AlreadyExists(_))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_tampered() {
+        let dir = test_dir("snapshot-tampered");
+        let manifest = create(&mut output_value(), 100, "hash".into(), &dir).unwrap();
+
+        let mut data = fs::read(dir.join(DATA)).unwrap();
+        *data.last_mut().unwrap() ^= 1;
+        fs::write(dir.join(DATA), data).unwrap();
+
+        assert!(matches!(
+            verify(&dir, &manifest),
+            Err(SnapshotError::HashMismatch(_, _))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_unsupported_entry() {
+        let dir = test_dir("snapshot-unsupported");
+        let mut output_value = output_value();
+        output_value.put(&[0; 256], &[0; 8]).unwrap();
+
+        assert!(matches!(
+            create(&mut output_value, 100, "hash".into(), &dir),
+            Err(SnapshotError::UnsupportedEntry(key)) if key.len() == 256
+        ));
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,204 @@
+//! Sats Names: `.sats` names registered by inscriptions, kept in their own
+//! keyspace.
+//!
+//! A name is inscribed as plain text, `name.sats`, or as json,
+//! `{"p":"sns","op":"reg","name":"name.sats"}`. The first inscription of a
+//! name by inscription number owns it, cursed inscriptions do not register.
+//! Its holder is whoever holds the inscription, resolved from `inscription_output`.
+
+use serde::{Deserialize, Serialize};
+
+use crate::inscription::Inscription;
+use crate::inscription_id::InscriptionId;
+use crate::store::keyspace::{self, KeyspaceError};
+use crate::store::Store;
+
+const PROTOCOL: &str = "sns";
+const SUFFIX: &str = ".sats";
+
+/// `n<name>` -> registration, as json.
+const NAME_PREFIX: u8 = b'n';
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SatsName {
+    /// Normalized, with its `.sats` suffix.
+    pub name: String,
+    pub inscription_id: InscriptionId,
+    pub number: i64,
+    pub height: u64,
+}
+
+#[derive(Deserialize)]
+struct Registration {
+    p: String,
+    op: String,
+    name: String,
+}
+
+/// Name registered by a text or json inscription, normalized.
+pub fn parse_name(inscription: &Inscription) -> Option<String> {
+    let content_type = inscription.content_type.as_deref()?;
+    if !content_type.starts_with(b"text/plain") && !content_type.starts_with(b"application/json") {
+        return None;
+    }
+
+    let body = std::str::from_utf8(inscription.body.as_deref()?).ok()?;
+    match serde_json::from_str::<Registration>(body) {
+        Ok(registration) if registration.p == PROTOCOL && registration.op == "reg" => {
+            normalize(&registration.name)
+        }
+        Ok(_) => None,
+        Err(_) if content_type.starts_with(b"text/plain") => normalize(body),
+        Err(_) => None,
+    }
+}
+
+/// Trimmed and lowercase name, a single label of any characters but dots,
+/// whitespace and control characters, followed by `.sats`.
+pub fn normalize(name: &str) -> Option<String> {
+    let name = name.trim().to_lowercase();
+    let label = name.strip_suffix(SUFFIX)?;
+    if label.is_empty()
+        || label
+            .chars()
+            .any(|c| c == '.' || c.is_whitespace() || c.is_control())
+    {
+        return None;
+    }
+
+    Some(name)
+}
+
+pub struct SnsIndex<'a> {
+    store: &'a mut dyn Store,
+}
+
+impl<'a> SnsIndex<'a> {
+    pub fn new(store: &'a mut dyn Store) -> SnsIndex<'a> {
+        SnsIndex { store }
+    }
+
+    /// Registers the name of a new inscription numbered `number`. Returns
+    /// the name, `None` if it is invalid or already registered.
+    pub fn inscribe(
+        &mut self,
+        inscription_id: InscriptionId,
+        number: i64,
+        inscription: &Inscription,
+        height: u64,
+    ) -> Result<Option<String>, KeyspaceError> {
+        let Some(name) = parse_name(inscription) else {
+            return Ok(None);
+        };
+        if number < 0 || self.name(&name)?.is_some() {
+            return Ok(None);
+        }
+
+        let registration = SatsName {
+            name: name.clone(),
+            inscription_id,
+            number,
+            height,
+        };
+        keyspace::put_json(self.store, &name_key(&name), &registration)?;
+        Ok(Some(name))
+    }
+
+    /// Registration of a name, in any case.
+    pub fn name(&mut self, name: &str) -> Result<Option<SatsName>, KeyspaceError> {
+        let Some(name) = normalize(name) else {
+            return Ok(None);
+        };
+        keyspace::get_json(self.store, &name_key(&name))
+    }
+}
+
+fn name_key(name: &str) -> Vec<u8> {
+    let mut key = vec![NAME_PREFIX];
+    key.extend_from_slice(name.as_bytes());
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{id, inscription};
+    use crate::store::MemoryStore;
+
+    #[test]
+    fn test_parse_name() {
+        assert_eq!(
+            parse_name(&inscription(" Satoshi.SATS\n")),
+            Some("satoshi.sats".to_string())
+        );
+        assert_eq!(
+            parse_name(&inscription(r#"{"p":"sns","op":"reg","name":"Hal.sats"}"#)),
+            Some("hal.sats".to_string())
+        );
+        assert_eq!(
+            parse_name(&Inscription {
+                content_type: Some(b"application/json".to_vec()),
+                ..inscription(r#"{"p":"sns","op":"reg","name":"hal.sats"}"#)
+            }),
+            Some("hal.sats".to_string())
+        );
+        assert_eq!(
+            parse_name(&inscription(
+                r#"{"p":"sns","op":"update","name":"hal.sats"}"#
+            )),
+            None
+        );
+        assert_eq!(parse_name(&inscription("a.b.sats")), None);
+        assert_eq!(parse_name(&inscription("sat oshi.sats")), None);
+        assert_eq!(parse_name(&inscription(".sats")), None);
+        assert_eq!(parse_name(&inscription("satoshi.btc")), None);
+        assert_eq!(
+            parse_name(&Inscription {
+                content_type: Some(b"application/json".to_vec()),
+                ..inscription("satoshi.sats")
+            }),
+            None
+        );
+    }
+
+    #[test]
+    fn test_sns_index() {
+        let mut store = MemoryStore::new();
+        let mut index = SnsIndex::new(&mut store);
+
+        assert_eq!(
+            index
+                .inscribe(id(0), -1, &inscription("satoshi.sats"), 1)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            index
+                .inscribe(id(1), 0, &inscription("Satoshi.sats"), 1)
+                .unwrap(),
+            Some("satoshi.sats".to_string())
+        );
+        // First inscription wins.
+        assert_eq!(
+            index
+                .inscribe(
+                    id(2),
+                    1,
+                    &inscription(r#"{"p":"sns","op":"reg","name":"satoshi.sats"}"#),
+                    2
+                )
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            index.name("SATOSHI.sats").unwrap(),
+            Some(SatsName {
+                name: "satoshi.sats".to_string(),
+                inscription_id: id(1),
+                number: 0,
+                height: 1,
+            })
+        );
+        assert_eq!(index.name("hal.sats").unwrap(), None);
+    }
+}
@@ -0,0 +1,132 @@
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bitcoin::hashes::sha256d;
+
+use crate::bitcoin::index::Index;
+use crate::bitcoin::prefetch::BlockPrefetcher;
+use crate::bitcoin::proto::block::Block;
+use crate::source::{BlockSource, BlockSourceError};
+
+/// Blocks in blk files, up to the best block of the index.
+///
+/// Reading blocks in height order is served by a `BlockPrefetcher`, which
+/// restarts whenever a block out of order is asked for.
+pub struct BlkBlockSource {
+    index: Rc<Index>,
+    prefetch_blocks: usize,
+    prefetcher: Option<(u64, BlockPrefetcher<Block>)>,
+    rescan_interval: Option<Duration>,
+    rescanned_at: Instant,
+}
+
+impl BlkBlockSource {
+    pub fn new(index: Rc<Index>, prefetch_blocks: usize) -> BlkBlockSource {
+        BlkBlockSource {
+            index,
+            prefetch_blocks,
+            prefetcher: None,
+            rescan_interval: None,
+            rescanned_at: Instant::now(),
+        }
+    }
+
+    /// Refreshes the index at most every `interval` while waiting for blocks,
+    /// to pick up the ones the node wrote since.
+    pub fn with_rescan(mut self, interval: Duration) -> BlkBlockSource {
+        self.rescan_interval = Some(interval);
+        self
+    }
+
+    pub fn index(&self) -> Rc<Index> {
+        self.index.clone()
+    }
+
+    fn rescan(&mut self) -> Result<(), BlockSourceError> {
+        self.index.refresh()?;
+        self.prefetcher = None;
+        self.rescanned_at = Instant::now();
+        Ok(())
+    }
+}
+
+impl BlockSource for BlkBlockSource {
+    fn tip(&mut self) -> Result<Option<u64>, BlockSourceError> {
+        Ok(Some(self.index.max_height()))
+    }
+
+    fn block_hash(&mut self, height: u64) -> Result<Option<sha256d::Hash>, BlockSourceError> {
+        Ok(self
+            .index
+            .get_index_entry(height)?
+            .map(|entry| entry.block_hash))
+    }
+
+    fn block(&mut self, height: u64) -> Result<Option<Block>, BlockSourceError> {
+        let max_height = self.index.max_height();
+        if height > max_height {
+            return Ok(None);
+        }
+
+        let mut prefetcher = match self.prefetcher.take() {
+            Some((next_height, prefetcher)) if next_height == height => prefetcher,
+            _ => BlockPrefetcher::new(
+                self.index.files.clone(),
+                self.index.block_locations(height, max_height)?,
+                self.prefetch_blocks,
+                |_, block| block,
+            ),
+        };
+
+        match prefetcher.next() {
+            Some(block) => {
+                let (_, block) = block?;
+                self.prefetcher = Some((height + 1, prefetcher));
+                Ok(Some(block))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn block_by_hash(
+        &mut self,
+        block_hash: &sha256d::Hash,
+    ) -> Result<Option<Block>, BlockSourceError> {
+        let entry = match self.index.height_of(block_hash)? {
+            Some(height) => self.index.get_index_entry(height)?,
+            None => None,
+        };
+        match entry {
+            Some(entry) => Ok(Some(
+                self.index
+                    .files
+                    .read_block(entry.blk_index, entry.data_offset)?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    fn wait_for_height(
+        &mut self,
+        height: u64,
+        timeout: Duration,
+    ) -> Result<bool, BlockSourceError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.index.max_height() >= height {
+                return Ok(true);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(false);
+            }
+            match self.rescan_interval {
+                Some(interval) if now >= self.rescanned_at + interval => self.rescan()?,
+                Some(interval) => thread::sleep((self.rescanned_at + interval).min(deadline) - now),
+                None => thread::sleep(deadline - now),
+            }
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use bitcoin::hashes::sha256d;
+
+use crate::bitcoin::proto::block::Block;
+use crate::bitcoin::CoinType;
+use crate::source::{BlockSource, BlockSourceError};
+
+/// Blocks of `coin` held in memory, from `first_height` on, e.g. fixtures in tests.
+///
+/// Clones share the blocks, so one can `push` while another waits for them.
+#[derive(Clone)]
+pub struct MemoryBlockSource {
+    first_height: u64,
+    coin: CoinType,
+    blocks: Arc<(Mutex<Vec<bitcoin::Block>>, Condvar)>,
+}
+
+impl MemoryBlockSource {
+    pub fn new(first_height: u64, coin: CoinType) -> MemoryBlockSource {
+        MemoryBlockSource {
+            first_height,
+            coin,
+            blocks: Arc::new((Mutex::new(vec![]), Condvar::new())),
+        }
+    }
+
+    /// Appends a block on top of the tip and wakes up waiters.
+    pub fn push(&self, block: bitcoin::Block) {
+        let (blocks, pushed) = &*self.blocks;
+        blocks.lock().unwrap().push(block);
+        pushed.notify_all();
+    }
+
+    fn get(&self, height: u64) -> Option<bitcoin::Block> {
+        let blocks = self.blocks.0.lock().unwrap();
+        height
+            .checked_sub(self.first_height)
+            .and_then(|i| blocks.get(i as usize))
+            .cloned()
+    }
+
+    fn decode(&self, block: bitcoin::Block) -> Block {
+        Block::from_bitcoin(block, self.coin.address_encoding())
+    }
+
+    fn tip_of(&self, len: usize) -> Option<u64> {
+        (len as u64).checked_sub(1).map(|i| self.first_height + i)
+    }
+}
+
+impl BlockSource for MemoryBlockSource {
+    fn tip(&mut self) -> Result<Option<u64>, BlockSourceError> {
+        Ok(self.tip_of(self.blocks.0.lock().unwrap().len()))
+    }
+
+    fn block_hash(&mut self, height: u64) -> Result<Option<sha256d::Hash>, BlockSourceError> {
+        Ok(self
+            .get(height)
+            .map(|block| block.block_hash().to_raw_hash()))
+    }
+
+    fn block(&mut self, height: u64) -> Result<Option<Block>, BlockSourceError> {
+        Ok(self.get(height).map(|block| self.decode(block)))
+    }
+
+    fn block_by_hash(
+        &mut self,
+        block_hash: &sha256d::Hash,
+    ) -> Result<Option<Block>, BlockSourceError> {
+        let blocks = self.blocks.0.lock().unwrap();
+        Ok(blocks
+            .iter()
+            .find(|block| block.block_hash().to_raw_hash() == *block_hash)
+            .cloned()
+            .map(|block| self.decode(block)))
+    }
+
+    fn wait_for_height(
+        &mut self,
+        height: u64,
+        timeout: Duration,
+    ) -> Result<bool, BlockSourceError> {
+        let deadline = Instant::now() + timeout;
+        let (blocks, pushed) = &*self.blocks;
+        let mut blocks = blocks.lock().unwrap();
+        loop {
+            if self.tip_of(blocks.len()).is_some_and(|tip| tip >= height) {
+                return Ok(true);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(false);
+            }
+            blocks = pushed.wait_timeout(blocks, deadline - now).unwrap().0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::BitcoinRegtest;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::hashes::Hash;
+    use bitcoin::Network;
+    use std::thread;
+
+    fn block(prev_block: &bitcoin::Block, nonce: u32) -> bitcoin::Block {
+        let mut block = prev_block.clone();
+        block.header.prev_blockhash = prev_block.block_hash();
+        block.header.nonce = nonce;
+        block
+    }
+
+    #[test]
+    fn test_memory_block_source() {
+        let genesis = genesis_block(Network::Regtest);
+        let next = block(&genesis, 1);
+
+        let mut source = MemoryBlockSource::new(100, BitcoinRegtest.into());
+        assert_eq!(source.tip().unwrap(), None);
+        source.push(genesis.clone());
+        source.push(next.clone());
+
+        assert_eq!(source.tip().unwrap(), Some(101));
+        assert_eq!(
+            source.block_hash(101).unwrap(),
+            Some(next.block_hash().to_raw_hash())
+        );
+        assert!(source.block(99).unwrap().is_none());
+        assert!(source.block(102).unwrap().is_none());
+
+        let block = source.block(100).unwrap().unwrap();
+        assert_eq!(block.header.hash, genesis.block_hash().to_raw_hash());
+        assert_eq!(block.txs[0].hash, genesis.txdata[0].txid().to_raw_hash());
+        let block = source
+            .block_by_hash(&next.block_hash().to_raw_hash())
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            block.header.value.prev_hash,
+            genesis.block_hash().to_raw_hash()
+        );
+        assert!(source
+            .block_by_hash(&sha256d::Hash::all_zeros())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_memory_block_source_wakes_up_waiter() {
+        let mut source = MemoryBlockSource::new(0, BitcoinRegtest.into());
+        assert!(!source
+            .wait_for_height(0, Duration::from_millis(10))
+            .unwrap());
+
+        let pusher = source.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            pusher.push(genesis_block(Network::Regtest));
+        });
+
+        assert!(source.wait_for_height(0, Duration::from_secs(10)).unwrap());
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,71 @@
+//! Where blocks come from.
+//!
+//! `Ordi` indexes blocks of any `BlockSource`: blk files while catching up,
+//! bitcoind's RPC near the tip, or blocks held in memory in tests.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bitcoin::hashes::sha256d;
+use thiserror::Error;
+
+use crate::bitcoin::blk::BlkError;
+use crate::bitcoin::index::IndexError;
+use crate::bitcoin::proto::block::Block;
+
+pub mod blk;
+pub mod memory;
+pub mod rpc;
+
+pub use self::blk::BlkBlockSource;
+pub use self::memory::MemoryBlockSource;
+pub use self::rpc::RpcBlockSource;
+
+/// Interval of `BlockSource::wait_for_height` checking the tip.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Error, Debug)]
+pub enum BlockSourceError {
+    #[error("Index error: `{0}`")]
+    IndexError(#[from] IndexError),
+    #[error("Blk error: `{0}`")]
+    BlkError(#[from] BlkError),
+    #[error("Bitcoin rpc error: `{0}`")]
+    BitcoinRpcError(#[from] bitcoincore_rpc::Error),
+    #[error("Malformed block: `{0}`")]
+    MalformedBlock(#[from] anyhow::Error),
+}
+
+pub trait BlockSource {
+    /// Height of the best block, `None` if there is none.
+    fn tip(&mut self) -> Result<Option<u64>, BlockSourceError>;
+
+    fn block_hash(&mut self, height: u64) -> Result<Option<sha256d::Hash>, BlockSourceError>;
+
+    fn block(&mut self, height: u64) -> Result<Option<Block>, BlockSourceError>;
+
+    fn block_by_hash(
+        &mut self,
+        block_hash: &sha256d::Hash,
+    ) -> Result<Option<Block>, BlockSourceError>;
+
+    /// Waits up to `timeout` for the tip to reach `height`, returns whether it did.
+    fn wait_for_height(
+        &mut self,
+        height: u64,
+        timeout: Duration,
+    ) -> Result<bool, BlockSourceError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.tip()?.is_some_and(|tip| tip >= height) {
+                return Ok(true);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(false);
+            }
+            thread::sleep(POLL_INTERVAL.min(deadline - now));
+        }
+    }
+}
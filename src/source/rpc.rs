@@ -0,0 +1,152 @@
+use std::io::Cursor;
+use std::sync::Arc;
+
+use bitcoin::hashes::hex::FromHex;
+use bitcoin::hashes::sha256d;
+use bitcoin::BlockHash;
+use bitcoincore_rpc::{Client, RpcApi};
+
+use crate::bitcoin::block_reader::BlockchainRead;
+use crate::bitcoin::proto::block::Block;
+use crate::bitcoin::CoinType;
+use crate::source::{BlockSource, BlockSourceError};
+
+/// Blocks of a running bitcoind.
+pub struct RpcBlockSource {
+    client: Arc<Client>,
+    coin: CoinType,
+}
+
+impl RpcBlockSource {
+    pub fn new(client: Arc<Client>, coin: CoinType) -> RpcBlockSource {
+        RpcBlockSource { client, coin }
+    }
+}
+
+/// Decodes a raw block of `getblock <hash> 0` like blocks of blk files, as
+/// `rust_bitcoin` knows neither AuxPoW headers nor MWEB transactions.
+pub fn decode_block_hex(hex: &str, coin: &CoinType) -> Result<Block, BlockSourceError> {
+    let bytes = Vec::<u8>::from_hex(hex).map_err(anyhow::Error::new)?;
+    let size = u32::try_from(bytes.len()).map_err(anyhow::Error::new)?;
+    Ok(Cursor::new(bytes).read_block(size, coin)?)
+}
+
+impl BlockSource for RpcBlockSource {
+    fn tip(&mut self) -> Result<Option<u64>, BlockSourceError> {
+        Ok(Some(self.client.get_block_count()?))
+    }
+
+    fn block_hash(&mut self, height: u64) -> Result<Option<sha256d::Hash>, BlockSourceError> {
+        if height > self.client.get_block_count()? {
+            return Ok(None);
+        }
+
+        Ok(Some(self.client.get_block_hash(height)?.to_raw_hash()))
+    }
+
+    fn block(&mut self, height: u64) -> Result<Option<Block>, BlockSourceError> {
+        match self.block_hash(height)? {
+            Some(block_hash) => self.block_by_hash(&block_hash),
+            None => Ok(None),
+        }
+    }
+
+    fn block_by_hash(
+        &mut self,
+        block_hash: &sha256d::Hash,
+    ) -> Result<Option<Block>, BlockSourceError> {
+        let block_hash = BlockHash::from_raw_hash(*block_hash);
+        let hex = self.client.get_block_hex(&block_hash)?;
+        Ok(Some(decode_block_hex(&hex, &self.coin)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::absolute::LockTime;
+    use bitcoin::consensus::serialize;
+    use bitcoin::{OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness};
+
+    use super::*;
+    use crate::bitcoin::{Dogecoin, Litecoin};
+
+    fn header(version: u32) -> Vec<u8> {
+        let mut header = version.to_le_bytes().to_vec();
+        header.extend_from_slice(&[0x11; 64]);
+        header.extend_from_slice(&[0x22; 12]);
+        header
+    }
+
+    fn coinbase(value: u64) -> Vec<u8> {
+        serialize(&Transaction {
+            version: 1,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::from_bytes(vec![0x01, 0x01]),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value,
+                script_pubkey: ScriptBuf::from_bytes(vec![0x51]),
+            }],
+        })
+    }
+
+    fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn test_decode_aux_pow_block() {
+        let coin = CoinType::from(Dogecoin);
+        let mut block = header(0x00620102);
+        // AuxPoW: parent coinbase, parent hash, two empty merkle branches and
+        // the parent header.
+        block.extend_from_slice(&coinbase(1));
+        block.extend_from_slice(&[0x33; 32]);
+        block.extend_from_slice(&[0x00, 0, 0, 0, 0]);
+        block.extend_from_slice(&[0x00, 0, 0, 0, 0]);
+        block.extend_from_slice(&header(2));
+        block.push(0x01);
+        block.extend_from_slice(&coinbase(10_000));
+
+        let block = decode_block_hex(&to_hex(&block), &coin).unwrap();
+        let aux_pow = block.aux_pow_extension.as_ref().unwrap();
+        assert_eq!(aux_pow.coinbase_tx.outputs[0].value, 1);
+        assert_eq!(aux_pow.parent_block.version, 2);
+        assert_eq!(block.txs.len(), 1);
+        assert_eq!(block.txs[0].value.outputs[0].out.value, 10_000);
+
+        // Not a block of a coin without AuxPoW.
+        assert!(decode_block_hex(&to_hex(&header(0x00620102)), &coin).is_err());
+    }
+
+    #[test]
+    fn test_decode_hogex_block() {
+        let coin = CoinType::from(Litecoin);
+        let mut block = header(0x20000000);
+        block.push(0x02);
+        block.extend_from_slice(&coinbase(10_000));
+        // HogEx: MWEB flag, no MWEB transaction, one input and one output.
+        block.extend_from_slice(&2u32.to_le_bytes());
+        block.extend_from_slice(&[0x00, 0x08, 0x01]);
+        block.extend_from_slice(&[0x44; 32]);
+        block.extend_from_slice(&0u32.to_le_bytes());
+        block.extend_from_slice(&[0x00]);
+        block.extend_from_slice(&u32::MAX.to_le_bytes());
+        block.extend_from_slice(&[0x01]);
+        block.extend_from_slice(&5000u64.to_le_bytes());
+        block.extend_from_slice(&[0x01, 0x51]);
+        block.push(0x00);
+        block.extend_from_slice(&0u32.to_le_bytes());
+        // MWEB data of the block, not read.
+        block.extend_from_slice(&[0x01, 0x55, 0x66]);
+
+        let block = decode_block_hex(&to_hex(&block), &coin).unwrap();
+        assert!(block.aux_pow_extension.is_none());
+        assert_eq!(block.txs.len(), 2);
+        assert_eq!(block.txs[1].value.outputs[0].out.value, 5000);
+    }
+}
@@ -0,0 +1,478 @@
+//! SRC-20 ledger: tokens deployed, minted and transferred by stamps, and the
+//! balances they leave, kept in their own keyspace.
+//!
+//! An operation is the json payload of a stamp, `{"p":"src-20","op":...}`,
+//! with a tick of 1 to 5 characters and numbers written as strings or
+//! numbers. The first deploy of a tick wins. Deploys and mints go to the
+//! address of the first output, transfers move `amt` to it from the address
+//! of the output the first input spends.
+
+use bitcoin::Txid;
+use bitcoincore_rpc::{Client, RpcApi};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::bitcoin::proto::script;
+use crate::block::{ProtoBlock, Tx};
+use crate::brc20::parse_amount;
+use crate::chain::Chain;
+use crate::outpoint::OutPoint;
+use crate::stamps;
+use crate::store::keyspace::{self, KeyspaceError};
+use crate::store::Store;
+
+const PROTOCOL: &str = "src-20";
+const MAX_TICK_LEN: usize = 5;
+const MAX_DECIMALS: u8 = 18;
+
+/// `t<tick>` -> token, as json.
+const TOKEN_PREFIX: u8 = b't';
+/// `b<address>\0<tick>` -> balance, 16 bytes LE.
+const BALANCE_PREFIX: u8 = b'b';
+
+#[derive(Error, Debug)]
+pub enum Src20Error {
+    #[error("Keyspace error: `{0}`")]
+    KeyspaceError(#[from] KeyspaceError),
+    #[error("Bitcoin rpc error: `{0}`")]
+    BitcoinRpcError(#[from] bitcoincore_rpc::Error),
+    #[error("Output `{0}` spent by a transfer not found, rpc is offline")]
+    SourceNotFound(OutPoint),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Src20Operation {
+    Deploy {
+        tick: String,
+        max: String,
+        lim: String,
+        dec: Option<String>,
+    },
+    Mint {
+        tick: String,
+        amt: String,
+    },
+    Transfer {
+        tick: String,
+        amt: String,
+    },
+}
+
+impl Src20Operation {
+    /// Operation in the payload of a stamp.
+    pub fn from_payload(payload: &[u8]) -> Option<Src20Operation> {
+        let value = serde_json::from_slice::<Value>(payload).ok()?;
+        if !value.get("p")?.as_str()?.eq_ignore_ascii_case(PROTOCOL) {
+            return None;
+        }
+
+        let tick = value.get("tick")?.as_str()?.to_string();
+        if !(1..=MAX_TICK_LEN).contains(&tick.chars().count()) {
+            return None;
+        }
+
+        let number = |key: &str| match value.get(key)? {
+            Value::String(number) => Some(number.clone()),
+            Value::Number(number) => Some(number.to_string()),
+            _ => None,
+        };
+        match value.get("op")?.as_str()?.to_lowercase().as_str() {
+            "deploy" => Some(Src20Operation::Deploy {
+                tick,
+                max: number("max")?,
+                lim: number("lim")?,
+                dec: match value.get("dec") {
+                    Some(_) => Some(number("dec")?),
+                    None => None,
+                },
+            }),
+            "mint" => Some(Src20Operation::Mint {
+                tick,
+                amt: number("amt")?,
+            }),
+            "transfer" => Some(Src20Operation::Transfer {
+                tick,
+                amt: number("amt")?,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn tick(&self) -> &str {
+        match self {
+            Src20Operation::Deploy { tick, .. }
+            | Src20Operation::Mint { tick, .. }
+            | Src20Operation::Transfer { tick, .. } => tick,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Src20Token {
+    /// Lowercase tick, ticks are case insensitive.
+    pub tick: String,
+    #[serde(
+        serialize_with = "serialize_txid",
+        deserialize_with = "deserialize_txid"
+    )]
+    pub txid: Txid,
+    pub deployer: String,
+    pub height: u64,
+    pub decimals: u8,
+    /// Amounts in units of `10^-decimals`.
+    pub max: u128,
+    pub limit: u128,
+    pub minted: u128,
+}
+
+fn serialize_txid<S: Serializer>(txid: &Txid, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(txid)
+}
+
+fn deserialize_txid<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Txid, D::Error> {
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(serde::de::Error::custom)
+}
+
+pub struct Src20Ledger<'a> {
+    store: &'a mut dyn Store,
+}
+
+impl<'a> Src20Ledger<'a> {
+    pub fn new(store: &'a mut dyn Store) -> Src20Ledger<'a> {
+        Src20Ledger { store }
+    }
+
+    /// Applies the stamps of a block in transaction order. The sender of a
+    /// transfer is looked up in the block, then from the bitcoin node.
+    pub fn index_block(
+        &mut self,
+        height: u64,
+        block: &ProtoBlock,
+        chain: &Chain,
+        btc_rpc_client: Option<&Client>,
+    ) -> Result<(), Src20Error> {
+        for tx in block.txs.iter() {
+            let Some(operation) = stamps::decode(&tx.value)
+                .and_then(|payload| Src20Operation::from_payload(&payload))
+            else {
+                continue;
+            };
+            let receiver = tx
+                .value
+                .outputs
+                .first()
+                .and_then(|output| output.script.address.clone());
+            let sender = match operation {
+                Src20Operation::Transfer { .. } => sender(tx, block, chain, btc_rpc_client)?,
+                _ => None,
+            };
+
+            self.apply(
+                Txid::from_raw_hash(tx.hash),
+                &operation,
+                sender.as_deref(),
+                receiver.as_deref(),
+                height,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Applies the operation of a stamp, whose first input spends an output of
+    /// `sender` and whose first output pays `receiver`. Invalid operations are
+    /// ignored.
+    pub fn apply(
+        &mut self,
+        txid: Txid,
+        operation: &Src20Operation,
+        sender: Option<&str>,
+        receiver: Option<&str>,
+        height: u64,
+    ) -> Result<(), Src20Error> {
+        let Some(receiver) = receiver else {
+            return Ok(());
+        };
+        let tick = operation.tick().to_lowercase();
+
+        match operation {
+            Src20Operation::Deploy { max, lim, dec, .. } => {
+                if self.token(&tick)?.is_some() {
+                    return Ok(());
+                }
+
+                let decimals = match dec {
+                    Some(dec) if dec.bytes().all(|b| b.is_ascii_digit()) => {
+                        match dec.parse::<u8>() {
+                            Ok(decimals) if decimals <= MAX_DECIMALS => decimals,
+                            _ => return Ok(()),
+                        }
+                    }
+                    Some(_) => return Ok(()),
+                    None => MAX_DECIMALS,
+                };
+                let (Some(max), Some(limit)) = (
+                    parse_amount(max, decimals).filter(|max| *max > 0),
+                    parse_amount(lim, decimals).filter(|lim| *lim > 0),
+                ) else {
+                    return Ok(());
+                };
+
+                self.put_token(&Src20Token {
+                    tick,
+                    txid,
+                    deployer: receiver.to_string(),
+                    height,
+                    decimals,
+                    max,
+                    limit,
+                    minted: 0,
+                })
+            }
+            Src20Operation::Mint { amt, .. } => {
+                let Some(mut token) = self.token(&tick)? else {
+                    return Ok(());
+                };
+                let amount = match parse_amount(amt, token.decimals) {
+                    Some(amount) if amount > 0 && amount <= token.limit => amount,
+                    _ => return Ok(()),
+                };
+                // The last mint gets what is left.
+                let amount = amount.min(token.max - token.minted);
+                if amount == 0 {
+                    return Ok(());
+                }
+
+                token.minted += amount;
+                self.put_token(&token)?;
+                let balance = self.balance(&tick, receiver)?;
+                self.put_balance(&tick, receiver, balance + amount)
+            }
+            Src20Operation::Transfer { amt, .. } => {
+                let (Some(token), Some(sender)) = (self.token(&tick)?, sender) else {
+                    return Ok(());
+                };
+                let balance = self.balance(&tick, sender)?;
+                let amount = match parse_amount(amt, token.decimals) {
+                    Some(amount) if amount > 0 && amount <= balance => amount,
+                    _ => return Ok(()),
+                };
+
+                self.put_balance(&tick, sender, balance - amount)?;
+                let balance = self.balance(&tick, receiver)?;
+                self.put_balance(&tick, receiver, balance + amount)
+            }
+        }
+    }
+
+    /// Deployed token of a tick, in any case.
+    pub fn token(&mut self, tick: &str) -> Result<Option<Src20Token>, Src20Error> {
+        Ok(keyspace::get_json(
+            self.store,
+            &token_key(&tick.to_lowercase()),
+        )?)
+    }
+
+    /// Deployed tokens, by tick.
+    pub fn tokens(&mut self) -> Result<Vec<Src20Token>, Src20Error> {
+        Ok(keyspace::scan_json(self.store, &[TOKEN_PREFIX])?)
+    }
+
+    pub fn balance(&mut self, tick: &str, address: &str) -> Result<u128, Src20Error> {
+        let key = balance_key(address, &tick.to_lowercase());
+        let balance = keyspace::get(self.store, &key, |value| {
+            value.try_into().ok().map(u128::from_le_bytes)
+        })?;
+        Ok(balance.unwrap_or_default())
+    }
+
+    fn put_token(&mut self, token: &Src20Token) -> Result<(), Src20Error> {
+        Ok(keyspace::put_json(
+            self.store,
+            &token_key(&token.tick),
+            token,
+        )?)
+    }
+
+    fn put_balance(&mut self, tick: &str, address: &str, balance: u128) -> Result<(), Src20Error> {
+        Ok(keyspace::put(
+            self.store,
+            &balance_key(address, tick),
+            &balance.to_le_bytes(),
+        )?)
+    }
+}
+
+/// Address of the output spent by the first input of `tx`.
+fn sender(
+    tx: &Tx,
+    block: &ProtoBlock,
+    chain: &Chain,
+    btc_rpc_client: Option<&Client>,
+) -> Result<Option<String>, Src20Error> {
+    let Some(input) = tx.value.inputs.first() else {
+        return Ok(None);
+    };
+    if let Some(previous) = block
+        .txs
+        .iter()
+        .find(|previous| previous.hash == input.outpoint.txid)
+    {
+        return Ok(previous
+            .value
+            .outputs
+            .get(input.outpoint.index as usize)
+            .and_then(|output| output.script.address.clone()));
+    }
+
+    let outpoint = OutPoint::new(
+        Txid::from_raw_hash(input.outpoint.txid),
+        input.outpoint.index,
+    );
+    let btc_rpc_client = btc_rpc_client.ok_or(Src20Error::SourceNotFound(outpoint))?;
+    let previous = btc_rpc_client.get_raw_transaction(&outpoint.txid, None)?;
+    Ok(previous
+        .output
+        .get(outpoint.vout as usize)
+        .and_then(|output| {
+            script::eval_from_bytes(
+                output.script_pubkey.as_bytes(),
+                chain.coin().address_encoding(),
+            )
+            .address
+        }))
+}
+
+fn token_key(tick: &str) -> Vec<u8> {
+    let mut key = vec![TOKEN_PREFIX];
+    key.extend_from_slice(tick.as_bytes());
+    key
+}
+
+fn balance_key(address: &str, tick: &str) -> Vec<u8> {
+    let mut key = vec![BALANCE_PREFIX];
+    key.extend_from_slice(address.as_bytes());
+    key.push(0);
+    key.extend_from_slice(tick.as_bytes());
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+
+    use super::*;
+    use crate::store::MemoryStore;
+
+    fn operation(json: &str) -> Src20Operation {
+        Src20Operation::from_payload(json.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_operation() {
+        assert_eq!(
+            Src20Operation::from_payload(
+                br#"{"p":"SRC-20","op":"DEPLOY","tick":"Kevin","max":21000,"lim":"1000","dec":0}"#
+            ),
+            Some(Src20Operation::Deploy {
+                tick: "Kevin".to_string(),
+                max: "21000".to_string(),
+                lim: "1000".to_string(),
+                dec: Some("0".to_string()),
+            })
+        );
+        assert_eq!(
+            Src20Operation::from_payload(
+                r#"{"p":"src-20","op":"mint","tick":"🐸","amt":1.5}"#.as_bytes()
+            ),
+            Some(Src20Operation::Mint {
+                tick: "🐸".to_string(),
+                amt: "1.5".to_string(),
+            })
+        );
+        assert_eq!(
+            Src20Operation::from_payload(br#"{"p":"src-20","op":"mint","tick":"kevins","amt":1}"#),
+            None
+        );
+        assert_eq!(
+            Src20Operation::from_payload(br#"{"p":"brc-20","op":"mint","tick":"kevin","amt":1}"#),
+            None
+        );
+        assert_eq!(
+            Src20Operation::from_payload(br#"{"p":"src-20","op":"deploy","tick":"kevin","max":1}"#),
+            None
+        );
+        assert_eq!(
+            Src20Operation::from_payload(
+                br#"{"p":"src-20","op":"deploy","tick":"kevin","max":1,"lim":1,"dec":null}"#
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_ledger() {
+        let mut store = MemoryStore::new();
+        let mut ledger = Src20Ledger::new(&mut store);
+        let txid = Txid::all_zeros();
+
+        ledger
+            .apply(
+                txid,
+                &operation(r#"{"p":"src-20","op":"deploy","tick":"KEVIN","max":"100","lim":"60","dec":"0"}"#),
+                None,
+                Some("alice"),
+                1,
+            )
+            .unwrap();
+        // First deploy wins.
+        ledger
+            .apply(
+                txid,
+                &operation(r#"{"p":"src-20","op":"deploy","tick":"kevin","max":"5","lim":"5"}"#),
+                None,
+                Some("bob"),
+                2,
+            )
+            .unwrap();
+
+        let mint = operation(r#"{"p":"src-20","op":"mint","tick":"kevin","amt":"60"}"#);
+        ledger.apply(txid, &mint, None, Some("alice"), 2).unwrap();
+        // Above the limit.
+        ledger
+            .apply(
+                txid,
+                &operation(r#"{"p":"src-20","op":"mint","tick":"kevin","amt":"61"}"#),
+                None,
+                Some("alice"),
+                2,
+            )
+            .unwrap();
+        // The last mint gets what is left.
+        ledger.apply(txid, &mint, None, Some("bob"), 2).unwrap();
+        ledger.apply(txid, &mint, None, Some("bob"), 2).unwrap();
+
+        let transfer = operation(r#"{"p":"src-20","op":"transfer","tick":"kevin","amt":"50"}"#);
+        ledger
+            .apply(txid, &transfer, Some("alice"), Some("bob"), 3)
+            .unwrap();
+        // Above the sender's balance.
+        ledger
+            .apply(txid, &transfer, Some("alice"), Some("bob"), 3)
+            .unwrap();
+        ledger
+            .apply(txid, &transfer, None, Some("carol"), 3)
+            .unwrap();
+
+        let token = ledger.token("Kevin").unwrap().unwrap();
+        assert_eq!(token.deployer, "alice");
+        assert_eq!((token.max, token.limit, token.minted), (100, 60, 100));
+        assert_eq!(ledger.tokens().unwrap(), vec![token]);
+        assert_eq!(ledger.balance("kevin", "alice").unwrap(), 10);
+        assert_eq!(ledger.balance("kevin", "bob").unwrap(), 90);
+        assert_eq!(ledger.balance("kevin", "carol").unwrap(), 0);
+    }
+}
@@ -0,0 +1,174 @@
+//! Bitcoin Stamps: data kept in the keys of bare 1-of-3 multisig outputs.
+//!
+//! The first two keys of each such output carry 31 bytes each, between their
+//! header byte and a last byte picked to make them valid points, the third is
+//! a real or burn key. The data of all outputs, in order, is ARC4 encrypted
+//! with the txid of the first input, and holds its length, 2 bytes big endian,
+//! then `stamp:` and the payload.
+//!
+//! Stamps issued through Counterparty, with a `CNTRPRTY` prefix, are not
+//! decoded.
+
+use bitcoin::hashes::Hash;
+
+use crate::bitcoin::proto::script::{self, ScriptPattern};
+use crate::bitcoin::proto::tx::EvaluatedTx;
+
+const PREFIX: &[u8] = b"stamp:";
+/// Keys of each output carrying data, the last one does not.
+const DATA_KEYS: usize = 2;
+
+/// Payload of a stamp, following its `stamp:` prefix in any case.
+pub fn decode(tx: &EvaluatedTx) -> Option<Vec<u8>> {
+    if tx.is_coinbase() {
+        return None;
+    }
+
+    let mut data = vec![];
+    for output in tx
+        .outputs
+        .iter()
+        .filter(|output| output.script.pattern == ScriptPattern::Pay2MultiSig(1, 3))
+    {
+        let (_, keys) = script::multisig(&output.out.script_pubkey)?;
+        for key in &keys[..DATA_KEYS] {
+            data.extend_from_slice(&key[1..key.len() - 1]);
+        }
+    }
+    if data.is_empty() {
+        return None;
+    }
+
+    // The txid as displayed, not in its internal byte order.
+    let mut key = tx.inputs.first()?.outpoint.txid.to_byte_array();
+    key.reverse();
+    arc4(&key, &mut data);
+
+    let length = u16::from_be_bytes(data.get(..2)?.try_into().ok()?) as usize;
+    let stamp = data.get(2..2 + length)?;
+    if !stamp.get(..PREFIX.len())?.eq_ignore_ascii_case(PREFIX) {
+        return None;
+    }
+
+    Some(stamp[PREFIX.len()..].to_vec())
+}
+
+/// Encrypts or decrypts `data` in place with the ARC4 stream of `key`.
+pub(crate) fn arc4(key: &[u8], data: &mut [u8]) {
+    let mut state = [0u8; 256];
+    for (i, byte) in state.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+
+    let mut j = 0u8;
+    for i in 0..256 {
+        j = j.wrapping_add(state[i]).wrapping_add(key[i % key.len()]);
+        state.swap(i, j as usize);
+    }
+
+    let (mut i, mut j) = (0u8, 0u8);
+    for byte in data.iter_mut() {
+        i = i.wrapping_add(1);
+        j = j.wrapping_add(state[i as usize]);
+        state.swap(i as usize, j as usize);
+        *byte ^= state[state[i as usize].wrapping_add(state[j as usize]) as usize];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::blockdata::script::{Builder, PushBytesBuf};
+    use bitcoin::hashes::hex::FromHex;
+    use bitcoin::opcodes::all;
+    use bitcoin::{
+        absolute::LockTime, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid,
+        Witness,
+    };
+
+    use super::*;
+    use crate::bitcoin::proto::script::AddressEncoding;
+
+    const BURN_KEY: &str = "022222222222222222222222222222222222222222222222222222222222222222";
+
+    /// A transaction spending `txid` with `payload` stamped in its outputs.
+    fn stamp_tx(txid: &str, payload: &[u8]) -> Transaction {
+        let mut data = (payload.len() as u16).to_be_bytes().to_vec();
+        data.extend_from_slice(payload);
+        data.resize(data.len().div_ceil(62) * 62, 0);
+        let txid = txid.parse::<Txid>().unwrap();
+        let mut key = txid.to_byte_array();
+        key.reverse();
+        arc4(&key, &mut data);
+
+        let mut output = vec![TxOut {
+            value: 546,
+            script_pubkey: ScriptBuf::from_bytes(
+                Vec::from_hex("0014751e76e8199196d454941c45d1b3a323f1433bd6").unwrap(),
+            ),
+        }];
+        for chunk in data.chunks(62) {
+            let mut builder = Builder::new().push_opcode(all::OP_PUSHNUM_1);
+            for part in chunk.chunks(31) {
+                let mut key = vec![0x02];
+                key.extend_from_slice(part);
+                key.push(0x00);
+                builder = builder.push_slice(PushBytesBuf::try_from(key).unwrap());
+            }
+            output.push(TxOut {
+                value: 810,
+                script_pubkey: builder
+                    .push_slice(PushBytesBuf::try_from(Vec::from_hex(BURN_KEY).unwrap()).unwrap())
+                    .push_opcode(all::OP_PUSHNUM_3)
+                    .push_opcode(all::OP_CHECKMULTISIG)
+                    .into_script(),
+            });
+        }
+
+        Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint { txid, vout: 0 },
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output,
+        }
+    }
+
+    fn evaluate(tx: Transaction) -> EvaluatedTx {
+        EvaluatedTx::from_transaction(tx, AddressEncoding::Bitcoin(Network::Bitcoin))
+    }
+
+    #[test]
+    fn test_arc4() {
+        let mut data = b"Plaintext".to_vec();
+        arc4(b"Key", &mut data);
+        assert_eq!(data, Vec::from_hex("bbf316e8d940af0ad3").unwrap());
+        arc4(b"Key", &mut data);
+        assert_eq!(data, b"Plaintext");
+    }
+
+    #[test]
+    fn test_decode() {
+        let txid = "6fb976ab49dcec017f1e201e84395983204ae1a7c2abf7ced0a85d692e442799";
+        let json = br#"{"p":"src-20","op":"deploy","tick":"stamp","max":"1000","lim":"10"}"#;
+        let mut payload = b"STAMP:".to_vec();
+        payload.extend_from_slice(json);
+
+        let tx = evaluate(stamp_tx(txid, &payload));
+        assert_eq!(
+            tx.outputs[1].script.pattern,
+            ScriptPattern::Pay2MultiSig(1, 3)
+        );
+        assert_eq!(decode(&tx), Some(json.to_vec()));
+
+        // Keyed by another txid.
+        let mut tx = stamp_tx(txid, &payload);
+        tx.input[0].previous_output.txid = Txid::all_zeros();
+        assert_eq!(decode(&evaluate(tx)), None);
+
+        assert_eq!(decode(&evaluate(stamp_tx(txid, json))), None);
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+
+use crate::store::{Store, StoreError, StoreSnapshot, WriteBatch};
+
+type Pending = BTreeMap<Vec<u8>, Option<Vec<u8>>>;
+
+/// Keeps writes in memory until `flush`, reads see the pending writes.
+///
+/// `flush` is the commit point: it writes everything pending to the inner
+/// store in one batch. `close` discards writes which were not flushed, so
+/// an interrupted block never reaches disk.
+pub struct BufferedStore {
+    inner: Box<dyn Store>,
+    pending: Pending,
+    pending_size: usize,
+}
+
+impl BufferedStore {
+    pub fn new(inner: Box<dyn Store>) -> BufferedStore {
+        BufferedStore {
+            inner,
+            pending: BTreeMap::new(),
+            pending_size: 0,
+        }
+    }
+
+    /// Approximate size of pending keys and values in bytes.
+    pub fn pending_size(&self) -> usize {
+        self.pending_size
+    }
+
+    /// Writes not flushed yet, in key order, `None` values are deletions.
+    pub fn pending(&self) -> impl Iterator<Item = (&[u8], Option<&[u8]>)> {
+        self.pending
+            .iter()
+            .map(|(k, v)| (k.as_slice(), v.as_deref()))
+    }
+
+    /// Store behind the buffer, writes to it bypass pending ones.
+    pub fn inner_mut(&mut self) -> &mut dyn Store {
+        self.inner.as_mut()
+    }
+
+    fn insert(&mut self, key: &[u8], value: Option<&[u8]>) {
+        let size = key.len() + value.map(|v| v.len()).unwrap_or(0);
+        let old = self.pending.insert(key.to_vec(), value.map(|v| v.to_vec()));
+        if let Some(old) = old {
+            self.pending_size -= key.len() + old.map(|v| v.len()).unwrap_or(0);
+        }
+        self.pending_size += size;
+    }
+}
+
+/// Visits the inner entries overlaid with `pending`, in key order.
+fn merged_scan(
+    pending: &Pending,
+    from: &[u8],
+    f: &mut dyn FnMut(&[u8], &[u8]) -> bool,
+    inner_scan: impl FnOnce(&mut dyn FnMut(&[u8], &[u8]) -> bool) -> Result<(), StoreError>,
+) -> Result<(), StoreError> {
+    let mut pending_iter = pending.range(from.to_vec()..).peekable();
+    let mut stopped = false;
+
+    inner_scan(&mut |key, value| {
+        while let Some((pending_key, pending_value)) =
+            pending_iter.next_if(|(k, _)| k.as_slice() <= key)
+        {
+            if pending_key.as_slice() == key {
+                return match pending_value {
+                    Some(v) => {
+                        stopped = !f(key, v);
+                        !stopped
+                    }
+                    None => true,
+                };
+            }
+            if let Some(v) = pending_value {
+                if !f(pending_key, v) {
+                    stopped = true;
+                    return false;
+                }
+            }
+        }
+
+        stopped = !f(key, value);
+        !stopped
+    })?;
+
+    if !stopped {
+        for (key, value) in pending_iter {
+            if let Some(v) = value {
+                if !f(key, v) {
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl Store for BufferedStore {
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        match self.pending.get(key) {
+            Some(value) => Ok(value.clone()),
+            None => self.inner.get(key),
+        }
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), StoreError> {
+        self.insert(key, Some(value));
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), StoreError> {
+        self.insert(key, None);
+        Ok(())
+    }
+
+    fn write(&mut self, batch: WriteBatch) -> Result<(), StoreError> {
+        for (k, v) in batch.iter() {
+            self.insert(k, v);
+        }
+        Ok(())
+    }
+
+    fn scan(
+        &mut self,
+        from: &[u8],
+        f: &mut dyn FnMut(&[u8], &[u8]) -> bool,
+    ) -> Result<(), StoreError> {
+        let inner = &mut self.inner;
+        merged_scan(&self.pending, from, f, |g| inner.scan(from, g))
+    }
+
+    fn snapshot(&mut self) -> Result<Box<dyn StoreSnapshot + '_>, StoreError> {
+        Ok(Box::new(BufferedSnapshot {
+            pending: self.pending.clone(),
+            inner: self.inner.snapshot()?,
+        }))
+    }
+
+    fn flush(&mut self) -> Result<(), StoreError> {
+        if !self.pending.is_empty() {
+            let mut wb = WriteBatch::new();
+            for (k, v) in std::mem::take(&mut self.pending) {
+                match v {
+                    Some(v) => wb.put(&k, &v),
+                    None => wb.delete(&k),
+                }
+            }
+            self.pending_size = 0;
+            self.inner.write(wb)?;
+        }
+
+        self.inner.flush()
+    }
+
+    fn close(&mut self) -> Result<(), StoreError> {
+        self.pending.clear();
+        self.pending_size = 0;
+        self.inner.close()
+    }
+}
+
+struct BufferedSnapshot<'a> {
+    pending: Pending,
+    inner: Box<dyn StoreSnapshot + 'a>,
+}
+
+impl StoreSnapshot for BufferedSnapshot<'_> {
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        match self.pending.get(key) {
+            Some(value) => Ok(value.clone()),
+            None => self.inner.get(key),
+        }
+    }
+
+    fn scan(
+        &mut self,
+        from: &[u8],
+        f: &mut dyn FnMut(&[u8], &[u8]) -> bool,
+    ) -> Result<(), StoreError> {
+        let inner = &mut self.inner;
+        merged_scan(&self.pending, from, f, |g| inner.scan(from, g))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+
+    fn keys(store: &mut dyn Store, from: &[u8]) -> Vec<Vec<u8>> {
+        let mut keys = vec![];
+        store
+            .scan(from, &mut |k, _| {
+                keys.push(k.to_vec());
+                true
+            })
+            .unwrap();
+        keys
+    }
+
+    #[test]
+    fn test_buffered_store_overlay() {
+        let mut inner = MemoryStore::new();
+        inner.put(b"a", b"1").unwrap();
+        inner.put(b"c", b"3").unwrap();
+        inner.put(b"e", b"5").unwrap();
+
+        let mut store = BufferedStore::new(Box::new(inner));
+        store.put(b"b", b"2").unwrap();
+        store.delete(b"c").unwrap();
+        store.put(b"f", b"6").unwrap();
+
+        assert_eq!(store.get(b"c").unwrap(), None);
+        assert_eq!(store.get(b"e").unwrap(), Some(b"5".to_vec()));
+        assert_eq!(
+            keys(&mut store, b""),
+            vec![b"a".to_vec(), b"b".to_vec(), b"e".to_vec(), b"f".to_vec()]
+        );
+        assert_eq!(keys(&mut store, b"c"), vec![b"e".to_vec(), b"f".to_vec()]);
+        assert_eq!(keys(store.inner_mut(), b"").len(), 3);
+
+        store.flush().unwrap();
+        assert_eq!(store.pending_size(), 0);
+        assert_eq!(
+            keys(store.inner_mut(), b""),
+            vec![b"a".to_vec(), b"b".to_vec(), b"e".to_vec(), b"f".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_buffered_store_close_discards_pending() {
+        let mut store = BufferedStore::new(Box::new(MemoryStore::new()));
+        store.put(b"a", b"1").unwrap();
+        store.close().unwrap();
+        assert_eq!(store.get(b"a").unwrap(), None);
+    }
+}
@@ -0,0 +1,203 @@
+//! Write-ahead journal making a flush of several keyspaces atomic.
+//!
+//! Pending writes of every keyspace are written to the journal in one batch,
+//! then to the keyspaces, then the journal is cleared. A flush interrupted
+//! once the journal is written is replayed when the journal is opened again,
+//! one interrupted before leaves every keyspace at the previous flush.
+//!
+//! Entries are keyed by the keyspace name, prefixed with its length, followed
+//! by the key. Values are `0` for deletions and `1` followed by the value.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::store::{self, BufferedStore, StorageEngine, Store, StoreError, WriteBatch};
+
+const JOURNAL: &str = "journal";
+
+/// Keyspace name, key and value of a journal entry, `None` for deletions.
+type Entry<'a> = (String, &'a [u8], Option<&'a [u8]>);
+
+pub struct Journal {
+    store: Box<dyn Store>,
+}
+
+impl Journal {
+    /// Opens the journal under `dir` and replays the flush it still holds, if
+    /// any. Keyspaces are opened to be replayed into, so this runs before they
+    /// are opened elsewhere. Returns the number of entries replayed.
+    pub fn open(engine: &StorageEngine, dir: &Path) -> Result<(Journal, u64), StoreError> {
+        let mut store = engine.open(dir, JOURNAL)?;
+
+        let mut batches: BTreeMap<String, WriteBatch> = BTreeMap::new();
+        let mut count = 0;
+        let mut malformed = None;
+        store.scan(&[], &mut |key, value| match decode_entry(key, value) {
+            Some((name, key, value)) => {
+                let wb = batches.entry(name).or_default();
+                match value {
+                    Some(value) => wb.put(key, value),
+                    None => wb.delete(key),
+                }
+                count += 1;
+                true
+            }
+            None => {
+                malformed = Some(key.to_vec());
+                false
+            }
+        })?;
+        if let Some(key) = malformed {
+            return Err(StoreError::MalformedJournalEntry(key));
+        }
+
+        for (name, wb) in batches {
+            let mut keyspace = engine.open(dir, &name)?;
+            keyspace.write(wb)?;
+            keyspace.flush()?;
+            keyspace.close()?;
+        }
+
+        let mut journal = Journal { store };
+        if count > 0 {
+            journal.clear()?;
+        }
+        Ok((journal, count))
+    }
+
+    /// Flushes `keyspaces` together, either all of their pending writes reach
+    /// disk or, once replayed, none of them.
+    pub fn flush<'n, 'a>(
+        &mut self,
+        keyspaces: impl IntoIterator<Item = (&'n str, &'a mut BufferedStore)>,
+    ) -> Result<(), StoreError> {
+        let mut keyspaces = keyspaces.into_iter().collect::<Vec<_>>();
+        let recorded = self.record(&keyspaces)?;
+
+        for (_, keyspace) in keyspaces.iter_mut() {
+            keyspace.flush()?;
+        }
+
+        if recorded {
+            self.clear()?;
+        }
+        Ok(())
+    }
+
+    /// Writes pending writes of `keyspaces` in one batch, returns whether
+    /// there were any.
+    pub(crate) fn record(
+        &mut self,
+        keyspaces: &[(&str, &mut BufferedStore)],
+    ) -> Result<bool, StoreError> {
+        let mut wb = WriteBatch::new();
+        for (name, keyspace) in keyspaces {
+            for (key, value) in keyspace.pending() {
+                wb.put(&encode_key(name, key), &encode_value(value));
+            }
+        }
+
+        if wb.is_empty() {
+            return Ok(false);
+        }
+        self.store.write(wb)?;
+        self.store.flush()?;
+        Ok(true)
+    }
+
+    fn clear(&mut self) -> Result<(), StoreError> {
+        store::clear(self.store.as_mut())
+    }
+
+    pub fn close(&mut self) -> Result<(), StoreError> {
+        self.store.close()
+    }
+}
+
+fn encode_key(name: &str, key: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(1 + name.len() + key.len());
+    bytes.push(name.len() as u8);
+    bytes.extend_from_slice(name.as_bytes());
+    bytes.extend_from_slice(key);
+    bytes
+}
+
+fn encode_value(value: Option<&[u8]>) -> Vec<u8> {
+    match value {
+        Some(value) => [&[1], value].concat(),
+        None => vec![0],
+    }
+}
+
+fn decode_entry<'a>(key: &'a [u8], value: &'a [u8]) -> Option<Entry<'a>> {
+    let (&name_len, key) = key.split_first()?;
+    let name = key.get(..name_len as usize)?;
+    let name = String::from_utf8(name.to_vec()).ok()?;
+    let key = &key[name_len as usize..];
+
+    let value = match value.split_first()? {
+        (0, []) => None,
+        (1, value) => Some(value),
+        _ => return None,
+    };
+    Some((name, key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_replays_interrupted_flush() {
+        let dir = std::env::temp_dir().join(format!("ordi-journal-{}", std::process::id()));
+        let engine = StorageEngine::LevelDB;
+
+        let (mut journal, replayed) = Journal::open(&engine, &dir).unwrap();
+        assert_eq!(replayed, 0);
+        let mut a = BufferedStore::new(engine.open(&dir, "a").unwrap());
+        let mut b = BufferedStore::new(engine.open(&dir, "b").unwrap());
+        a.put(b"1", b"one").unwrap();
+        journal.flush([("a", &mut a), ("b", &mut b)]).unwrap();
+        assert_eq!(a.inner_mut().get(b"1").unwrap(), Some(b"one".to_vec()));
+
+        // Interrupted once the journal is written, before the keyspaces are.
+        a.delete(b"1").unwrap();
+        b.put(b"2", b"two").unwrap();
+        journal.record(&[("a", &mut a), ("b", &mut b)]).unwrap();
+        journal.close().unwrap();
+        a.close().unwrap();
+        b.close().unwrap();
+
+        let (mut journal, replayed) = Journal::open(&engine, &dir).unwrap();
+        assert_eq!(replayed, 2);
+        let mut a = engine.open(&dir, "a").unwrap();
+        let mut b = engine.open(&dir, "b").unwrap();
+        assert_eq!(a.get(b"1").unwrap(), None);
+        assert_eq!(b.get(b"2").unwrap(), Some(b"two".to_vec()));
+        a.close().unwrap();
+        b.close().unwrap();
+
+        // Replayed once.
+        journal.close().unwrap();
+        let (mut journal, replayed) = Journal::open(&engine, &dir).unwrap();
+        assert_eq!(replayed, 0);
+        journal.close().unwrap();
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_journal_entry_encoding() {
+        let key = encode_key("output_value", b"key");
+        assert_eq!(
+            decode_entry(&key, &encode_value(Some(b"value"))),
+            Some(("output_value".to_string(), &b"key"[..], Some(&b"value"[..])))
+        );
+        assert_eq!(
+            decode_entry(&key, &encode_value(None)),
+            Some(("output_value".to_string(), &b"key"[..], None))
+        );
+        assert_eq!(decode_entry(&key, &[0, 1]), None);
+        assert_eq!(decode_entry(&[20, b'a'], &[0]), None);
+    }
+}
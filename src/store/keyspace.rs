@@ -0,0 +1,100 @@
+//! Reads and writes of the protocol keyspaces, whose records are json or
+//! fixed size values under one byte prefixes.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::store::{Store, StoreError};
+
+#[derive(Error, Debug)]
+pub enum KeyspaceError {
+    #[error("Store error: `{0}`")]
+    StoreError(#[from] StoreError),
+    #[error("Malformed value of key: `{0:?}`")]
+    MalformedValue(Vec<u8>),
+}
+
+/// Value of `key` decoded by `decode`, the value is malformed if it can not be.
+pub fn get<T>(
+    store: &mut dyn Store,
+    key: &[u8],
+    decode: impl FnOnce(&[u8]) -> Option<T>,
+) -> Result<Option<T>, KeyspaceError> {
+    match store.get(key)? {
+        Some(value) => match decode(&value) {
+            Some(value) => Ok(Some(value)),
+            None => Err(KeyspaceError::MalformedValue(key.to_vec())),
+        },
+        None => Ok(None),
+    }
+}
+
+pub fn put(store: &mut dyn Store, key: &[u8], value: &[u8]) -> Result<(), KeyspaceError> {
+    Ok(store.put(key, value)?)
+}
+
+/// `key` -> record, as json.
+pub fn get_json<T: DeserializeOwned>(
+    store: &mut dyn Store,
+    key: &[u8],
+) -> Result<Option<T>, KeyspaceError> {
+    get(store, key, |value| serde_json::from_slice(value).ok())
+}
+
+pub fn put_json<T: Serialize>(
+    store: &mut dyn Store,
+    key: &[u8],
+    record: &T,
+) -> Result<(), KeyspaceError> {
+    put(store, key, &serde_json::to_vec(record).unwrap())
+}
+
+/// Records of the keys starting with `prefix`, in key order.
+pub fn scan_json<T: DeserializeOwned>(
+    store: &mut dyn Store,
+    prefix: &[u8],
+) -> Result<Vec<T>, KeyspaceError> {
+    let mut records = vec![];
+    let mut malformed = None;
+    store.scan(prefix, &mut |key, value| {
+        if !key.starts_with(prefix) {
+            return false;
+        }
+        match serde_json::from_slice(value) {
+            Ok(record) => records.push(record),
+            Err(_) => malformed = Some(key.to_vec()),
+        }
+        malformed.is_none()
+    })?;
+
+    match malformed {
+        Some(key) => Err(KeyspaceError::MalformedValue(key)),
+        None => Ok(records),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+
+    #[test]
+    fn test_json_records() {
+        let mut store = MemoryStore::new();
+        put_json(&mut store, b"a1", &1u64).unwrap();
+        put_json(&mut store, b"a2", &2u64).unwrap();
+        put_json(&mut store, b"b1", &3u64).unwrap();
+
+        assert_eq!(get_json::<u64>(&mut store, b"a2").unwrap(), Some(2));
+        assert_eq!(get_json::<u64>(&mut store, b"a3").unwrap(), None);
+        assert_eq!(scan_json::<u64>(&mut store, b"a").unwrap(), vec![1, 2]);
+
+        store.put(b"a3", b"{").unwrap();
+        assert!(matches!(
+            get_json::<u64>(&mut store, b"a3"),
+            Err(KeyspaceError::MalformedValue(key)) if key == b"a3"
+        ));
+        assert!(scan_json::<u64>(&mut store, b"a").is_err());
+    }
+}
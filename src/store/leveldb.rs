@@ -0,0 +1,116 @@
+use std::path::Path;
+
+use rusty_leveldb::{DBIterator, LdbIterator, DB};
+
+use crate::store::{Store, StoreError, StoreSnapshot, WriteBatch};
+
+pub struct LevelDBStore {
+    db: DB,
+}
+
+impl LevelDBStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<LevelDBStore, StoreError> {
+        let options = rusty_leveldb::Options {
+            max_file_size: 2 << 25,
+            ..Default::default()
+        };
+
+        Ok(LevelDBStore {
+            db: DB::open(path, options)?,
+        })
+    }
+
+    pub fn in_memory(name: &str) -> Result<LevelDBStore, StoreError> {
+        Ok(LevelDBStore {
+            db: DB::open(name, rusty_leveldb::in_memory())?,
+        })
+    }
+}
+
+fn scan_iter(iter: &mut DBIterator, from: &[u8], f: &mut dyn FnMut(&[u8], &[u8]) -> bool) {
+    let (mut key, mut value) = (vec![], vec![]);
+    iter.seek(from);
+    while iter.valid() {
+        iter.current(&mut key, &mut value);
+        if !f(&key, &value) || !iter.advance() {
+            break;
+        }
+    }
+}
+
+impl Store for LevelDBStore {
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        Ok(self.db.get(key))
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), StoreError> {
+        Ok(self.db.put(key, value)?)
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), StoreError> {
+        Ok(self.db.delete(key)?)
+    }
+
+    fn write(&mut self, batch: WriteBatch) -> Result<(), StoreError> {
+        let mut wb = rusty_leveldb::WriteBatch::new();
+        for (k, v) in batch.iter() {
+            match v {
+                Some(v) => wb.put(k, v),
+                None => wb.delete(k),
+            }
+        }
+
+        Ok(self.db.write(wb, false)?)
+    }
+
+    fn scan(
+        &mut self,
+        from: &[u8],
+        f: &mut dyn FnMut(&[u8], &[u8]) -> bool,
+    ) -> Result<(), StoreError> {
+        scan_iter(&mut self.db.new_iter()?, from, f);
+        Ok(())
+    }
+
+    fn snapshot(&mut self) -> Result<Box<dyn StoreSnapshot + '_>, StoreError> {
+        let snapshot = self.db.get_snapshot();
+        Ok(Box::new(LevelDBSnapshot {
+            iter: self.db.new_iter_at(snapshot)?,
+        }))
+    }
+
+    fn flush(&mut self) -> Result<(), StoreError> {
+        Ok(self.db.flush()?)
+    }
+
+    fn close(&mut self) -> Result<(), StoreError> {
+        Ok(self.db.close()?)
+    }
+}
+
+/// Reads through an iterator pinned to a snapshot, the snapshot type of
+/// `rusty_leveldb` is not public.
+struct LevelDBSnapshot {
+    iter: DBIterator,
+}
+
+impl StoreSnapshot for LevelDBSnapshot {
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        let (mut found, mut value) = (vec![], vec![]);
+        self.iter.seek(key);
+        if self.iter.valid() && self.iter.current(&mut found, &mut value) && found == key {
+            return Ok(Some(value));
+        }
+
+        Ok(None)
+    }
+
+    fn scan(
+        &mut self,
+        from: &[u8],
+        f: &mut dyn FnMut(&[u8], &[u8]) -> bool,
+    ) -> Result<(), StoreError> {
+        scan_iter(&mut self.iter, from, f);
+        Ok(())
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+
+use crate::store::{Store, StoreError, StoreSnapshot, WriteBatch};
+
+/// Ordered in-memory map, for tests and keyspaces which are rebuilt on start.
+#[derive(Default)]
+pub struct MemoryStore {
+    map: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+fn scan_map(
+    map: &BTreeMap<Vec<u8>, Vec<u8>>,
+    from: &[u8],
+    f: &mut dyn FnMut(&[u8], &[u8]) -> bool,
+) {
+    for (k, v) in map.range(from.to_vec()..) {
+        if !f(k, v) {
+            break;
+        }
+    }
+}
+
+impl Store for MemoryStore {
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        Ok(self.map.get(key).cloned())
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), StoreError> {
+        self.map.insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), StoreError> {
+        self.map.remove(key);
+        Ok(())
+    }
+
+    fn write(&mut self, batch: WriteBatch) -> Result<(), StoreError> {
+        for (k, v) in batch {
+            match v {
+                Some(v) => self.map.insert(k, v),
+                None => self.map.remove(&k),
+            };
+        }
+        Ok(())
+    }
+
+    fn scan(
+        &mut self,
+        from: &[u8],
+        f: &mut dyn FnMut(&[u8], &[u8]) -> bool,
+    ) -> Result<(), StoreError> {
+        scan_map(&self.map, from, f);
+        Ok(())
+    }
+
+    fn snapshot(&mut self) -> Result<Box<dyn StoreSnapshot + '_>, StoreError> {
+        Ok(Box::new(MemorySnapshot {
+            map: self.map.clone(),
+        }))
+    }
+
+    fn flush(&mut self) -> Result<(), StoreError> {
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), StoreError> {
+        Ok(())
+    }
+}
+
+struct MemorySnapshot {
+    map: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl StoreSnapshot for MemorySnapshot {
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        Ok(self.map.get(key).cloned())
+    }
+
+    fn scan(
+        &mut self,
+        from: &[u8],
+        f: &mut dyn FnMut(&[u8], &[u8]) -> bool,
+    ) -> Result<(), StoreError> {
+        scan_map(&self.map, from, f);
+        Ok(())
+    }
+}
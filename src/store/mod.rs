@@ -0,0 +1,261 @@
+//! Key-value storage behind the indexer.
+//!
+//! `Ordi` only talks to the `Store` trait, the engine is selected with
+//! `Options::storage_engine`. LevelDB and the in-memory map are always
+//! available, redb is enabled with the `redb` feature.
+
+use std::path::Path;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+pub mod buffered;
+pub mod journal;
+pub mod keyspace;
+pub mod leveldb;
+pub mod memory;
+#[cfg(feature = "redb")]
+pub mod redb;
+
+pub use self::buffered::BufferedStore;
+pub use self::journal::Journal;
+pub use self::keyspace::KeyspaceError;
+pub use self::leveldb::LevelDBStore;
+pub use self::memory::MemoryStore;
+#[cfg(feature = "redb")]
+pub use self::redb::RedbStore;
+
+#[derive(Error, Debug)]
+pub enum StoreError {
+    #[error("Leveldb error: `{0}`")]
+    LevelDBError(#[from] rusty_leveldb::Status),
+    #[cfg(feature = "redb")]
+    #[error("Redb error: `{0}`")]
+    RedbError(#[from] ::redb::Error),
+    #[error("Unknown storage engine: `{0}`")]
+    UnknownEngine(String),
+    #[error("Malformed journal entry: `{0:?}`")]
+    MalformedJournalEntry(Vec<u8>),
+}
+
+/// Writes applied together by `Store::write`.
+#[derive(Default, Clone, Debug)]
+pub struct WriteBatch {
+    entries: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+    size: usize,
+}
+
+impl WriteBatch {
+    pub fn new() -> WriteBatch {
+        WriteBatch::default()
+    }
+
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.size += key.len() + value.len();
+        self.entries.push((key.to_vec(), Some(value.to_vec())));
+    }
+
+    pub fn delete(&mut self, key: &[u8]) {
+        self.size += key.len();
+        self.entries.push((key.to_vec(), None));
+    }
+
+    pub fn count(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Approximate size of keys and values in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Entries in insertion order, `None` values are deletions.
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], Option<&[u8]>)> {
+        self.entries
+            .iter()
+            .map(|(k, v)| (k.as_slice(), v.as_deref()))
+    }
+}
+
+impl IntoIterator for WriteBatch {
+    type Item = (Vec<u8>, Option<Vec<u8>>);
+    type IntoIter = std::vec::IntoIter<(Vec<u8>, Option<Vec<u8>>)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+/// A keyspace, ordered by key.
+pub trait Store {
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError>;
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), StoreError>;
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), StoreError>;
+
+    fn write(&mut self, batch: WriteBatch) -> Result<(), StoreError>;
+
+    /// Visits entries in key order, starting at the first key `>= from`,
+    /// until `f` returns false.
+    fn scan(
+        &mut self,
+        from: &[u8],
+        f: &mut dyn FnMut(&[u8], &[u8]) -> bool,
+    ) -> Result<(), StoreError>;
+
+    /// Consistent read view, unaffected by later writes.
+    fn snapshot(&mut self) -> Result<Box<dyn StoreSnapshot + '_>, StoreError>;
+
+    fn flush(&mut self) -> Result<(), StoreError>;
+
+    fn close(&mut self) -> Result<(), StoreError>;
+}
+
+pub trait StoreSnapshot {
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError>;
+
+    fn scan(
+        &mut self,
+        from: &[u8],
+        f: &mut dyn FnMut(&[u8], &[u8]) -> bool,
+    ) -> Result<(), StoreError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StorageEngine {
+    #[default]
+    LevelDB,
+    Memory,
+    #[cfg(feature = "redb")]
+    Redb,
+}
+
+impl StorageEngine {
+    /// Opens keyspace `name` under `dir`.
+    pub fn open(&self, dir: &Path, name: &str) -> Result<Box<dyn Store>, StoreError> {
+        Ok(match self {
+            StorageEngine::LevelDB => Box::new(LevelDBStore::open(dir.join(name))?),
+            StorageEngine::Memory => Box::new(MemoryStore::new()),
+            #[cfg(feature = "redb")]
+            StorageEngine::Redb => Box::new(RedbStore::open(dir.join(format!("{}.redb", name)))?),
+        })
+    }
+}
+
+impl FromStr for StorageEngine {
+    type Err = StoreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" | "leveldb" => Ok(StorageEngine::LevelDB),
+            "memory" => Ok(StorageEngine::Memory),
+            #[cfg(feature = "redb")]
+            "redb" => Ok(StorageEngine::Redb),
+            _ => Err(StoreError::UnknownEngine(s.to_string())),
+        }
+    }
+}
+
+/// Deletes every entry, in batches.
+pub fn clear(db: &mut dyn Store) -> Result<(), StoreError> {
+    const CLEAR_BATCH_SIZE: usize = 100_000;
+
+    loop {
+        let mut wb = WriteBatch::new();
+        db.scan(&[], &mut |key, _| {
+            wb.delete(key);
+            wb.count() < CLEAR_BATCH_SIZE
+        })?;
+
+        if wb.is_empty() {
+            break;
+        }
+        db.write(wb)?;
+    }
+
+    db.flush()
+}
+
+/// Smallest key greater than `key`, used to resume scans.
+pub fn successor(key: &[u8]) -> Vec<u8> {
+    let mut next = key.to_vec();
+    next.push(0);
+    next
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exercise(store: &mut dyn Store) {
+        store.put(b"b", b"2").unwrap();
+        store.put(b"a", b"1").unwrap();
+        assert_eq!(store.get(b"a").unwrap(), Some(b"1".to_vec()));
+
+        let mut wb = WriteBatch::new();
+        wb.put(b"c", b"3");
+        wb.delete(b"a");
+        store.write(wb).unwrap();
+        assert_eq!(store.get(b"a").unwrap(), None);
+
+        let mut snapshot_keys = vec![];
+        {
+            let mut snapshot = store.snapshot().unwrap();
+            assert_eq!(snapshot.get(b"b").unwrap(), Some(b"2".to_vec()));
+            assert_eq!(snapshot.get(b"a").unwrap(), None);
+            snapshot
+                .scan(b"", &mut |k, _| {
+                    snapshot_keys.push(k.to_vec());
+                    true
+                })
+                .unwrap();
+        }
+        assert_eq!(snapshot_keys, vec![b"b".to_vec(), b"c".to_vec()]);
+
+        store.delete(b"b").unwrap();
+        let mut keys = vec![];
+        store
+            .scan(b"b", &mut |k, v| {
+                keys.push((k.to_vec(), v.to_vec()));
+                true
+            })
+            .unwrap();
+        assert_eq!(keys, vec![(b"c".to_vec(), b"3".to_vec())]);
+    }
+
+    #[test]
+    fn test_memory_store() {
+        exercise(&mut MemoryStore::new());
+    }
+
+    #[test]
+    fn test_leveldb_store() {
+        exercise(&mut LevelDBStore::in_memory("test").unwrap());
+    }
+
+    #[cfg(feature = "redb")]
+    #[test]
+    fn test_redb_store() {
+        let path = std::env::temp_dir().join(format!("ordi-test-{}.redb", std::process::id()));
+        exercise(&mut RedbStore::open(&path).unwrap());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_storage_engine_from_str() {
+        assert_eq!(
+            "leveldb".parse::<StorageEngine>().unwrap(),
+            StorageEngine::LevelDB
+        );
+        assert_eq!(
+            "memory".parse::<StorageEngine>().unwrap(),
+            StorageEngine::Memory
+        );
+        assert!("sqlite".parse::<StorageEngine>().is_err());
+    }
+}
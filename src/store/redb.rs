@@ -0,0 +1,135 @@
+use std::path::Path;
+
+use redb::{Database, Durability, ReadTransaction, ReadableTable, TableDefinition};
+
+use crate::store::{Store, StoreError, StoreSnapshot, WriteBatch};
+
+const TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("ordi");
+
+/// redb keeps one keyspace per file. Writes are committed with eventual
+/// durability, `flush` commits an immediate one to sync them to disk.
+pub struct RedbStore {
+    db: Database,
+}
+
+impl RedbStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<RedbStore, StoreError> {
+        let db = Database::create(path).map_err(redb::Error::from)?;
+
+        let tx = db.begin_write().map_err(redb::Error::from)?;
+        tx.open_table(TABLE).map_err(redb::Error::from)?;
+        tx.commit().map_err(redb::Error::from)?;
+
+        Ok(RedbStore { db })
+    }
+
+    fn commit<F>(&mut self, durability: Durability, f: F) -> Result<(), StoreError>
+    where
+        F: FnOnce(&mut redb::Table<&[u8], &[u8]>) -> Result<(), redb::StorageError>,
+    {
+        let mut tx = self.db.begin_write().map_err(redb::Error::from)?;
+        tx.set_durability(durability);
+        {
+            let mut table = tx.open_table(TABLE).map_err(redb::Error::from)?;
+            f(&mut table).map_err(redb::Error::from)?;
+        }
+        tx.commit().map_err(redb::Error::from)?;
+
+        Ok(())
+    }
+}
+
+fn scan_tx(
+    tx: &ReadTransaction,
+    from: &[u8],
+    f: &mut dyn FnMut(&[u8], &[u8]) -> bool,
+) -> Result<(), redb::Error> {
+    let table = tx.open_table(TABLE)?;
+    for entry in table.range(from..)? {
+        let (k, v) = entry?;
+        if !f(k.value(), v.value()) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn get_tx(tx: &ReadTransaction, key: &[u8]) -> Result<Option<Vec<u8>>, redb::Error> {
+    let table = tx.open_table(TABLE)?;
+    let value = table.get(key)?.map(|v| v.value().to_vec());
+    Ok(value)
+}
+
+impl Store for RedbStore {
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        let tx = self.db.begin_read().map_err(redb::Error::from)?;
+        Ok(get_tx(&tx, key)?)
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), StoreError> {
+        self.commit(Durability::Eventual, |table| {
+            table.insert(key, value)?;
+            Ok(())
+        })
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<(), StoreError> {
+        self.commit(Durability::Eventual, |table| {
+            table.remove(key)?;
+            Ok(())
+        })
+    }
+
+    fn write(&mut self, batch: WriteBatch) -> Result<(), StoreError> {
+        self.commit(Durability::Eventual, |table| {
+            for (k, v) in batch.iter() {
+                match v {
+                    Some(v) => table.insert(k, v)?,
+                    None => table.remove(k)?,
+                };
+            }
+            Ok(())
+        })
+    }
+
+    fn scan(
+        &mut self,
+        from: &[u8],
+        f: &mut dyn FnMut(&[u8], &[u8]) -> bool,
+    ) -> Result<(), StoreError> {
+        let tx = self.db.begin_read().map_err(redb::Error::from)?;
+        Ok(scan_tx(&tx, from, f)?)
+    }
+
+    fn snapshot(&mut self) -> Result<Box<dyn StoreSnapshot + '_>, StoreError> {
+        let tx = self.db.begin_read().map_err(redb::Error::from)?;
+        Ok(Box::new(RedbSnapshot { tx }))
+    }
+
+    fn flush(&mut self) -> Result<(), StoreError> {
+        self.commit(Durability::Immediate, |_| Ok(()))
+    }
+
+    fn close(&mut self) -> Result<(), StoreError> {
+        self.flush()
+    }
+}
+
+struct RedbSnapshot<'a> {
+    tx: ReadTransaction<'a>,
+}
+
+impl StoreSnapshot for RedbSnapshot<'_> {
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        Ok(get_tx(&self.tx, key)?)
+    }
+
+    fn scan(
+        &mut self,
+        from: &[u8],
+        f: &mut dyn FnMut(&[u8], &[u8]) -> bool,
+    ) -> Result<(), StoreError> {
+        Ok(scan_tx(&self.tx, from, f)?)
+    }
+}
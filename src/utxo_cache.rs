@@ -0,0 +1,117 @@
+use std::num::NonZeroUsize;
+
+use lru::LruCache;
+
+use crate::outpoint::OutPoint;
+
+/// Approximate memory held by one entry: the outpoint and value, plus the
+/// hash table slot and list links of the LRU.
+const ENTRY_SIZE: usize = 96;
+
+/// Values of recently created outputs, in front of `output_value`.
+///
+/// Most inputs spend outputs created shortly before, so entries are taken
+/// out of the cache when they are spent.
+pub struct UtxoCache {
+    cache: Option<LruCache<OutPoint, u64>>,
+    hits: u64,
+    misses: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct UtxoCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub len: usize,
+    pub capacity: usize,
+}
+
+impl UtxoCacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+}
+
+impl UtxoCache {
+    /// Cache bounded to about `memory_budget` bytes, disabled if it holds no entry.
+    pub fn new(memory_budget: usize) -> UtxoCache {
+        UtxoCache {
+            cache: NonZeroUsize::new(memory_budget / ENTRY_SIZE).map(LruCache::new),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn insert(&mut self, outpoint: OutPoint, value: u64) {
+        if let Some(cache) = self.cache.as_mut() {
+            cache.put(outpoint, value);
+        }
+    }
+
+    /// Removes the value of a spent output, counting the lookup.
+    pub fn take(&mut self, outpoint: &OutPoint) -> Option<u64> {
+        let value = self.cache.as_mut().and_then(|cache| cache.pop(outpoint));
+        match value {
+            Some(_) => self.hits += 1,
+            None => self.misses += 1,
+        }
+
+        value
+    }
+
+    pub fn stats(&self) -> UtxoCacheStats {
+        UtxoCacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            len: self.cache.as_ref().map(|cache| cache.len()).unwrap_or(0),
+            capacity: self
+                .cache
+                .as_ref()
+                .map(|cache| cache.cap().get())
+                .unwrap_or(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TXID: &str = "1111111111111111111111111111111111111111111111111111111111111111";
+
+    fn outpoint(vout: u32) -> OutPoint {
+        OutPoint::new(TXID.parse().unwrap(), vout)
+    }
+
+    #[test]
+    fn test_utxo_cache_evicts_least_recently_used() {
+        let mut cache = UtxoCache::new(2 * ENTRY_SIZE);
+        cache.insert(outpoint(0), 100);
+        cache.insert(outpoint(1), 200);
+        cache.insert(outpoint(2), 300);
+
+        assert_eq!(cache.take(&outpoint(0)), None);
+        assert_eq!(cache.take(&outpoint(2)), Some(300));
+        assert_eq!(cache.take(&outpoint(2)), None);
+        assert_eq!(
+            cache.stats(),
+            UtxoCacheStats {
+                hits: 1,
+                misses: 2,
+                len: 1,
+                capacity: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_utxo_cache_disabled() {
+        let mut cache = UtxoCache::new(0);
+        cache.insert(outpoint(0), 100);
+        assert_eq!(cache.take(&outpoint(0)), None);
+        assert_eq!(cache.stats().capacity, 0);
+    }
+}
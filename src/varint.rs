@@ -0,0 +1,92 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum VarintError {
+    #[error("Varint is too long")]
+    Overlong,
+    #[error("Varint overflows u128")]
+    Overflow,
+    #[error("Varint is unterminated")]
+    Unterminated,
+}
+
+/// Appends `n` as LEB128 to `v`.
+pub fn encode_to_vec(mut n: u128, v: &mut Vec<u8>) {
+    while n >> 7 > 0 {
+        v.push(n.to_le_bytes()[0] | 0b1000_0000);
+        n >>= 7;
+    }
+
+    v.push(n.to_le_bytes()[0]);
+}
+
+pub fn encode(n: u128) -> Vec<u8> {
+    let mut v = Vec::new();
+    encode_to_vec(n, &mut v);
+    v
+}
+
+/// Decodes a LEB128 integer from the front of `buffer`,
+/// returns the value and the number of bytes consumed.
+pub fn decode(buffer: &[u8]) -> Result<(u128, usize), VarintError> {
+    let mut n = 0u128;
+
+    for (i, &byte) in buffer.iter().enumerate() {
+        if i > 18 {
+            return Err(VarintError::Overlong);
+        }
+
+        let value = u128::from(byte) & 0b0111_1111;
+
+        if i == 18 && value & 0b0111_1100 != 0 {
+            return Err(VarintError::Overflow);
+        }
+
+        n |= value << (7 * i);
+
+        if byte & 0b1000_0000 == 0 {
+            return Ok((n, i + 1));
+        }
+    }
+
+    Err(VarintError::Unterminated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varint_round_trip() {
+        for n in [
+            0,
+            1,
+            127,
+            128,
+            255,
+            300,
+            u32::MAX as u128,
+            u64::MAX as u128,
+            u128::MAX,
+        ] {
+            let encoded = encode(n);
+            assert_eq!(decode(&encoded), Ok((n, encoded.len())));
+        }
+    }
+
+    #[test]
+    fn test_varint_small_values_are_one_byte() {
+        assert_eq!(encode(0), vec![0x00]);
+        assert_eq!(encode(127), vec![0x7f]);
+        assert_eq!(encode(128), vec![0x80, 0x01]);
+    }
+
+    #[test]
+    fn test_varint_errors() {
+        assert_eq!(decode(&[0x80]), Err(VarintError::Unterminated));
+        assert_eq!(decode(&[0x80; 20]), Err(VarintError::Overlong));
+        let mut overflow = vec![0xff; 18];
+        overflow.push(0x7f);
+        assert_eq!(decode(&overflow), Err(VarintError::Overflow));
+    }
+}